@@ -0,0 +1,399 @@
+//! Off-chain helpers for building escrow instructions.
+//!
+//! Everything here derives addresses with the same seeds and program IDs the
+//! on-chain instructions validate against, so clients can't drift from them.
+
+use pinocchio::{error::ProgramError, sysvars::rent::{ACCOUNT_STORAGE_OVERHEAD, DEFAULT_LAMPORTS_PER_BYTE}, Address};
+use pinocchio_token::state::TokenAccount;
+use crate::constants::{ASSOCIATED_TOKEN_PROGRAM_ID, SYSTEM_PROGRAM_ID, TOKEN_PROGRAM_ID};
+use crate::error::{EscrowError, INVALID_ACCOUNT_ERROR_BASE, MISSING_ACCOUNT_ERROR_BASE, READONLY_ACCOUNT_ERROR_BASE};
+use crate::state::{Config, Escrow};
+use crate::instructions::{MAX_SEED, MIN_SEED};
+
+/// Owned account meta, in the order an instruction expects its accounts
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AccountMeta {
+    pub address: Address,
+    pub is_signer: bool,
+    pub is_writable: bool,
+}
+
+impl AccountMeta {
+    pub fn writable_signer(address: Address) -> Self {
+        Self { address, is_signer: true, is_writable: true }
+    }
+    pub fn writable(address: Address) -> Self {
+        Self { address, is_signer: false, is_writable: true }
+    }
+    pub fn readonly(address: Address) -> Self {
+        Self { address, is_signer: false, is_writable: false }
+    }
+}
+
+/// Derives the associated token account of `wallet` for `mint` under `token_program`
+pub fn associated_token_address(wallet: &Address, mint: &Address, token_program: &Address) -> Address {
+    Address::find_program_address(
+        &[wallet.as_ref(), token_program.as_ref(), mint.as_ref()],
+        &ASSOCIATED_TOKEN_PROGRAM_ID,
+    )
+    .0
+}
+
+/// Derives the escrow PDA of `maker` for `seed`, returning its address and bump
+///
+/// This is the canonical derivation: the seeds are the exact
+/// `[Escrow::SEED, maker, seed.to_le_bytes()]` array `Make` derives and every
+/// other instruction re-derives, so build escrow addresses with this only.
+pub fn derive_escrow(maker: &Address, seed: u64) -> (Address, u8) {
+    Address::find_program_address(
+        &[Escrow::SEED, maker.as_ref(), &seed.to_le_bytes()],
+        &crate::ID,
+    )
+}
+
+/// Derives the program config PDA
+pub fn config_address() -> Address {
+    Address::find_program_address(&[Config::SEED], &crate::ID).0
+}
+
+/// Combines a per-maker nonce with a seed into the `u64` seed passed to Make
+///
+/// The nonce fills the high 32 bits, so bumping it after each closed escrow
+/// gives a fresh escrow address even when the low `seed` is reused. Returns
+/// `None` from a nonce of 2^31 on, whose seed would fall past `MAX_SEED`.
+pub fn nonced_seed(nonce: u32, seed: u32) -> Option<u64> {
+    let seed = ((nonce as u64) << 32) | seed as u64;
+    (MIN_SEED..=MAX_SEED).contains(&seed).then_some(seed)
+}
+
+/// Addresses and account metas needed to submit a Make instruction
+pub struct MakeAddresses {
+    /// Escrow PDA
+    pub escrow: Address,
+    /// Bump of the escrow PDA
+    pub bump: u8,
+    /// Vault: the escrow's associated token account for mint_a
+    pub vault: Address,
+    /// Accounts in the order `MakeAccounts` expects them
+    pub accounts: [AccountMeta; 10],
+}
+
+/// Derives the escrow PDA, the vault ATA and the ordered accounts for a Make instruction
+pub fn make_addresses(maker: &Address, mint_a: &Address, mint_b: &Address, seed: u64) -> MakeAddresses {
+    let token_program = TOKEN_PROGRAM_ID;
+    let (escrow, bump) = derive_escrow(maker, seed);
+    let vault = associated_token_address(&escrow, mint_a, &token_program);
+    let maker_ata_a = associated_token_address(maker, mint_a, &token_program);
+
+    MakeAddresses {
+        accounts: [
+            AccountMeta::writable_signer(maker.clone()),
+            AccountMeta::writable(escrow.clone()),
+            AccountMeta::readonly(mint_a.clone()),
+            AccountMeta::readonly(mint_b.clone()),
+            AccountMeta::writable(maker_ata_a),
+            AccountMeta::writable(vault.clone()),
+            AccountMeta::readonly(SYSTEM_PROGRAM_ID),
+            AccountMeta::readonly(token_program),
+            AccountMeta::readonly(config_address()),
+            AccountMeta::readonly(ASSOCIATED_TOKEN_PROGRAM_ID),
+        ],
+        escrow,
+        bump,
+        vault,
+    }
+}
+
+/// Addresses and account metas needed to submit a Take instruction
+pub struct TakeAddresses {
+    /// Escrow PDA
+    pub escrow: Address,
+    /// Vault: the escrow's associated token account for mint_a
+    pub vault: Address,
+    /// Accounts in the order `TakeAccounts` expects them
+    pub accounts: [AccountMeta; 13],
+}
+
+/// Derives the escrow PDA, the vault ATA and the ordered accounts for a Take instruction
+///
+/// The system program is only invoked when `taker_ata_a` or `maker_ata_b` still has
+/// to be created. When both already exist, pass `create_atas = false` and its slot is
+/// filled with the token program instead, which the transaction references anyway:
+/// the list keeps its length, but the transaction one account key less.
+pub fn take_addresses(
+    taker: &Address,
+    maker: &Address,
+    mint_a: &Address,
+    mint_b: &Address,
+    seed: u64,
+    create_atas: bool,
+) -> TakeAddresses {
+    let token_program = TOKEN_PROGRAM_ID;
+    let (escrow, _) = derive_escrow(maker, seed);
+    let vault = associated_token_address(&escrow, mint_a, &token_program);
+    let system_program = if create_atas { SYSTEM_PROGRAM_ID } else { token_program.clone() };
+
+    TakeAddresses {
+        accounts: [
+            AccountMeta::writable_signer(taker.clone()),
+            AccountMeta::writable(maker.clone()),
+            AccountMeta::writable(escrow.clone()),
+            AccountMeta::readonly(mint_a.clone()),
+            AccountMeta::readonly(mint_b.clone()),
+            AccountMeta::writable(vault.clone()),
+            AccountMeta::writable(associated_token_address(taker, mint_a, &token_program)),
+            AccountMeta::writable(associated_token_address(taker, mint_b, &token_program)),
+            AccountMeta::writable(associated_token_address(maker, mint_b, &token_program)),
+            AccountMeta::readonly(system_program),
+            AccountMeta::readonly(token_program),
+            AccountMeta::readonly(config_address()),
+            AccountMeta::readonly(ASSOCIATED_TOKEN_PROGRAM_ID),
+        ],
+        escrow,
+        vault,
+    }
+}
+
+/// Largest serialized transaction the network accepts, in bytes
+pub const PACKET_DATA_SIZE: usize = 1232;
+
+/// Estimates the serialized size of a legacy transaction holding only one
+/// escrow instruction, with `accounts` and `data_len` bytes of instruction data
+///
+/// Exact for that transaction: each distinct address is one 32-byte key, each
+/// distinct signer one 64-byte signature. Instructions added alongside (compute
+/// budget, ATA creation, ...) come on top. Compare with `PACKET_DATA_SIZE` to
+/// decide whether to create ATAs in a transaction of their own.
+pub fn estimate_transaction_size(accounts: &[AccountMeta], data_len: usize) -> usize {
+    // Length prefix of a transaction array, in the compact-u16 encoding
+    let compact_len = |len: usize| match len {
+        0..=0x7f => 1,
+        0x80..=0x3fff => 2,
+        _ => 3,
+    };
+
+    // An address passed several times is one key, signing if any of its metas does
+    let program = AccountMeta::readonly(crate::ID);
+    let mut keys: Vec<(&Address, bool)> = Vec::with_capacity(accounts.len() + 1);
+    for account in accounts.iter().chain(core::iter::once(&program)) {
+        match keys.iter_mut().find(|(address, _)| *address == &account.address) {
+            Some((_, is_signer)) => *is_signer |= account.is_signer,
+            None => keys.push((&account.address, account.is_signer)),
+        }
+    }
+    let signers = keys.iter().filter(|(_, is_signer)| *is_signer).count();
+
+    let signatures = compact_len(signers) + signers * 64;
+    let header = 3;
+    let account_keys = compact_len(keys.len()) + keys.len() * 32;
+    let blockhash = 32;
+    let instruction = 1 + compact_len(accounts.len()) + accounts.len() + compact_len(data_len) + data_len;
+
+    signatures + header + account_keys + blockhash + compact_len(1) + instruction
+}
+
+/// Rent-exempt minimum balance of an account holding `data_len` bytes, at the default rent
+pub fn rent_exempt_minimum(data_len: usize) -> u64 {
+    (ACCOUNT_STORAGE_OVERHEAD + data_len as u64) * DEFAULT_LAMPORTS_PER_BYTE
+}
+
+/// Lamports a closing escrow gives back: the rent of the escrow and of its vault
+///
+/// What the final Take or a Refund pays out, to the maker by default (see
+/// `ESCROW_RENT_TO_TAKER` and `RENT_TO_TREASURY` for who receives which part).
+/// Lamports sent to either account beyond its rent come on top.
+pub fn reclaimable_rent() -> u64 {
+    rent_exempt_minimum(Escrow::LEN) + rent_exempt_minimum(TokenAccount::LEN)
+}
+
+/// `dataSize` filter for `getProgramAccounts` selecting escrow accounts
+///
+/// Escrows carry no discriminator: every account of the program with exactly
+/// `Escrow::LEN` bytes is an escrow (configs and proposals have other sizes).
+/// Combine it with `memcmp` filters at `Escrow::MAKER_OFFSET`, `MINT_A_OFFSET` or
+/// `MINT_B_OFFSET` to narrow the escrows down by maker or mint.
+pub const ESCROW_DATA_SIZE: u64 = Escrow::LEN as u64;
+
+/// Decodes a batch of escrow account datas, e.g. from `getProgramAccounts`
+///
+/// Each buffer decodes independently, so a malformed or uninitialized account
+/// only yields an error at its own index instead of failing the whole batch.
+/// Buffers may sit at any alignment, see `Escrow::load_unaligned`.
+pub fn unpack_many(accounts: &[&[u8]]) -> Vec<Result<Escrow, ProgramError>> {
+    accounts.iter().map(|data| Escrow::load_unaligned(data)).collect()
+}
+
+/// Previews a take of `fill` mint_a (`None` = everything left) out of `escrow`,
+/// whose vault holds `vault_amount`, returning the mint_a the taker receives and
+/// the mint_b they pay
+///
+/// Runs the same fill checks and pricing as `Take`, so it fails where Take would.
+/// Oracle-priced escrows are quoted at take time and can't be previewed from the
+/// escrow alone. A `TAKE_FLAT_FEE`, paid in its own mint, comes on top.
+pub fn preview_take(escrow: &Escrow, vault_amount: u64, fill: Option<u64>) -> Result<(u64, u64), ProgramError> {
+    if escrow.uses_oracle() {
+        return Err(ProgramError::InvalidArgument);
+    }
+    let fill = fill.unwrap_or(vault_amount);
+    escrow.check_fill(fill, vault_amount)?;
+    Ok((fill, escrow.fixed_price_quote(fill, vault_amount)?))
+}
+
+/// Describes a `Custom(code)` error returned by the program
+///
+/// Covers every `EscrowError` and the positional account error ranges
+/// (see `error::invalid_account`, `error::missing_account` and `error::readonly_account`).
+pub fn describe_error(code: u32) -> &'static str {
+    if let Ok(error) = EscrowError::try_from(code) {
+        return describe_escrow_error(error);
+    }
+    match code {
+        INVALID_ACCOUNT_ERROR_BASE..MISSING_ACCOUNT_ERROR_BASE => "An account is invalid; its position is the code minus 100",
+        MISSING_ACCOUNT_ERROR_BASE..READONLY_ACCOUNT_ERROR_BASE => "Accounts are missing, starting at the code minus 200",
+        READONLY_ACCOUNT_ERROR_BASE..400 => "An account must be writable; its position is the code minus 300",
+        _ => "Unknown escrow program error",
+    }
+}
+
+/// Message for each `EscrowError`, matched exhaustively so a new variant can't be missed
+fn describe_escrow_error(error: EscrowError) -> &'static str {
+    match error {
+        EscrowError::EscrowExpired => "The escrow's deadline has passed",
+        EscrowError::InvalidDeadline => "The deadline is in the past, or doesn't extend the current one",
+        EscrowError::TransferFeeNotSupported => "Mints charging a transfer fee aren't supported",
+        EscrowError::ProgramPaused => "The program is paused",
+        EscrowError::FillBelowMinimum => "The partial fill is below the escrow's minimum fill, or too small to price",
+        EscrowError::IncompatibleEscrows => "The escrows don't trade inverse mints with covering amounts",
+        EscrowError::MintMismatch => "A token account holds a different mint than expected",
+        EscrowError::UninitializedEscrow => "The escrow account was never initialized",
+        EscrowError::TransferHookNotSupported => "Mints with a transfer hook aren't supported",
+        EscrowError::ReceivedAmountMismatch => "The maker or the vault didn't receive exactly the amount transferred",
+        EscrowError::VaultClosed => "The escrow's vault is already closed",
+        EscrowError::OutputBelowMinimum => "The take would pay out less than the taker's minimum",
+        EscrowError::InvalidPermit => "The take permit is missing, invalid or doesn't cover this take",
+        EscrowError::PartiallyFilled => "The escrow is sealed by a partial fill and can't be modified",
+        EscrowError::InstructionDataTooLarge => "The instruction data is too long",
+        EscrowError::PartialFillNotAllowed => "The escrow only accepts a take of the whole vault",
+        EscrowError::NotAnNft => "The NFT escrow's mint isn't an NFT, or the deposit isn't exactly one token",
+        EscrowError::EscrowNotExpired => "The escrow hasn't expired, so only its maker can refund it",
+        EscrowError::ReservedBitsSet => "The instruction data sets reserved bits, which must be zero",
+        EscrowError::VaultNotEmpty => "The persistent escrow's vault still holds a deposit",
+        EscrowError::RefundGracePeriod => "The escrow was made too recently to be refunded",
+        EscrowError::CosignerRequired => "The escrow's cosigner must sign the take",
+        EscrowError::TakeDeadlinePassed => "The take landed after the taker's deadline",
+        EscrowError::MintBlocked => "The mint is blocked by the program",
+        EscrowError::DepositIncomplete => "The position deposit didn't take the whole fill",
+        EscrowError::PermitNonceMismatch => "The take permit was already used, or its nonce isn't the taker's next one",
+        EscrowError::KeypairEscrowNotSupported => "Keypair escrows only support Take and Refund",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instructions::{MakeAccount, TakeAccount};
+
+    #[test]
+    fn make_addresses_match_the_manual_derivation() {
+        let (maker, mint_a, mint_b) = (Address::new_from_array([1; 32]), Address::new_from_array([2; 32]), Address::new_from_array([3; 32]));
+        let addresses = make_addresses(&maker, &mint_a, &mint_b, 7);
+
+        let (escrow, bump) = Address::find_program_address(&[b"escrow", maker.as_ref(), &7u64.to_le_bytes()], &crate::ID);
+        let ata = |wallet: &Address| {
+            Address::find_program_address(&[wallet.as_ref(), TOKEN_PROGRAM_ID.as_ref(), mint_a.as_ref()], &ASSOCIATED_TOKEN_PROGRAM_ID).0
+        };
+        assert_eq!((addresses.escrow.clone(), addresses.bump), (escrow.clone(), bump));
+        assert_eq!(addresses.vault, ata(&escrow));
+
+        let account = |position: MakeAccount| &addresses.accounts[position as usize];
+        assert_eq!(account(MakeAccount::Maker), &AccountMeta::writable_signer(maker.clone()));
+        assert_eq!(account(MakeAccount::Escrow), &AccountMeta::writable(escrow.clone()));
+        assert_eq!(account(MakeAccount::MintA), &AccountMeta::readonly(mint_a.clone()));
+        assert_eq!(account(MakeAccount::MintB), &AccountMeta::readonly(mint_b));
+        assert_eq!(account(MakeAccount::MakerAtaA), &AccountMeta::writable(ata(&maker)));
+        assert_eq!(account(MakeAccount::Vault), &AccountMeta::writable(ata(&escrow)));
+        assert_eq!(account(MakeAccount::TokenProgram).address, TOKEN_PROGRAM_ID);
+        assert_eq!(account(MakeAccount::Config).address, Address::find_program_address(&[b"config"], &crate::ID).0);
+        assert_eq!(addresses.accounts.len(), MakeAccount::COUNT);
+    }
+
+    #[test]
+    fn take_addresses_reuse_the_token_program_without_atas_to_create() {
+        let (taker, maker, mint_a, mint_b) = (
+            Address::new_from_array([1; 32]),
+            Address::new_from_array([2; 32]),
+            Address::new_from_array([3; 32]),
+            Address::new_from_array([4; 32]),
+        );
+        let creating = take_addresses(&taker, &maker, &mint_a, &mint_b, 7, true);
+        let existing = take_addresses(&taker, &maker, &mint_a, &mint_b, 7, false);
+        assert_eq!(creating.accounts[TakeAccount::SystemProgram as usize].address, SYSTEM_PROGRAM_ID);
+        assert_eq!(existing.accounts[TakeAccount::SystemProgram as usize], AccountMeta::readonly(TOKEN_PROGRAM_ID));
+        assert_eq!(existing.accounts.len(), TakeAccount::COUNT);
+
+        // The same accounts otherwise, one account key less
+        for (position, (creating, existing)) in creating.accounts.iter().zip(&existing.accounts).enumerate() {
+            if position != TakeAccount::SystemProgram as usize {
+                assert_eq!(creating, existing);
+            }
+        }
+        assert_eq!(estimate_transaction_size(&creating.accounts, 0) - estimate_transaction_size(&existing.accounts, 0), 32);
+    }
+
+    #[test]
+    fn estimates_the_serialized_transaction_size() {
+        // A signer passed twice is one key and one signature, the program a key of its own:
+        // 1 + 64 signature, 3 header, 1 + 3 * 32 keys, 32 blockhash, then one instruction
+        // of 1 program index, 1 + 3 account indices and 1 + 1 data
+        let (a, b) = (Address::new_from_array([1; 32]), Address::new_from_array([2; 32]));
+        let accounts = [AccountMeta::writable_signer(a.clone()), AccountMeta::readonly(b), AccountMeta::readonly(a.clone())];
+        assert_eq!(estimate_transaction_size(&accounts, 1), 65 + 3 + 97 + 32 + 1 + 7);
+
+        let make = make_addresses(&Address::new_from_array([1; 32]), &Address::new_from_array([2; 32]), &Address::new_from_array([3; 32]), 7);
+        assert_eq!(estimate_transaction_size(&make.accounts, 25), 492);
+
+        // The token program standing in for the system program saves its key
+        let take = |create_atas| {
+            let addresses = take_addresses(&a, &Address::new_from_array([3; 32]), &Address::new_from_array([4; 32]), &Address::new_from_array([5; 32]), 7, create_atas);
+            estimate_transaction_size(&addresses.accounts, 1)
+        };
+        assert_eq!(take(true) - take(false), 32);
+        assert!(take(true) <= PACKET_DATA_SIZE);
+    }
+
+    #[test]
+    fn every_escrow_error_has_its_own_description() {
+        let descriptions: Vec<&str> = (0..=EscrowError::KeypairEscrowNotSupported as u32).map(describe_error).collect();
+        for description in &descriptions {
+            assert!(!description.is_empty());
+            assert_ne!(*description, describe_error(u32::MAX));
+        }
+        let mut distinct = descriptions.clone();
+        distinct.sort();
+        distinct.dedup();
+        assert_eq!(distinct.len(), descriptions.len());
+    }
+
+    #[test]
+    fn unpack_many_decodes_each_buffer_on_its_own() {
+        let valid = crate::test_utils::escrow_data(|escrow| escrow.set_maker(Address::new_from_array([1; 32])));
+        let uninitialized = [0; Escrow::LEN];
+        let short = &valid[..Escrow::LEN - 1];
+        // Off the allocation's alignment, as a slice of a larger RPC response may be
+        let unaligned = [&[0][..], &valid].concat();
+
+        let escrows = unpack_many(&[&valid, &uninitialized, short, &unaligned[1..]]);
+        assert_eq!(escrows.len(), 4);
+        assert_eq!(escrows[0].as_ref().map(|escrow| escrow.maker.clone()), Ok(Address::new_from_array([1; 32])));
+        assert_eq!(escrows[1].as_ref().err(), Some(&EscrowError::UninitializedEscrow.into()));
+        assert_eq!(escrows[2].as_ref().err(), Some(&ProgramError::InvalidInstructionData));
+        assert!(escrows[3].is_ok());
+    }
+
+    #[test]
+    fn nonced_seed_stays_within_the_seed_policy() {
+        assert_eq!(nonced_seed(1, 2), Some((1 << 32) | 2));
+        assert_eq!(nonced_seed((1 << 31) - 1, u32::MAX), Some(MAX_SEED));
+        assert_eq!(nonced_seed(1 << 31, 0), None);
+    }
+}
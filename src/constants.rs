@@ -0,0 +1,138 @@
+//! Program IDs, seeds and instruction discriminators shared by the program and its clients.
+//!
+//! Every instruction and client helper refers to these, so a program ID or seed
+//! can't silently differ between the code that derives an address and the code
+//! that validates it.
+
+use pinocchio::Address;
+
+/// Legacy SPL token program, the token program escrows trade on by default
+pub const TOKEN_PROGRAM_ID: Address = pinocchio_token::ID;
+
+/// Token-2022 program. Its mints are only accepted with the `multi-token-program` feature.
+pub const TOKEN_2022_PROGRAM_ID: Address = Address::new_from_array([
+    0x06, 0xdd, 0xf6, 0xe1, 0xee, 0x75, 0x8f, 0xde,
+    0x18, 0x42, 0x5d, 0xbc, 0xe4, 0x6c, 0xcd, 0xda,
+    0xb6, 0x1a, 0xfc, 0x4d, 0x83, 0xb9, 0x0d, 0x27,
+    0xfe, 0xbd, 0xf9, 0x28, 0xd8, 0xa1, 0x8b, 0xfc,
+]);
+
+/// System program
+pub const SYSTEM_PROGRAM_ID: Address = pinocchio_system::ID;
+
+/// Associated token account program the client helpers pass. `Make` and `Take`
+/// take it as an account and invoke whichever program is passed, after checking the
+/// account to create is that program's address for the wallet and mint.
+pub const ASSOCIATED_TOKEN_PROGRAM_ID: Address = pinocchio_associated_token_account::ID;
+
+/// Seed prefix of escrow PDAs: `[ESCROW_SEED, maker, seed.to_le_bytes()]`
+pub const ESCROW_SEED: &[u8] = b"escrow";
+
+/// Seed of the program config PDA: `[CONFIG_SEED]`
+pub const CONFIG_SEED: &[u8] = b"config";
+
+/// Seed of the treasury PDA collecting rent when `RENT_TO_TREASURY` is set: `[TREASURY_SEED]`
+pub const TREASURY_SEED: &[u8] = b"treasury";
+
+/// Seed prefix of proposal PDAs: `[PROPOSAL_SEED, maker, seed.to_le_bytes()]`
+pub const PROPOSAL_SEED: &[u8] = b"proposal";
+
+/// Seed of the PDA owning a keypair escrow's vault: `[VAULT_AUTHORITY_SEED, escrow]`
+pub const VAULT_AUTHORITY_SEED: &[u8] = b"vault_authority";
+
+/// Seed of an escrow's take history PDA, kept with `RECORD_TAKE_HISTORY`: `[TAKE_HISTORY_SEED, escrow]`
+pub const TAKE_HISTORY_SEED: &[u8] = b"take_history";
+
+/// Seed of a taker's permit nonce PDA: `[PERMIT_NONCE_SEED, taker]`
+pub const PERMIT_NONCE_SEED: &[u8] = b"permit_nonce";
+
+/// Largest instruction data `process_instructions` accepts, discriminator included
+///
+/// Well above the longest instruction today (Make with every optional entry behind
+/// an 8-byte Anchor discriminator), so only inputs no parser expects are cut off
+/// before reaching one. Raise it together with any longer instruction.
+pub const MAX_INSTRUCTION_DATA_LEN: usize = 256;
+
+/// Bits of the discriminator byte above the instruction id, holding its version
+///
+/// A discriminator is `version << INSTRUCTION_VERSION_SHIFT | id`: the low 5 bits
+/// name the instruction (the `*_DISCRIMINATOR` ids below), the high 3 bits its
+/// version. At version 0 the discriminator equals the id. A breaking change to
+/// an instruction's accounts or data ships as a new version routed to its own
+/// handler, while older clients keep using the previous one.
+pub const INSTRUCTION_VERSION_SHIFT: u32 = 5;
+
+/// Mask of the instruction id in a discriminator byte
+pub const INSTRUCTION_ID_MASK: u8 = (1 << INSTRUCTION_VERSION_SHIFT) - 1;
+
+/// Splits a discriminator byte into its version and instruction id
+#[inline(always)]
+pub const fn split_discriminator(discriminator: u8) -> (u8, u8) {
+    (discriminator >> INSTRUCTION_VERSION_SHIFT, discriminator & INSTRUCTION_ID_MASK)
+}
+
+/// Builds the discriminator byte of version `version` of instruction `id`
+#[inline(always)]
+pub const fn versioned_discriminator(id: u8, version: u8) -> u8 {
+    version << INSTRUCTION_VERSION_SHIFT | id
+}
+
+/// Every instruction's id, the single source of the `*_DISCRIMINATOR` bytes
+///
+/// Ids are part of the wire format: append new instructions, never renumber.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Instruction {
+    Make = 0,
+    Take = 1,
+    Refund = 2,
+    ExtendDeadline = 3,
+    RefundExpired = 4,
+    SetPaused = 5,
+    GetEscrow = 6,
+    Match = 7,
+    Propose = 8,
+    Accept = 9,
+    SweepTreasury = 10,
+    SetMintB = 11,
+    VerifyVault = 12,
+    Crank = 13,
+    Rebalance = 14,
+    MakeMany = 15,
+    Deposit = 16,
+    InitConfig = 17,
+    InitPermitNonce = 18,
+    CancelProposal = 19,
+}
+
+pub const MAKE_DISCRIMINATOR: u8 = Instruction::Make as u8;
+pub const TAKE_DISCRIMINATOR: u8 = Instruction::Take as u8;
+pub const REFUND_DISCRIMINATOR: u8 = Instruction::Refund as u8;
+pub const EXTEND_DEADLINE_DISCRIMINATOR: u8 = Instruction::ExtendDeadline as u8;
+pub const REFUND_EXPIRED_DISCRIMINATOR: u8 = Instruction::RefundExpired as u8;
+pub const SET_PAUSED_DISCRIMINATOR: u8 = Instruction::SetPaused as u8;
+pub const GET_ESCROW_DISCRIMINATOR: u8 = Instruction::GetEscrow as u8;
+pub const MATCH_DISCRIMINATOR: u8 = Instruction::Match as u8;
+pub const PROPOSE_DISCRIMINATOR: u8 = Instruction::Propose as u8;
+pub const ACCEPT_DISCRIMINATOR: u8 = Instruction::Accept as u8;
+pub const SWEEP_TREASURY_DISCRIMINATOR: u8 = Instruction::SweepTreasury as u8;
+pub const SET_MINT_B_DISCRIMINATOR: u8 = Instruction::SetMintB as u8;
+pub const VERIFY_VAULT_DISCRIMINATOR: u8 = Instruction::VerifyVault as u8;
+pub const CRANK_DISCRIMINATOR: u8 = Instruction::Crank as u8;
+pub const REBALANCE_DISCRIMINATOR: u8 = Instruction::Rebalance as u8;
+pub const MAKE_MANY_DISCRIMINATOR: u8 = Instruction::MakeMany as u8;
+pub const DEPOSIT_DISCRIMINATOR: u8 = Instruction::Deposit as u8;
+pub const INIT_CONFIG_DISCRIMINATOR: u8 = Instruction::InitConfig as u8;
+pub const INIT_PERMIT_NONCE_DISCRIMINATOR: u8 = Instruction::InitPermitNonce as u8;
+pub const CANCEL_PROPOSAL_DISCRIMINATOR: u8 = Instruction::CancelProposal as u8;
+
+/// Version 1 of SetMintB, which also replaces `receive`
+pub const SET_MINT_B_V1_DISCRIMINATOR: u8 = versioned_discriminator(SET_MINT_B_DISCRIMINATOR, 1);
+
+/// Anchor discriminator of Make: `sha256("global:make")[..8]`
+#[cfg(feature = "anchor-compat")]
+pub const ANCHOR_MAKE_DISCRIMINATOR: [u8; 8] = [0x8a, 0xe3, 0xe8, 0x4d, 0xdf, 0xa6, 0x60, 0xc5];
+
+/// Anchor discriminator of Take: `sha256("global:take")[..8]`
+#[cfg(feature = "anchor-compat")]
+pub const ANCHOR_TAKE_DISCRIMINATOR: [u8; 8] = [0x95, 0xe2, 0x34, 0x68, 0x06, 0x8e, 0xe6, 0x27];
@@ -0,0 +1,153 @@
+//! The cross-program invocations the instructions make, one function each.
+//!
+//! On the host there is no runtime to invoke: under `cfg(test)` each call is
+//! simulated by `test_utils`, which applies the callee's effect to the accounts
+//! directly, so an instruction can be tested end to end.
+//!
+//! Token instructions are sent to the token program account the instruction was
+//! given, legacy or Token-2022 (see `TokenProgram::check`), rather than to
+//! `pinocchio_token`'s hardcoded program ID.
+
+use pinocchio::{AccountView, Address, ProgramResult, cpi::Signer, instruction::InstructionView};
+use crate::instructions::CreateAssociatedTokenAccount;
+#[cfg(not(test))]
+use crate::instructions::MAX_DEPOSIT_ACCOUNTS;
+#[cfg(not(test))]
+use pinocchio::{cpi::{invoke_signed, invoke_with_bounds}, instruction::InstructionAccount};
+#[cfg(not(test))]
+use pinocchio_system::instructions::{CreateAccount, Transfer as TransferLamports};
+
+/// Token program instruction discriminators, shared by Token-2022
+#[cfg(not(test))]
+const TOKEN_TRANSFER: u8 = 3;
+#[cfg(not(test))]
+const TOKEN_APPROVE: u8 = 4;
+#[cfg(not(test))]
+const TOKEN_CLOSE_ACCOUNT: u8 = 9;
+
+/// Data of a token instruction taking a single u64 amount
+#[cfg(not(test))]
+fn amount_data(discriminator: u8, amount: u64) -> [u8; 9] {
+    let mut data = [discriminator; 9];
+    data[1..].copy_from_slice(&amount.to_le_bytes());
+    data
+}
+
+/// Creates `account` with `space` bytes owned by `owner`, its rent-exempt
+/// minimum paid by `payer`
+#[cfg(not(test))]
+pub(crate) fn create_account(payer: &AccountView, account: &AccountView, space: u64, owner: &Address, signers: &[Signer]) -> ProgramResult {
+    CreateAccount::with_minimum_balance(payer, account, space, owner, None)?.invoke_signed(signers)
+}
+
+#[cfg(test)]
+pub(crate) fn create_account(payer: &AccountView, account: &AccountView, space: u64, owner: &Address, _signers: &[Signer]) -> ProgramResult {
+    crate::test_utils::simulate_create_account(payer, account, space, owner)
+}
+
+/// Creates an associated token account, see `CreateAssociatedTokenAccount`
+#[cfg(not(test))]
+pub(crate) fn create_associated_token_account(create: &CreateAssociatedTokenAccount) -> ProgramResult {
+    create.invoke()
+}
+
+#[cfg(test)]
+pub(crate) fn create_associated_token_account(create: &CreateAssociatedTokenAccount) -> ProgramResult {
+    crate::test_utils::simulate_create_associated_token_account(create)
+}
+
+/// Transfers `lamports` from the system account `from` to `to`
+#[cfg(not(test))]
+pub(crate) fn transfer_lamports(from: &AccountView, to: &AccountView, lamports: u64, signers: &[Signer]) -> ProgramResult {
+    TransferLamports { from, to, lamports }.invoke_signed(signers)
+}
+
+#[cfg(test)]
+pub(crate) fn transfer_lamports(from: &AccountView, to: &AccountView, lamports: u64, _signers: &[Signer]) -> ProgramResult {
+    crate::test_utils::simulate_transfer_lamports(from, to, lamports)
+}
+
+/// Transfers `amount` tokens from `from` to `to`, signed by `authority`
+#[cfg(not(test))]
+pub(crate) fn transfer(token_program: &AccountView, from: &AccountView, to: &AccountView, authority: &AccountView, amount: u64, signers: &[Signer]) -> ProgramResult {
+    let instruction_accounts = [
+        InstructionAccount::writable(from.address()),
+        InstructionAccount::writable(to.address()),
+        InstructionAccount::readonly_signer(authority.address()),
+    ];
+    let instruction = InstructionView {
+        program_id: token_program.address(),
+        accounts: &instruction_accounts,
+        data: &amount_data(TOKEN_TRANSFER, amount),
+    };
+    invoke_signed(&instruction, &[from, to, authority], signers)
+}
+
+#[cfg(test)]
+pub(crate) fn transfer(token_program: &AccountView, from: &AccountView, to: &AccountView, authority: &AccountView, amount: u64, _signers: &[Signer]) -> ProgramResult {
+    crate::test_utils::simulate_transfer(token_program, from, to, authority, amount)
+}
+
+/// Closes the emptied token account `account`, its rent going to `destination`
+#[cfg(not(test))]
+pub(crate) fn close_account(token_program: &AccountView, account: &AccountView, destination: &AccountView, authority: &AccountView, signers: &[Signer]) -> ProgramResult {
+    let instruction_accounts = [
+        InstructionAccount::writable(account.address()),
+        InstructionAccount::writable(destination.address()),
+        InstructionAccount::readonly_signer(authority.address()),
+    ];
+    let instruction = InstructionView {
+        program_id: token_program.address(),
+        accounts: &instruction_accounts,
+        data: &[TOKEN_CLOSE_ACCOUNT],
+    };
+    invoke_signed(&instruction, &[account, destination, authority], signers)
+}
+
+#[cfg(test)]
+pub(crate) fn close_account(token_program: &AccountView, account: &AccountView, destination: &AccountView, authority: &AccountView, _signers: &[Signer]) -> ProgramResult {
+    crate::test_utils::simulate_close_account(token_program, account, destination, authority)
+}
+
+/// Lets `delegate` transfer up to `amount` tokens out of `source`
+#[cfg(not(test))]
+pub(crate) fn approve(token_program: &AccountView, source: &AccountView, delegate: &AccountView, authority: &AccountView, amount: u64) -> ProgramResult {
+    let instruction_accounts = [
+        InstructionAccount::writable(source.address()),
+        InstructionAccount::readonly(delegate.address()),
+        InstructionAccount::readonly_signer(authority.address()),
+    ];
+    let instruction = InstructionView {
+        program_id: token_program.address(),
+        accounts: &instruction_accounts,
+        data: &amount_data(TOKEN_APPROVE, amount),
+    };
+    invoke_signed(&instruction, &[source, delegate, authority], &[])
+}
+
+#[cfg(test)]
+pub(crate) fn approve(token_program: &AccountView, source: &AccountView, delegate: &AccountView, authority: &AccountView, amount: u64) -> ProgramResult {
+    crate::test_utils::simulate_approve(token_program, source, delegate, authority, amount)
+}
+
+/// Invokes a `DEPOSIT_PROGRAMS` program's deposit instruction, see `PositionDeposit`
+#[cfg(not(test))]
+pub(crate) fn invoke_deposit(instruction: &InstructionView, accounts: &[&AccountView]) -> ProgramResult {
+    invoke_with_bounds::<MAX_DEPOSIT_ACCOUNTS>(instruction, accounts)
+}
+
+#[cfg(test)]
+pub(crate) fn invoke_deposit(instruction: &InstructionView, accounts: &[&AccountView]) -> ProgramResult {
+    crate::test_utils::simulate_deposit(instruction, accounts)
+}
+
+/// Sets the instruction's return data
+#[cfg(not(test))]
+pub(crate) fn set_return_data(data: &[u8]) {
+    pinocchio::cpi::set_return_data(data)
+}
+
+#[cfg(test)]
+pub(crate) fn set_return_data(data: &[u8]) {
+    crate::test_utils::simulate_set_return_data(data)
+}
@@ -0,0 +1,159 @@
+use pinocchio::error::ProgramError;
+
+/// Custom errors returned by the escrow program.
+///
+/// Each variant is surfaced to clients as `ProgramError::Custom(code)`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum EscrowError {
+    /// The escrow's deadline has passed
+    EscrowExpired = 0,
+    /// The deadline is in the past, or doesn't extend the current one
+    InvalidDeadline = 1,
+    /// The mint charges a transfer fee, which would break exact-amount swaps
+    TransferFeeNotSupported = 2,
+    /// The program is paused by its admin
+    ProgramPaused = 3,
+    /// A partial fill is below the escrow's minimum fill, or too small to leave
+    /// any mint_b owed for the rest of the vault
+    FillBelowMinimum = 4,
+    /// The escrows don't trade inverse mint pairs with covering amounts
+    IncompatibleEscrows = 5,
+    /// A token account holds a different mint than the one it's swapped against
+    MintMismatch = 6,
+    /// The escrow account has the right size but was never initialized
+    UninitializedEscrow = 7,
+    /// The mint runs a transfer hook, whose extra accounts the escrow doesn't forward
+    TransferHookNotSupported = 8,
+    /// The maker's mint_b balance (on Take) or the vault's (on Make) didn't grow by
+    /// exactly the amount transferred
+    ReceivedAmountMismatch = 9,
+    /// The escrow's vault has already been closed
+    VaultClosed = 10,
+    /// The taker would receive less mint_a than their minimum
+    OutputBelowMinimum = 11,
+    /// The take permit is missing, malformed, not signed by the taker or doesn't cover this take
+    InvalidPermit = 12,
+    /// The escrow is sealed by a partial fill, so its terms can't change
+    PartiallyFilled = 13,
+    /// The instruction data is longer than `MAX_INSTRUCTION_DATA_LEN`
+    InstructionDataTooLarge = 14,
+    /// A partial fill of an escrow made without `Escrow::FLAG_PARTIAL_FILL`
+    PartialFillNotAllowed = 15,
+    /// An NFT escrow's mint_a isn't an NFT (0 decimals, supply 1), or the deposit isn't exactly 1
+    NotAnNft = 16,
+    /// The escrow has no deadline, or it hasn't passed yet
+    EscrowNotExpired = 17,
+    /// The instruction data sets reserved bits, which must be zero
+    ReservedBitsSet = 18,
+    /// A persistent escrow is only refilled once a take has drained its vault
+    VaultNotEmpty = 19,
+    /// The escrow is still within its refund grace period, see `REFUND_GRACE_PERIOD`
+    RefundGracePeriod = 20,
+    /// The escrow's cosigner didn't sign the take, or a deposit above the cosigner threshold names none
+    CosignerRequired = 21,
+    /// The take landed after the taker's `valid_until`
+    TakeDeadlinePassed = 22,
+    /// The mint is listed in `BLOCKED_MINTS`
+    MintBlocked = 23,
+    /// A position deposit left part of the fill in taker_ata_a
+    DepositIncomplete = 24,
+    /// The permit's nonce isn't the taker's current permit nonce: it was used or skipped ahead
+    PermitNonceMismatch = 25,
+    /// The instruction doesn't support keypair escrows, which only Take and Refund handle
+    KeypairEscrowNotSupported = 26,
+}
+
+impl TryFrom<u32> for EscrowError {
+    type Error = ProgramError;
+
+    /// Decodes a `Custom` error code back into the `EscrowError` it came from
+    fn try_from(code: u32) -> Result<Self, Self::Error> {
+        Ok(match code {
+            0 => Self::EscrowExpired,
+            1 => Self::InvalidDeadline,
+            2 => Self::TransferFeeNotSupported,
+            3 => Self::ProgramPaused,
+            4 => Self::FillBelowMinimum,
+            5 => Self::IncompatibleEscrows,
+            6 => Self::MintMismatch,
+            7 => Self::UninitializedEscrow,
+            8 => Self::TransferHookNotSupported,
+            9 => Self::ReceivedAmountMismatch,
+            10 => Self::VaultClosed,
+            11 => Self::OutputBelowMinimum,
+            12 => Self::InvalidPermit,
+            13 => Self::PartiallyFilled,
+            14 => Self::InstructionDataTooLarge,
+            15 => Self::PartialFillNotAllowed,
+            16 => Self::NotAnNft,
+            17 => Self::EscrowNotExpired,
+            18 => Self::ReservedBitsSet,
+            19 => Self::VaultNotEmpty,
+            20 => Self::RefundGracePeriod,
+            21 => Self::CosignerRequired,
+            22 => Self::TakeDeadlinePassed,
+            23 => Self::MintBlocked,
+            24 => Self::DepositIncomplete,
+            25 => Self::PermitNonceMismatch,
+            26 => Self::KeypairEscrowNotSupported,
+            _ => return Err(ProgramError::InvalidArgument),
+        })
+    }
+}
+
+impl From<EscrowError> for ProgramError {
+    fn from(error: EscrowError) -> Self {
+        ProgramError::Custom(error as u32)
+    }
+}
+
+/// Base of the error codes naming an invalid account: `INVALID_ACCOUNT_ERROR_BASE + index`,
+/// where `index` is the account's position in the instruction's account list
+pub const INVALID_ACCOUNT_ERROR_BASE: u32 = 100;
+
+/// Base of the error codes naming the first missing account: `MISSING_ACCOUNT_ERROR_BASE + index`
+pub const MISSING_ACCOUNT_ERROR_BASE: u32 = 200;
+
+/// Base of the error codes naming a read-only account the instruction writes to:
+/// `READONLY_ACCOUNT_ERROR_BASE + index`
+pub const READONLY_ACCOUNT_ERROR_BASE: u32 = 300;
+
+/// Error for the invalid account at `index`
+pub fn invalid_account(index: usize) -> ProgramError {
+    ProgramError::Custom(INVALID_ACCOUNT_ERROR_BASE + index as u32)
+}
+
+/// Error for a missing account, the first one absent being at `index`
+pub fn missing_account(index: usize) -> ProgramError {
+    ProgramError::Custom(MISSING_ACCOUNT_ERROR_BASE + index as u32)
+}
+
+/// Error for the account at `index`, which must be writable but isn't
+pub fn readonly_account(index: usize) -> ProgramError {
+    ProgramError::Custom(READONLY_ACCOUNT_ERROR_BASE + index as u32)
+}
+
+/// Maps a validation failure of the account at `index` to `invalid_account(index)`,
+/// leaving the program's own `EscrowError`s untouched
+pub fn at_account(index: usize) -> impl Fn(ProgramError) -> ProgramError {
+    move |error| match error {
+        ProgramError::Custom(_) => error,
+        _ => invalid_account(index),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_code_round_trips() {
+        let last = EscrowError::KeypairEscrowNotSupported as u32;
+        for code in 0..=last {
+            let error = EscrowError::try_from(code).unwrap();
+            assert_eq!(ProgramError::from(error), ProgramError::Custom(code));
+        }
+        assert_eq!(EscrowError::try_from(last + 1), Err(ProgramError::InvalidArgument));
+    }
+}
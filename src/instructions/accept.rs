@@ -0,0 +1,209 @@
+use pinocchio::{AccountView, Address, ProgramResult, cpi::{Seed,Signer}, error::ProgramError};
+use super::make::{SignerAccount,ProgramAccount,AssociatedTokenAccount,TokenProgram,ConfigAccount};
+use crate::state::Proposal;
+use crate::cpi;
+
+/// Settles a proposal, swapping its terms with the designated taker.
+///
+/// Only the taker recorded by `Propose` may accept. The maker's mint_a is drawn
+/// from `maker_ata_a` through the proposal's delegate approval, and the proposal
+/// is closed back to the maker.
+///
+/// # Accounts
+/// - `taker`: Designated taker (signer)
+/// - `maker`: Maker's wallet account, receiving the proposal rent
+/// - `proposal`: Proposal account to settle
+/// - `mint_a`: Mint of the token offered
+/// - `mint_b`: Mint of the token asked for
+/// - `maker_ata_a`: Maker's token account for mint_a
+/// - `maker_ata_b`: Maker's token account for mint_b
+/// - `taker_ata_a`: Taker's token account for mint_a
+/// - `taker_ata_b`: Taker's token account for mint_b
+/// - `token_program`: Token program
+/// - `config`: Program config PDA (may be uninitialized)
+pub struct Accept<'a> {
+    pub accounts: AcceptAccounts<'a>,
+}
+
+impl<'a> TryFrom<&'a [AccountView]> for Accept<'a> {
+    type Error = ProgramError;
+    fn try_from(accounts: &'a [AccountView]) -> Result<Self, Self::Error> {
+        Ok(Self {
+            accounts: AcceptAccounts::try_from(accounts)?,
+        })
+    }
+}
+
+impl<'a> Accept<'a> {
+    pub const DISCRIMINATOR: &'a u8 = &crate::constants::ACCEPT_DISCRIMINATOR;
+
+    /// 1. proposal is valid and the taker is the designated one
+    /// 2. maker_ata_a:mint_a -> taker_ata_a, signed by the proposal as delegate
+    /// 3. taker_ata_b:mint_b -> maker_ata_b
+    /// 4. close proposal
+    pub fn process(&self) -> ProgramResult {
+        let data = self.accounts.proposal.try_borrow()?;
+        let proposal = Proposal::load(&data)?;
+        let (proposal_address, _) = Address::find_program_address(&[
+            Proposal::SEED,
+            self.accounts.maker.address().as_ref(),
+            &proposal.seed.to_le_bytes(),
+        ], &crate::ID);
+        if proposal_address != *self.accounts.proposal.address() {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        // Negotiated terms only settle with the taker they were negotiated with
+        if proposal.taker != *self.accounts.taker.address() {
+            return Err(ProgramError::IncorrectAuthority);
+        }
+        if proposal.mint_a != *self.accounts.mint_a.address() || proposal.mint_b != *self.accounts.mint_b.address() {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let seed_binding = proposal.seed.to_le_bytes();
+        let bump_binding = proposal.bump;
+        let seed = [
+            Seed::from(Proposal::SEED),
+            Seed::from(self.accounts.maker.address().as_ref()),
+            Seed::from(&seed_binding),
+            Seed::from(&bump_binding),
+        ];
+
+        // maker_ata_a:mint_a -> taker_ata_a
+        cpi::transfer(
+            self.accounts.token_program,
+            self.accounts.maker_ata_a,
+            self.accounts.taker_ata_a,
+            self.accounts.proposal,
+            proposal.amount,
+            &[Signer::from(&seed)],
+        )?;
+
+        // taker_ata_b:mint_b -> maker_ata_b
+        cpi::transfer(
+            self.accounts.token_program,
+            self.accounts.taker_ata_b,
+            self.accounts.maker_ata_b,
+            self.accounts.taker,
+            proposal.receive,
+            &[],
+        )?;
+
+        drop(data);
+        ProgramAccount::close(self.accounts.proposal, self.accounts.maker)
+    }
+}
+
+pub struct AcceptAccounts<'a> {
+    pub taker: &'a AccountView,
+    pub maker: &'a AccountView,
+    pub proposal: &'a AccountView,
+    pub mint_a: &'a AccountView,
+    pub mint_b: &'a AccountView,
+    pub maker_ata_a: &'a AccountView,
+    pub maker_ata_b: &'a AccountView,
+    pub taker_ata_a: &'a AccountView,
+    pub taker_ata_b: &'a AccountView,
+    pub token_program: &'a AccountView,
+    pub config: &'a AccountView,
+}
+
+impl<'a> TryFrom<&'a [AccountView]> for AcceptAccounts<'a> {
+    type Error = ProgramError;
+    fn try_from(accounts: &'a [AccountView]) -> Result<Self, Self::Error> {
+        let [taker, maker, proposal, mint_a, mint_b, maker_ata_a, maker_ata_b, taker_ata_a, taker_ata_b, token_program, config] = accounts else {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        };
+
+        ConfigAccount::check_not_paused(config)?;
+        SignerAccount::check(taker)?;
+        ProgramAccount::check(proposal)?;
+        TokenProgram::check(token_program)?;
+        AssociatedTokenAccount::check(maker_ata_a, maker, mint_a, token_program)?;
+        AssociatedTokenAccount::check(maker_ata_b, maker, mint_b, token_program)?;
+        AssociatedTokenAccount::check(taker_ata_a, taker, mint_a, token_program)?;
+        AssociatedTokenAccount::check(taker_ata_b, taker, mint_b, token_program)?;
+
+        Ok(Self {
+            taker,
+            maker,
+            proposal,
+            mint_a,
+            mint_b,
+            maker_ata_a,
+            maker_ata_b,
+            taker_ata_a,
+            taker_ata_b,
+            token_program,
+            config,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pinocchio_token::state::TokenAccount;
+    use crate::{constants::{SYSTEM_PROGRAM_ID, TOKEN_PROGRAM_ID}, test_utils::*};
+
+    const PROPOSAL_RENT: u64 = 1_500_000;
+
+    /// Maker 1's proposal of 10 of mint 3 for 20 of mint 4 to taker 2, accepted by
+    /// `taker`, in `AcceptAccounts` order
+    fn accept_accounts(taker: Address) -> Vec<TestAccount> {
+        let (maker, mint_a, mint_b) = (address(1), address(3), address(4));
+        let (proposal_address, bump) = Address::find_program_address(&[Proposal::SEED, maker.as_ref(), &7u64.to_le_bytes()], &crate::ID);
+        let proposal = TestAccount::new(proposal_address.clone(), crate::ID, PROPOSAL_RENT, &[0; Proposal::LEN]).writable();
+        {
+            let view = proposal.view();
+            let mut data = view.try_borrow_mut().unwrap();
+            let state = Proposal::load_mut(&mut data).unwrap();
+            state.seed = 7;
+            state.maker = maker.clone();
+            state.taker = address(2);
+            state.mint_a = mint_a.clone();
+            state.mint_b = mint_b.clone();
+            state.amount = 10;
+            state.receive = 20;
+            state.bump = [bump];
+        }
+        vec![
+            TestAccount::wallet(taker.clone()).signer().writable(),
+            TestAccount::wallet(maker.clone()).writable(),
+            proposal,
+            mint(mint_a.clone()),
+            mint(mint_b.clone()),
+            // Approved to the proposal by Propose
+            delegated(token_account(address(5), &mint_a, &maker, 10), &proposal_address, 10),
+            token_account(address(6), &mint_b, &maker, 0),
+            token_account(address(7), &mint_a, &taker, 0),
+            token_account(address(8), &mint_b, &taker, 20),
+            TestAccount::program(TOKEN_PROGRAM_ID),
+            config(),
+        ]
+    }
+
+    #[test]
+    fn designated_taker_settles_and_closes_the_proposal() {
+        let accounts = accept_accounts(address(2));
+        let views: Vec<AccountView> = accounts.iter().map(TestAccount::view).collect();
+        let maker_lamports = views[1].lamports();
+
+        Accept::try_from(&views[..]).unwrap().process().unwrap();
+
+        assert!(views[2].owned_by(&SYSTEM_PROGRAM_ID));
+        assert_eq!(views[1].lamports(), maker_lamports + PROPOSAL_RENT);
+        // Both sides of the swap moved
+        assert_eq!(TokenAccount::from_account_view(&views[7]).unwrap().amount(), 10);
+        assert_eq!(TokenAccount::from_account_view(&views[6]).unwrap().amount(), 20);
+    }
+
+    #[test]
+    fn rejects_another_taker() {
+        let accounts = accept_accounts(address(9));
+        let views: Vec<AccountView> = accounts.iter().map(TestAccount::view).collect();
+        assert_eq!(Accept::try_from(&views[..]).unwrap().process(), Err(ProgramError::IncorrectAuthority));
+        assert!(views[2].owned_by(&crate::ID));
+    }
+}
@@ -0,0 +1,107 @@
+use pinocchio::{AccountView, Address, ProgramResult, error::ProgramError};
+use super::make::{SignerAccount,ProgramAccount};
+use crate::state::Proposal;
+
+/// Withdraws a proposal nobody accepted, returning its rent to the maker.
+///
+/// Allowed while the program is paused, like refunds. The delegate approval
+/// `Propose` gave the proposal is left on `maker_ata_a`: only this program can
+/// sign for the closed PDA, and the maker can revoke it through the token program.
+///
+/// # Accounts
+/// - `maker`: Maker's wallet account (signer), receiving the proposal rent
+/// - `proposal`: Proposal account to close
+pub struct CancelProposal<'a> {
+    pub accounts: CancelProposalAccounts<'a>,
+}
+
+impl<'a> TryFrom<&'a [AccountView]> for CancelProposal<'a> {
+    type Error = ProgramError;
+    fn try_from(accounts: &'a [AccountView]) -> Result<Self, Self::Error> {
+        Ok(Self {
+            accounts: CancelProposalAccounts::try_from(accounts)?,
+        })
+    }
+}
+
+impl<'a> CancelProposal<'a> {
+    pub const DISCRIMINATOR: &'a u8 = &crate::constants::CANCEL_PROPOSAL_DISCRIMINATOR;
+
+    /// 1. proposal is the maker's
+    /// 2. close proposal
+    pub fn process(&self) -> ProgramResult {
+        let data = self.accounts.proposal.try_borrow()?;
+        let proposal = Proposal::load(&data)?;
+        let (proposal_address, _) = Address::find_program_address(&[
+            Proposal::SEED,
+            self.accounts.maker.address().as_ref(),
+            &proposal.seed.to_le_bytes(),
+        ], &crate::ID);
+        if proposal_address != *self.accounts.proposal.address() || proposal.maker != *self.accounts.maker.address() {
+            return Err(ProgramError::IncorrectAuthority);
+        }
+
+        drop(data);
+        ProgramAccount::close(self.accounts.proposal, self.accounts.maker)
+    }
+}
+
+pub struct CancelProposalAccounts<'a> {
+    pub maker: &'a AccountView,
+    pub proposal: &'a AccountView,
+}
+
+impl<'a> TryFrom<&'a [AccountView]> for CancelProposalAccounts<'a> {
+    type Error = ProgramError;
+    fn try_from(accounts: &'a [AccountView]) -> Result<Self, Self::Error> {
+        let [maker, proposal] = accounts else {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        };
+
+        SignerAccount::check(maker)?;
+        ProgramAccount::check(proposal)?;
+
+        Ok(Self { maker, proposal })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{constants::SYSTEM_PROGRAM_ID, test_utils::*};
+
+    /// Maker 1's proposal of seed 7, canceled by `maker`
+    fn cancel(maker: Address) -> (Vec<TestAccount>, ProgramResult) {
+        let (proposal_address, bump) = Address::find_program_address(&[Proposal::SEED, address(1).as_ref(), &7u64.to_le_bytes()], &crate::ID);
+        let proposal = TestAccount::new(proposal_address, crate::ID, rent(Proposal::LEN), &[0; Proposal::LEN]).writable();
+        {
+            let view = proposal.view();
+            let mut data = view.try_borrow_mut().unwrap();
+            let state = Proposal::load_mut(&mut data).unwrap();
+            state.seed = 7;
+            state.maker = address(1);
+            state.taker = address(2);
+            state.bump = [bump];
+        }
+        let accounts = vec![TestAccount::wallet(maker).signer().writable(), proposal];
+        let views: Vec<AccountView> = accounts.iter().map(TestAccount::view).collect();
+        let result = CancelProposal::try_from(&views[..]).and_then(|cancel| cancel.process());
+        (accounts, result)
+    }
+
+    #[test]
+    fn the_maker_closes_the_proposal() {
+        let (accounts, result) = cancel(address(1));
+        result.unwrap();
+        assert!(accounts[1].view().owned_by(&SYSTEM_PROGRAM_ID));
+        assert_eq!(accounts[0].view().lamports(), 1_000_000_000 + rent(Proposal::LEN));
+    }
+
+    #[test]
+    fn rejects_another_signer() {
+        // Not even the designated taker
+        let (accounts, result) = cancel(address(2));
+        assert_eq!(result, Err(ProgramError::IncorrectAuthority));
+        assert!(accounts[1].view().owned_by(&crate::ID));
+    }
+}
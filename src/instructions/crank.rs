@@ -0,0 +1,223 @@
+use pinocchio::{AccountView, Address, ProgramResult, cpi::{Seed,Signer}, error::ProgramError};
+use super::make::{assert_distinct,SignerAccount,AssociatedTokenAccount,ProgramAccount,TreasuryAccount,RENT_TO_TREASURY};
+use crate::{error::EscrowError, state::Escrow};
+use crate::cpi;
+
+/// Whether the cranker is paid the vault's rent for cleaning up an expired escrow.
+///
+/// The maker still gets the whole deposit and the escrow's rent back. Without the
+/// reward nobody but the maker has a reason to crank, so it is on by default.
+pub const CRANK_REWARD_VAULT_RENT: bool = true;
+
+/// Refunds an expired escrow to its maker, callable by anyone.
+///
+/// Keeps expired escrows from lingering when the maker doesn't refund them.
+/// Fails unless the escrow has a deadline and it has passed. Keypair escrows,
+/// whose vault belongs to their vault authority, are left to the maker's Refund.
+///
+/// # Accounts
+/// - `cranker`: Anyone (signer), rewarded with the vault rent when `CRANK_REWARD_VAULT_RENT` is set
+/// - `maker`: The escrow's maker
+/// - `escrow`: Expired escrow to close
+/// - `mint_a`: Mint of the deposited token
+/// - `vault`: Vault token account holding the deposit
+/// - `maker_ata_a`: Maker's token account receiving the deposit back
+/// - `token_program`: Token program
+/// - `treasury`: Treasury PDA receiving the rent, only with `RENT_TO_TREASURY`
+pub struct Crank<'a> {
+    pub accounts: CrankAccounts<'a>,
+}
+
+impl<'a> TryFrom<&'a [AccountView]> for Crank<'a> {
+    type Error = ProgramError;
+    fn try_from(accounts: &'a [AccountView]) -> Result<Self, Self::Error> {
+        Ok(Self {
+            accounts: CrankAccounts::try_from(accounts)?,
+        })
+    }
+}
+
+impl<'a> Crank<'a> {
+    pub const DISCRIMINATOR: &'a u8 = &crate::constants::CRANK_DISCRIMINATOR;
+
+    /// 1. escrow is valid and expired
+    /// 2. vault:mint_a -> maker_ata_a
+    /// 3. close vault to the cranker, or the rent destination without the reward
+    /// 4. close escrow to the rent destination
+    pub fn process(&self) -> ProgramResult {
+        let data = self.accounts.escrow.try_borrow()?;
+        let escrow = Escrow::load(&data)?;
+        let (escrow_address, _) = Address::find_program_address(&[
+            Escrow::SEED,
+            self.accounts.maker.address().as_ref(),
+            &escrow.seed.to_le_bytes(),
+        ], &crate::ID);
+        if escrow_address != *self.accounts.escrow.address() {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        if !escrow.is_expired(crate::unix_timestamp()?) {
+            return Err(EscrowError::EscrowNotExpired.into());
+        }
+
+        let seed_binding = escrow.seed.to_le_bytes();
+        let bump_binding = escrow.bump;
+        let seed = [
+            Seed::from(Escrow::SEED),
+            Seed::from(self.accounts.maker.address().as_ref()),
+            Seed::from(&seed_binding),
+            Seed::from(&bump_binding),
+        ];
+        let signer = Signer::from(&seed);
+
+        let vault_amount = escrow.vault_amount(self.accounts.escrow, self.accounts.vault)?;
+
+        // vault:mint_a -> maker_ata_a
+        cpi::transfer(
+            self.accounts.token_program,
+            self.accounts.vault,
+            self.accounts.maker_ata_a,
+            self.accounts.escrow,
+            vault_amount,
+            core::slice::from_ref(&signer),
+        )?;
+
+        cpi::close_account(
+            self.accounts.token_program,
+            self.accounts.vault,
+            if CRANK_REWARD_VAULT_RENT { self.accounts.cranker } else { self.accounts.rent_destination },
+            self.accounts.escrow,
+            &[signer],
+        )?;
+
+        drop(data);
+        ProgramAccount::close(self.accounts.escrow, self.accounts.rent_destination)
+    }
+}
+
+pub struct CrankAccounts<'a> {
+    pub cranker: &'a AccountView,
+    pub maker: &'a AccountView,
+    pub escrow: &'a AccountView,
+    pub mint_a: &'a AccountView,
+    pub vault: &'a AccountView,
+    pub maker_ata_a: &'a AccountView,
+    pub token_program: &'a AccountView,
+    /// Receives the escrow rent: the maker, or the treasury with `RENT_TO_TREASURY`
+    pub rent_destination: &'a AccountView,
+}
+
+impl<'a> TryFrom<&'a [AccountView]> for CrankAccounts<'a> {
+    type Error = ProgramError;
+    fn try_from(accounts: &'a [AccountView]) -> Result<Self, Self::Error> {
+        let [cranker, maker, escrow, mint_a, vault, maker_ata_a, token_program, rest @ ..] = accounts else {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        };
+        let rent_destination = match rest {
+            [] if !RENT_TO_TREASURY => maker,
+            [treasury] if RENT_TO_TREASURY => {
+                TreasuryAccount::check(treasury)?;
+                treasury
+            }
+            _ => return Err(ProgramError::InvalidArgument),
+        };
+
+        SignerAccount::check(cranker)?;
+        ProgramAccount::check(escrow)?;
+        assert_distinct(&[escrow, vault, maker_ata_a])?;
+
+        // The deposit only ever goes back to the stored maker, whoever cranks
+        {
+            let data = escrow.try_borrow()?;
+            let escrow_state = Escrow::load(&data)?;
+            if escrow_state.maker != *maker.address() {
+                return Err(ProgramError::IncorrectAuthority);
+            }
+            if escrow_state.mint_a != *mint_a.address() {
+                return Err(ProgramError::InvalidAccountData);
+            }
+            // Only the PDA escrow is re-derived and signs for its vault
+            if escrow_state.is_keypair() {
+                return Err(EscrowError::KeypairEscrowNotSupported.into());
+            }
+        }
+
+        AssociatedTokenAccount::check(vault, escrow, mint_a, token_program)?;
+        AssociatedTokenAccount::check(maker_ata_a, maker, mint_a, token_program)?;
+
+        Ok(Self {
+            cranker,
+            maker,
+            escrow,
+            mint_a,
+            vault,
+            maker_ata_a,
+            token_program,
+            rent_destination,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{constants::{SYSTEM_PROGRAM_ID, TOKEN_PROGRAM_ID}, test_utils::*};
+
+    /// Cranker 9 cranks maker 1's escrow of 10 mint_a (3), edited by `edit`, in
+    /// `CrankAccounts` order
+    fn crank_accounts(edit: impl FnOnce(&mut Escrow)) -> Vec<TestAccount> {
+        let (maker, mint_a) = (address(1), address(3));
+        let escrow = escrow(&maker, 7, edit);
+        let vault = vault(escrow.view().address(), &mint_a, 10);
+        vec![
+            TestAccount::wallet(address(9)).signer().writable(),
+            TestAccount::wallet(maker.clone()).writable(),
+            escrow,
+            mint(mint_a.clone()),
+            vault,
+            token_account(ata_address(&maker, &mint_a), &mint_a, &maker, 0),
+            TestAccount::program(TOKEN_PROGRAM_ID),
+        ]
+    }
+
+    fn crank(accounts: &[TestAccount]) -> ProgramResult {
+        let views: Vec<AccountView> = accounts.iter().map(TestAccount::view).collect();
+        Crank::try_from(&views[..])?.process()
+    }
+
+    #[test]
+    fn refunds_an_expired_escrow_and_rewards_the_cranker() {
+        set_now(1_000);
+        let accounts = crank_accounts(|escrow| escrow.set_deadline(999));
+        let lamports = |index: usize| accounts[index].view().lamports();
+        let (cranker, maker, escrow_rent, vault_rent) = (lamports(0), lamports(1), lamports(2), lamports(4));
+
+        crank(&accounts).unwrap();
+        assert_eq!(balance(&accounts[5].view()), 10);
+        assert!(accounts[2].view().owned_by(&SYSTEM_PROGRAM_ID));
+        let reward = if CRANK_REWARD_VAULT_RENT { vault_rent } else { 0 };
+        assert_eq!(lamports(1), maker + escrow_rent + vault_rent - reward);
+        assert_eq!(lamports(0), cranker + reward);
+    }
+
+    #[test]
+    fn rejects_an_escrow_not_expired_yet() {
+        set_now(1_000);
+        let accounts = crank_accounts(|escrow| escrow.set_deadline(1_000));
+        assert_eq!(crank(&accounts), Err(EscrowError::EscrowNotExpired.into()));
+        // Nor one that never expires
+        let accounts = crank_accounts(|_| {});
+        assert_eq!(crank(&accounts), Err(EscrowError::EscrowNotExpired.into()));
+        assert_eq!(balance(&accounts[4].view()), 10);
+    }
+
+    #[test]
+    fn rejects_a_keypair_escrow() {
+        set_now(1_000);
+        let accounts = crank_accounts(|escrow| {
+            escrow.set_deadline(999);
+            escrow.set_flags(Escrow::FLAG_KEYPAIR);
+        });
+        assert_eq!(crank(&accounts), Err(EscrowError::KeypairEscrowNotSupported.into()));
+    }
+}
@@ -0,0 +1,204 @@
+use pinocchio::{AccountView, ProgramResult, error::ProgramError};
+use super::make::{assert_distinct,WritableAccount,SignerAccount,AssociatedTokenAccount,ProgramAccount,TokenProgram};
+use crate::{error::EscrowError, state::{DepositAmount, Escrow, ReceiveAmount}};
+use crate::cpi;
+
+/// Refills a persistent escrow drained by a take, starting a new round.
+///
+/// The vault must be empty: `amount` is deposited from `maker_ata_a` and the
+/// escrow asks `receive` for it, its fill progress reset. To resize a deposit
+/// that isn't drained yet, use `Rebalance`.
+///
+/// # Accounts
+/// - `maker`: Maker's wallet account (signer)
+/// - `escrow`: Persistent escrow to refill
+/// - `mint_a`: Mint of the deposited token
+/// - `vault`: Vault token account, empty
+/// - `maker_ata_a`: Maker's token account the deposit comes from
+/// - `token_program`: Token program
+pub struct Deposit<'a> {
+    pub accounts: DepositAccounts<'a>,
+    pub instruction_data: DepositInstructionData,
+}
+
+impl<'a> TryFrom<(&'a [AccountView], &'a [u8])> for Deposit<'a> {
+    type Error = ProgramError;
+    fn try_from((accounts, data): (&'a [AccountView], &'a [u8])) -> Result<Self, Self::Error> {
+        Ok(Self {
+            accounts: DepositAccounts::try_from(accounts)?,
+            instruction_data: DepositInstructionData::try_from(data)?,
+        })
+    }
+}
+
+impl<'a> Deposit<'a> {
+    pub const DISCRIMINATOR: &'a u8 = &crate::constants::DEPOSIT_DISCRIMINATOR;
+
+    /// 1. escrow belongs to the maker, is persistent and hasn't expired
+    /// 2. vault is empty
+    /// 3. maker_ata_a:mint_a -> vault
+    /// 4. store the new receive and start a new round of fills
+    pub fn process(&self) -> ProgramResult {
+        let escrow = Escrow::load_owned(&self.accounts.escrow.try_borrow()?)?;
+        if escrow.maker != *self.accounts.maker.address() {
+            return Err(ProgramError::IncorrectAuthority);
+        }
+        if escrow.mint_a != *self.accounts.mint_a.address() {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if !escrow.is_persistent() {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if escrow.is_expired(crate::unix_timestamp()?) {
+            return Err(EscrowError::EscrowExpired.into());
+        }
+
+        let amount = self.instruction_data.amount.get();
+        if escrow.is_nft() && amount != 1 {
+            return Err(EscrowError::NotAnNft.into());
+        }
+
+        // Only a drained vault is refilled, a take may still be running against this one
+        if escrow.vault_amount(self.accounts.escrow, self.accounts.vault)? != 0 {
+            return Err(EscrowError::VaultNotEmpty.into());
+        }
+
+        // maker_ata_a:mint_a -> vault
+        cpi::transfer(
+            self.accounts.token_program,
+            self.accounts.maker_ata_a,
+            self.accounts.vault,
+            self.accounts.maker,
+            amount,
+            &[],
+        )?;
+
+        // The previous round's fills don't bind the new one
+        let mut data = self.accounts.escrow.try_borrow_mut()?;
+        let escrow = &mut *Escrow::load_mut(&mut data)?;
+        escrow.set_receive(self.instruction_data.receive);
+        escrow.set_filled(0);
+        escrow.set_flags(escrow.flags & !Escrow::FLAG_SEALED);
+        Ok(())
+    }
+}
+
+pub struct DepositAccounts<'a> {
+    pub maker: &'a AccountView,
+    pub escrow: &'a AccountView,
+    pub mint_a: &'a AccountView,
+    pub vault: &'a AccountView,
+    pub maker_ata_a: &'a AccountView,
+    pub token_program: &'a AccountView,
+}
+
+impl<'a> TryFrom<&'a [AccountView]> for DepositAccounts<'a> {
+    type Error = ProgramError;
+    fn try_from(accounts: &'a [AccountView]) -> Result<Self, Self::Error> {
+        let [maker, escrow, mint_a, vault, maker_ata_a, token_program] = accounts else {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        };
+
+        SignerAccount::check(maker)?;
+        ProgramAccount::check(escrow)?;
+        TokenProgram::check(token_program)?;
+        assert_distinct(&[escrow, vault, maker_ata_a])?;
+        WritableAccount::check(escrow, 1)?;
+        WritableAccount::check(vault, 3)?;
+        WritableAccount::check(maker_ata_a, 4)?;
+
+        AssociatedTokenAccount::check(vault, escrow, mint_a, token_program)?;
+        AssociatedTokenAccount::check(maker_ata_a, maker, mint_a, token_program)?;
+
+        Ok(Self { maker, escrow, mint_a, vault, maker_ata_a, token_program })
+    }
+}
+
+pub struct DepositInstructionData {
+    /// Amount of mint_a deposited into the empty vault
+    pub amount: DepositAmount,
+    /// Amount of mint_b to receive for the new deposit
+    pub receive: ReceiveAmount,
+}
+
+impl<'a> TryFrom<&'a [u8]> for DepositInstructionData {
+    type Error = ProgramError;
+
+    /// Wire format (little endian): `[0..8]` amount (u64), `[8..16]` receive (u64)
+    fn try_from(data: &'a [u8]) -> Result<Self, Self::Error> {
+        if data.len() != 16 {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        let amount = DepositAmount(u64::from_le_bytes(data[0..8].try_into().map_err(|_| ProgramError::InvalidInstructionData)?));
+        let receive = ReceiveAmount(u64::from_le_bytes(data[8..16].try_into().map_err(|_| ProgramError::InvalidInstructionData)?));
+
+        // An empty vault or a free deposit would leave a broken escrow behind
+        if amount.get() == 0 || receive.get() == 0 {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+
+        Ok(Self { amount, receive })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{constants::TOKEN_PROGRAM_ID, test_utils::*};
+
+    #[test]
+    fn refills_a_persistent_escrow_drained_by_a_take() {
+        let (maker, mint_a) = (address(1), address(3));
+        let (escrow, bump) = escrow_address(&maker, 7);
+        // Persistent escrow of 10 mint_a for 20 mint_b, after the take of its whole vault
+        let data = escrow_data(|state| {
+            state.set_inner(7, maker.clone(), mint_a.clone(), address(4), ReceiveAmount(20), 0, 0, [bump]);
+            state.set_flags(Escrow::FLAG_PERSISTENT);
+            state.apply_fill(10, 20).unwrap();
+        });
+        let accounts = [
+            TestAccount::wallet(maker.clone()).signer(),
+            TestAccount::new(escrow.clone(), crate::ID, 1, &data).writable(),
+            mint(mint_a.clone()),
+            vault(&escrow, &mint_a, 0),
+            token_account(address(6), &mint_a, &maker, 100),
+            TestAccount::program(TOKEN_PROGRAM_ID),
+        ];
+        let views: Vec<AccountView> = accounts.iter().map(TestAccount::view).collect();
+        {
+            let data = views[1].try_borrow().unwrap();
+            let drained = Escrow::load(&data).unwrap();
+            assert_eq!((drained.receive, drained.filled, drained.is_sealed()), (ReceiveAmount(0), 10, true));
+        }
+
+        let data = [15u64.to_le_bytes(), 30u64.to_le_bytes()].concat();
+        Deposit::try_from((&views[..], &data[..])).unwrap().process().unwrap();
+
+        // A new round: the new price, no fills yet and open terms
+        let data = views[1].try_borrow().unwrap();
+        let refilled = Escrow::load(&data).unwrap();
+        assert_eq!((refilled.receive, refilled.filled, refilled.is_sealed()), (ReceiveAmount(30), 0, false));
+        assert!(refilled.is_persistent());
+    }
+
+    #[test]
+    fn rejects_a_vault_not_drained_yet() {
+        let (maker, mint_a) = (address(1), address(3));
+        let (escrow, bump) = escrow_address(&maker, 7);
+        let data = escrow_data(|state| {
+            state.set_inner(7, maker.clone(), mint_a.clone(), address(4), ReceiveAmount(20), 0, 0, [bump]);
+            state.set_flags(Escrow::FLAG_PERSISTENT);
+        });
+        let accounts = [
+            TestAccount::wallet(maker.clone()).signer(),
+            TestAccount::new(escrow.clone(), crate::ID, 1, &data).writable(),
+            mint(mint_a.clone()),
+            vault(&escrow, &mint_a, 10),
+            token_account(address(6), &mint_a, &maker, 100),
+            TestAccount::program(TOKEN_PROGRAM_ID),
+        ];
+        let views: Vec<AccountView> = accounts.iter().map(TestAccount::view).collect();
+        let data = [15u64.to_le_bytes(), 30u64.to_le_bytes()].concat();
+        assert_eq!(Deposit::try_from((&views[..], &data[..])).unwrap().process().err(), Some(EscrowError::VaultNotEmpty.into()));
+    }
+}
@@ -0,0 +1,129 @@
+use pinocchio::{AccountView, ProgramResult, error::ProgramError};
+use super::make::{SignerAccount,ProgramAccount};
+use crate::{error::EscrowError, state::Escrow};
+
+/// Pushes out the deadline of an existing escrow.
+///
+/// The new deadline must be strictly later than the current one, and still in
+/// the future: an extension can't leave the escrow expired. Escrows
+/// without a deadline (0) never expire, so they can't be extended, and a new
+/// deadline of 0 is rejected since it would silently disable expiry.
+///
+/// # Accounts
+/// - `maker`: Maker's wallet account (signer)
+/// - `escrow`: Escrow account to update
+pub struct ExtendDeadline<'a> {
+    pub accounts: ExtendDeadlineAccounts<'a>,
+    pub instruction_data: ExtendDeadlineInstructionData,
+}
+
+impl<'a> TryFrom<(&'a [AccountView], &'a [u8])> for ExtendDeadline<'a> {
+    type Error = ProgramError;
+    fn try_from((accounts, data): (&'a [AccountView], &'a [u8])) -> Result<Self, Self::Error> {
+        Ok(Self {
+            accounts: ExtendDeadlineAccounts::try_from(accounts)?,
+            instruction_data: ExtendDeadlineInstructionData::try_from(data)?,
+        })
+    }
+}
+
+impl<'a> ExtendDeadline<'a> {
+    pub const DISCRIMINATOR: &'a u8 = &crate::constants::EXTEND_DEADLINE_DISCRIMINATOR;
+
+    /// 1. escrow belongs to the maker
+    /// 2. new deadline is later than the current one and in the future
+    /// 3. store the new deadline
+    pub fn process(&self) -> ProgramResult {
+        let mut data = self.accounts.escrow.try_borrow_mut()?;
+        let escrow = &mut *Escrow::load_mut(&mut data)?;
+        if escrow.maker != *self.accounts.maker.address() {
+            return Err(ProgramError::IncorrectAuthority);
+        }
+
+        if escrow.deadline == 0 || self.instruction_data.deadline <= escrow.deadline {
+            return Err(EscrowError::InvalidDeadline.into());
+        }
+        if self.instruction_data.deadline <= crate::unix_timestamp()? {
+            return Err(EscrowError::InvalidDeadline.into());
+        }
+
+        escrow.set_deadline(self.instruction_data.deadline);
+        Ok(())
+    }
+}
+
+pub struct ExtendDeadlineAccounts<'a> {
+    pub maker: &'a AccountView,
+    pub escrow: &'a AccountView,
+}
+
+impl<'a> TryFrom<&'a [AccountView]> for ExtendDeadlineAccounts<'a> {
+    type Error = ProgramError;
+    fn try_from(accounts: &'a [AccountView]) -> Result<Self, Self::Error> {
+        let [maker, escrow] = accounts else {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        };
+
+        SignerAccount::check(maker)?;
+        ProgramAccount::check(escrow)?;
+
+        Ok(Self { maker, escrow })
+    }
+}
+
+pub struct ExtendDeadlineInstructionData {
+    /// New unix timestamp after which the escrow can't be taken
+    pub deadline: i64,
+}
+
+impl<'a> TryFrom<&'a [u8]> for ExtendDeadlineInstructionData {
+    type Error = ProgramError;
+    fn try_from(data: &'a [u8]) -> Result<Self, Self::Error> {
+        let deadline = i64::from_le_bytes(data.try_into().map_err(|_| ProgramError::InvalidInstructionData)?);
+        Ok(Self { deadline })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{state::ReceiveAmount, test_utils::*};
+
+    const NOW: i64 = 1_700_000_000;
+
+    /// Extends the maker's escrow, due at `deadline`, to `new_deadline`, returning
+    /// the stored deadline
+    fn extend(deadline: i64, new_deadline: i64) -> Result<i64, ProgramError> {
+        set_now(NOW);
+        let maker = address(1);
+        let (escrow, bump) = escrow_address(&maker, 7);
+        let data = escrow_data(|state| state.set_inner(7, maker.clone(), address(3), address(4), ReceiveAmount(20), deadline, 0, [bump]));
+        let (maker, escrow) = (TestAccount::wallet(maker).signer(), TestAccount::new(escrow, crate::ID, 1, &data).writable());
+        let accounts = [maker.view(), escrow.view()];
+
+        ExtendDeadline::try_from((&accounts[..], &new_deadline.to_le_bytes()[..]))?.process()?;
+        let data = accounts[1].try_borrow()?;
+        Ok(Escrow::load(&data)?.deadline)
+    }
+
+    #[test]
+    fn extends_a_live_deadline() {
+        assert_eq!(extend(NOW + 10, NOW + 100), Ok(NOW + 100));
+    }
+
+    #[test]
+    fn extends_an_expired_deadline_into_the_future() {
+        assert_eq!(extend(NOW - 100, NOW + 100), Ok(NOW + 100));
+    }
+
+    #[test]
+    fn rejects_a_deadline_still_in_the_past() {
+        assert_eq!(extend(NOW - 100, NOW - 10), Err(EscrowError::InvalidDeadline.into()));
+        assert_eq!(extend(NOW - 100, NOW), Err(EscrowError::InvalidDeadline.into()));
+    }
+
+    #[test]
+    fn rejects_an_earlier_deadline() {
+        assert_eq!(extend(NOW + 100, NOW + 10), Err(EscrowError::InvalidDeadline.into()));
+    }
+}
@@ -0,0 +1,85 @@
+use pinocchio::{AccountView, ProgramResult, error::ProgramError};
+use super::make::ProgramAccount;
+use crate::state::Escrow;
+use crate::cpi;
+
+/// Version of the `GetEscrow` return data layout
+pub const GET_ESCROW_VERSION: u8 = 1;
+
+/// Length of the `GetEscrow` return data
+pub const GET_ESCROW_RETURN_LEN: usize = 1 + 32 * 3 + 8 + 8;
+
+/// Writes the escrow's details to return data.
+///
+/// This is the stable ABI for programs that don't deserialize `Escrow`
+/// themselves: the on-chain layout may evolve, but this one only ever grows by
+/// appending fields under a new version.
+///
+/// Return data layout (little endian):
+/// - `[0]`: layout version (`GET_ESCROW_VERSION`)
+/// - `[1..33]`: maker
+/// - `[33..65]`: mint_a
+/// - `[65..97]`: mint_b
+/// - `[97..105]`: receive (u64)
+/// - `[105..113]`: deadline (i64, 0 = never expires)
+///
+/// # Accounts
+/// - `escrow`: Escrow account to read
+pub struct GetEscrow<'a> {
+    pub escrow: &'a AccountView,
+}
+
+impl<'a> TryFrom<&'a [AccountView]> for GetEscrow<'a> {
+    type Error = ProgramError;
+    fn try_from(accounts: &'a [AccountView]) -> Result<Self, Self::Error> {
+        let [escrow] = accounts else {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        };
+
+        ProgramAccount::check(escrow)?;
+
+        Ok(Self { escrow })
+    }
+}
+
+impl<'a> GetEscrow<'a> {
+    pub const DISCRIMINATOR: &'a u8 = &crate::constants::GET_ESCROW_DISCRIMINATOR;
+
+    pub fn process(&self) -> ProgramResult {
+        let data = self.escrow.try_borrow()?;
+        let escrow = Escrow::load(&data)?;
+
+        let mut details = [0u8; GET_ESCROW_RETURN_LEN];
+        details[0] = GET_ESCROW_VERSION;
+        details[1..33].copy_from_slice(escrow.maker.as_ref());
+        details[33..65].copy_from_slice(escrow.mint_a.as_ref());
+        details[65..97].copy_from_slice(escrow.mint_b.as_ref());
+        details[97..105].copy_from_slice(&escrow.receive.get().to_le_bytes());
+        details[105..113].copy_from_slice(&escrow.deadline.to_le_bytes());
+
+        cpi::set_return_data(&details);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{address, escrow, return_data};
+
+    #[test]
+    fn returns_the_escrow_details_in_the_stable_layout() {
+        let account = escrow(&address(1), 7, |state| state.set_deadline(1_000));
+        let accounts = [account.view()];
+        GetEscrow::try_from(&accounts[..]).unwrap().process().unwrap();
+
+        let details = return_data();
+        assert_eq!(details.len(), GET_ESCROW_RETURN_LEN);
+        assert_eq!(details[0], GET_ESCROW_VERSION);
+        assert_eq!(&details[1..33], address(1).as_ref());
+        assert_eq!(&details[33..65], address(3).as_ref());
+        assert_eq!(&details[65..97], address(4).as_ref());
+        assert_eq!(u64::from_le_bytes(details[97..105].try_into().unwrap()), 20);
+        assert_eq!(i64::from_le_bytes(details[105..113].try_into().unwrap()), 1_000);
+    }
+}
@@ -0,0 +1,107 @@
+use pinocchio::{AccountView, ProgramResult, error::ProgramError};
+use super::make::{SignerAccount,SystemProgram,ConfigAccount};
+
+/// Creates the program config PDA with its default settings (see `Config::DEFAULT`).
+///
+/// Only the deploy admin (`crate::ADMIN`) may call it, once. Until then every
+/// instruction reads the same defaults from the absent config, so initializing
+/// it changes nothing by itself: it makes room for the admin's later settings.
+///
+/// # Accounts
+/// - `admin`: Deploy admin (signer), paying the config rent
+/// - `config`: Program config PDA, uninitialized
+/// - `system_program`: System program
+pub struct InitConfig<'a> {
+    pub accounts: InitConfigAccounts<'a>,
+}
+
+impl<'a> TryFrom<&'a [AccountView]> for InitConfig<'a> {
+    type Error = ProgramError;
+    fn try_from(accounts: &'a [AccountView]) -> Result<Self, Self::Error> {
+        Ok(Self {
+            accounts: InitConfigAccounts::try_from(accounts)?,
+        })
+    }
+}
+
+impl<'a> InitConfig<'a> {
+    pub const DISCRIMINATOR: &'a u8 = &crate::constants::INIT_CONFIG_DISCRIMINATOR;
+
+    /// 1. admin is the deploy admin and the config doesn't exist yet
+    /// 2. create the config PDA with the default settings
+    pub fn process(&self) -> ProgramResult {
+        ConfigAccount::init(self.accounts.admin, self.accounts.config)
+    }
+}
+
+pub struct InitConfigAccounts<'a> {
+    pub admin: &'a AccountView,
+    pub config: &'a AccountView,
+    pub system_program: &'a AccountView,
+}
+
+impl<'a> TryFrom<&'a [AccountView]> for InitConfigAccounts<'a> {
+    type Error = ProgramError;
+    fn try_from(accounts: &'a [AccountView]) -> Result<Self, Self::Error> {
+        let [admin, config, system_program] = accounts else {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        };
+
+        SignerAccount::check(admin)?;
+        SystemProgram::check(system_program)?;
+
+        Ok(Self { admin, config, system_program })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pinocchio::Address;
+    use crate::{constants::SYSTEM_PROGRAM_ID, state::Config, test_utils::*};
+
+    /// InitConfig accounts with `admin` signing, the config PDA not created yet
+    fn init_config_accounts(admin: Address) -> Vec<TestAccount> {
+        let config = Address::find_program_address(&[Config::SEED], &crate::ID).0;
+        vec![
+            TestAccount::wallet(admin).signer().writable(),
+            TestAccount::new(config, Address::default(), 0, &[]).writable(),
+            TestAccount::program(SYSTEM_PROGRAM_ID),
+        ]
+    }
+
+    #[test]
+    fn reads_the_same_settings_with_and_without_a_config() {
+        let absent = config();
+        let config = ConfigAccount::load(&absent.view()).unwrap();
+        assert_eq!((config.admin, config.paused), (crate::ADMIN, 0));
+
+        let accounts = init_config_accounts(crate::ADMIN);
+        let views: Vec<AccountView> = accounts.iter().map(TestAccount::view).collect();
+        InitConfig::try_from(&views[..]).unwrap().process().unwrap();
+
+        assert!(views[1].owned_by(&crate::ID));
+        let config = ConfigAccount::load(&views[1]).unwrap();
+        assert_eq!((config.admin, config.paused), (crate::ADMIN, 0));
+        assert_eq!(config.bump, [Address::find_program_address(&[Config::SEED], &crate::ID).1]);
+
+        // Only once
+        assert_eq!(InitConfig::try_from(&views[..]).unwrap().process(), Err(ProgramError::AccountAlreadyInitialized));
+    }
+
+    #[test]
+    fn only_the_deploy_admin_creates_the_config() {
+        let accounts = init_config_accounts(address(1));
+        let views: Vec<AccountView> = accounts.iter().map(TestAccount::view).collect();
+        assert_eq!(InitConfig::try_from(&views[..]).unwrap().process(), Err(ProgramError::IncorrectAuthority));
+        assert!(views[1].is_data_empty());
+    }
+
+    #[test]
+    fn rejects_an_account_other_than_the_config_pda() {
+        let mut accounts = init_config_accounts(crate::ADMIN);
+        accounts[1] = TestAccount::new(address(9), Address::default(), 0, &[]).writable();
+        let views: Vec<AccountView> = accounts.iter().map(TestAccount::view).collect();
+        assert_eq!(InitConfig::try_from(&views[..]).unwrap().process(), Err(ProgramError::InvalidSeeds));
+    }
+}
@@ -0,0 +1,56 @@
+use pinocchio::{AccountView, ProgramResult, error::ProgramError};
+use super::make::{SignerAccount,SystemProgram,WritableAccount};
+use super::permit::PermitNonceAccount;
+
+/// Creates the taker's permit nonce PDA, which a take under a `TakePermit` needs.
+///
+/// The nonce starts at 0 and each permitted take advances it, so the taker signs
+/// every permit with the nonce it currently holds.
+///
+/// # Accounts
+/// - `taker`: Taker (signer, writable), paying the nonce rent
+/// - `permit_nonce`: The taker's permit nonce PDA, uninitialized
+/// - `system_program`: System program
+pub struct InitPermitNonce<'a> {
+    pub accounts: InitPermitNonceAccounts<'a>,
+}
+
+impl<'a> TryFrom<&'a [AccountView]> for InitPermitNonce<'a> {
+    type Error = ProgramError;
+    fn try_from(accounts: &'a [AccountView]) -> Result<Self, Self::Error> {
+        Ok(Self {
+            accounts: InitPermitNonceAccounts::try_from(accounts)?,
+        })
+    }
+}
+
+impl<'a> InitPermitNonce<'a> {
+    pub const DISCRIMINATOR: &'a u8 = &crate::constants::INIT_PERMIT_NONCE_DISCRIMINATOR;
+
+    /// 1. permit_nonce is the taker's PDA and doesn't exist yet
+    /// 2. create it at nonce 0
+    pub fn process(&self) -> ProgramResult {
+        PermitNonceAccount::init(self.accounts.taker, self.accounts.permit_nonce)
+    }
+}
+
+pub struct InitPermitNonceAccounts<'a> {
+    pub taker: &'a AccountView,
+    pub permit_nonce: &'a AccountView,
+    pub system_program: &'a AccountView,
+}
+
+impl<'a> TryFrom<&'a [AccountView]> for InitPermitNonceAccounts<'a> {
+    type Error = ProgramError;
+    fn try_from(accounts: &'a [AccountView]) -> Result<Self, Self::Error> {
+        let [taker, permit_nonce, system_program] = accounts else {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        };
+
+        SignerAccount::check(taker)?;
+        WritableAccount::check(taker, 0)?;
+        SystemProgram::check(system_program)?;
+
+        Ok(Self { taker, permit_nonce, system_program })
+    }
+}
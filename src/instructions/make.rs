@@ -1,16 +1,16 @@
-use std::pin;
-
-use pinocchio::{Address, AccountView, error::ProgramError, ProgramResult, cpi::{Seed, Signer}};
-use pinocchio_token::{state::TokenAccount,instructions::Transfer};
-use pinocchio_system::instructions::CreateAccount;
-use pinocchio_associated_token_account::instructions::CreateIdempotent;
-use crate::state::Escrow;
+use pinocchio::{Address, AccountView, account::Ref, error::ProgramError, ProgramResult, cpi::{invoke, Seed, Signer}, instruction::{InstructionAccount, InstructionView}};
+use pinocchio_token::state::{Mint, TokenAccount};
+use crate::{constants::{ASSOCIATED_TOKEN_PROGRAM_ID, SYSTEM_PROGRAM_ID, TOKEN_2022_PROGRAM_ID, TOKEN_PROGRAM_ID, TREASURY_SEED}, error::{readonly_account, EscrowError}, state::{Config, DepositAmount, Escrow, ReceiveAmount}};
+use crate::cpi;
 
 /// Creates a new escrow account for token swapping.
 /// 
 /// This instruction initializes the escrow data and creates the associated vault
 /// token account if it doesn't already exist.
 /// 
+/// With an `amount` of 0 the vault must already be funded: the escrow is
+/// recorded against the existing vault balance and no deposit is transferred.
+/// 
 /// # Accounts
 /// - `maker`: Maker's wallet account (signer)
 /// - `escrow`: Escrow account to be created
@@ -20,12 +20,19 @@ use crate::state::Escrow;
 /// - `vault`: Vault token account for holding deposited tokens
 /// - `system_program`: System program
 /// - `token_program`: Token program
+/// - `config`: Program config PDA (may be uninitialized)
+/// - `associated_token_program`: Associated token account program
+/// - `vault_authority`: Vault authority PDA, only for a keypair escrow
+/// - `payer`: Optional rent payer (signer), defaults to `maker`
+///
+/// A signing `escrow` makes a keypair escrow (see `Escrow::FLAG_KEYPAIR`): it
+/// isn't derived from the seed, and its vault is owned by `vault_authority`.
 pub struct Make<'a> {
     /// Accounts required for the make instruction
     pub accounts: MakeAccounts<'a>,
     /// Instruction data containing seed, receive amount, and send amount
     pub instruction_data: MakeInstructionData,
-    /// Bump seed for program address derivation
+    /// Bump of the escrow PDA, or of the vault authority for a keypair escrow
     pub bump: u8,
 }
 
@@ -39,19 +46,99 @@ impl<'a> TryFrom<(&'a [AccountView], &'a [u8])> for Make<'a> {
         let accounts = MakeAccounts::try_from(accounts)?;
         let instruction_data = MakeInstructionData::try_from(data)?;
         
-        // Derive the expected escrow address using program address derivation
-        let (escrow_address, bump) = Address::find_program_address(
-            &[
-                b"escrow",
-                accounts.maker.address().as_ref(),
-                &instruction_data.seed.to_le_bytes(),
-            ],
-            &crate::ID,
-        );
-        
-        // Validate that the provided escrow account matches the expected address
-        if accounts.escrow.address() != &escrow_address {
-            return Err(ProgramError::InvalidInstructionData);
+        // A keypair escrow is vouched for by its signature instead, and the PDA
+        // signing for it is its vault authority
+        let bump = match accounts.vault_authority {
+            Some(vault_authority) => {
+                let (vault_authority_address, bump) = Escrow::vault_authority(accounts.escrow.address());
+                if vault_authority.address() != &vault_authority_address {
+                    return Err(ProgramError::InvalidSeeds);
+                }
+                bump
+            }
+            None => {
+                // Derive the expected escrow address using program address derivation
+                let (escrow_address, bump) = Address::find_program_address(
+                    &[
+                        Escrow::SEED,
+                        accounts.maker.address().as_ref(),
+                        &instruction_data.seed.to_le_bytes(),
+                    ],
+                    &crate::ID,
+                );
+
+                // Validate that the provided escrow account matches the expected address
+                if accounts.escrow.address() != &escrow_address {
+                    return Err(ProgramError::InvalidInstructionData);
+                }
+                bump
+            }
+        };
+
+        // Refilling goes through `Deposit`, which only knows the PDA-owned vault
+        if accounts.vault_authority.is_some() && instruction_data.flags & Escrow::FLAG_PERSISTENT != 0 {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        // CreateAccount needs an empty system account, and would fail opaquely otherwise
+        if !accounts.escrow.owned_by(&SYSTEM_PROGRAM_ID) {
+            return Err(ProgramError::IllegalOwner);
+        }
+        if !accounts.escrow.is_data_empty() {
+            return Err(ProgramError::AccountAlreadyInitialized);
+        }
+
+        // Validate that a deadline, if set, is still in the future
+        if instruction_data.deadline != 0 && instruction_data.deadline <= crate::unix_timestamp()? {
+            return Err(EscrowError::InvalidDeadline.into());
+        }
+
+        // An NFT escrow deposits exactly the one token of an NFT mint
+        if instruction_data.is_nft() {
+            MintInterface::check_nft(accounts.mint_a)?;
+            if !instruction_data.is_prefunded() && instruction_data.amount.get() != 1 {
+                return Err(EscrowError::NotAnNft.into());
+            }
+        }
+
+        // A pre-funded vault must be the escrow's canonical vault and hold the deposit
+        if instruction_data.is_prefunded() {
+            let (vault_address, _) = Address::find_program_address(
+                &[
+                    accounts.vault_wallet().address().as_ref(),
+                    accounts.token_program.address().as_ref(),
+                    accounts.mint_a.address().as_ref(),
+                ],
+                accounts.associated_token_program.address(),
+            );
+            if accounts.vault.address() != &vault_address {
+                return Err(ProgramError::InvalidAccountData);
+            }
+            AssociatedTokenAccount::check(accounts.vault, accounts.vault_wallet(), accounts.mint_a, accounts.token_program)?;
+            if TokenAccountInterface::from_account_view(accounts.vault)?.amount() == 0 {
+                return Err(ProgramError::InsufficientFunds);
+            }
+        } else if TokenAccountInterface::from_account_view(accounts.maker_ata_a)?.amount() < instruction_data.amount.get() {
+            // Fail here rather than deep inside the deposit transfer
+            return Err(ProgramError::InsufficientFunds);
+        }
+
+        // High-value escrows need a cosigner on every take
+        if let Some(threshold) = COSIGNER_THRESHOLD.filter(|_| instruction_data.cosigner == Address::default()) {
+            let deposit = if instruction_data.is_prefunded() {
+                TokenAccountInterface::from_account_view(accounts.vault)?.amount()
+            } else {
+                instruction_data.amount.get()
+            };
+            if deposit > threshold {
+                return Err(EscrowError::CosignerRequired.into());
+            }
+        }
+
+        // Anyone can create the vault ahead of time: one closable by a third party
+        // could be closed out from under the escrow
+        if !accounts.vault.is_data_empty() {
+            AssociatedTokenAccount::check_close_authority(accounts.vault, accounts.vault_wallet())?;
         }
 
         Ok(Self { accounts, instruction_data, bump })
@@ -60,14 +147,16 @@ impl<'a> TryFrom<(&'a [AccountView], &'a [u8])> for Make<'a> {
 
 impl<'a> Make<'a> {
     /// Instruction discriminator for the Make instruction
-    pub const DISCRIMINATOR: &'a u8 = &0;
+    pub const DISCRIMINATOR: &'a u8 = &crate::constants::MAKE_DISCRIMINATOR;
 
     /// Processes the Make instruction.
     /// 
     /// This function:
-    /// 1. Creates the escrow account with minimum balance
+    /// 1. Creates the escrow account with minimum balance, funded by the payer
     /// 2. Initializes the escrow account data
-    /// 3. Creates the vault token account if it doesn't exist
+    /// 3. Creates the vault token account if it doesn't exist, funded by the payer
+    /// 4. Transfers the deposit to the vault, unless it is pre-funded, and checks the
+    ///    vault received exactly `amount`
     pub fn process(&mut self) -> ProgramResult {
         let accounts = &self.accounts;
         let instruction_data = &self.instruction_data;
@@ -76,61 +165,113 @@ impl<'a> Make<'a> {
         let seed_binding = instruction_data.seed.to_le_bytes();
         let bump_binding = [self.bump];
         let seeds = [
-            Seed::from(b"escrow"),
+            Seed::from(Escrow::SEED),
             Seed::from(accounts.maker.address().as_ref()),
             Seed::from(&seed_binding),
             Seed::from(&bump_binding),
         ];
 
-        // Create the escrow account with minimum balance
-        let signer = &[Signer::from(&seeds)];
-        CreateAccount::with_minimum_balance(
-            accounts.maker,
+        // Create the escrow account with minimum balance. A keypair escrow signs
+        // the transaction itself
+        let signer: &[Signer] = if accounts.vault_authority.is_some() { &[] } else { &[Signer::from(&seeds)] };
+        log_compute_units!("make: create escrow");
+        cpi::create_account(
+            accounts.payer,
             accounts.escrow,
             Escrow::LEN as u64,
             &crate::ID,
-            None,
-        )?
-        .invoke_signed(signer)?;
+            signer,
+        )?;
         
         // Initialize escrow account data
-        let mut data = self.accounts.escrow.try_borrow_mut()?;
-        let escrow = Escrow::load_mut(&mut data)?;
-        escrow.set_inner(
-            instruction_data.seed,
-            accounts.maker.address().clone(),
-            accounts.mint_a.address().clone(),
-            accounts.mint_b.address().clone(),
-            instruction_data.receive,
-            [self.bump],
-        );
+        {
+            let mut data = self.accounts.escrow.try_borrow_mut()?;
+            let escrow = &mut *Escrow::load_mut(&mut data)?;
+            escrow.set_inner(
+                instruction_data.seed,
+                accounts.maker.address().clone(),
+                accounts.mint_a.address().clone(),
+                accounts.mint_b.address().clone(),
+                instruction_data.receive,
+                instruction_data.deadline,
+                instruction_data.min_fill,
+                [self.bump],
+            );
+            escrow.set_oracle(instruction_data.oracle.clone(), instruction_data.multiplier);
+            escrow.set_delegate(instruction_data.delegate.clone());
+            escrow.set_cosigner(instruction_data.cosigner.clone());
+            let keypair = if accounts.vault_authority.is_some() { Escrow::FLAG_KEYPAIR } else { 0 };
+            escrow.set_flags(instruction_data.flags | keypair);
+            escrow.set_created_at(crate::unix_timestamp()?);
+        }
 
-        // Create the vault token account if it doesn't exist
-        if accounts.vault.is_data_empty() {
-            pinocchio_associated_token_account::instructions::Create {
-                funding_account: accounts.maker,
-                account: accounts.vault,
-                wallet: accounts.escrow,
-                mint: accounts.mint_a,
-                system_program: accounts.system_program,
-                token_program: accounts.token_program,
-            }
-            .invoke()?;
+        // A pre-funded vault already holds the deposit
+        if instruction_data.is_prefunded() {
+            return Ok(());
         }
+
+        // Create the vault token account if it doesn't exist. Like Take's ATAs this
+        // goes through CreateIdempotent, so a vault someone created ahead of time
+        // (anyone can create an ATA) is validated and used instead of failing Make
+        log_compute_units!("make: create vault");
+        AssociatedTokenAccount::init_if_needed(
+            accounts.vault,
+            accounts.mint_a,
+            accounts.vault_wallet(),
+            accounts.payer,
+            accounts.system_program,
+            accounts.token_program,
+            accounts.associated_token_program,
+        )?;
         
+        // Read before the transfer: a vault created ahead of time may hold tokens already
+        let vault_before = TokenAccountInterface::from_account_view(accounts.vault)?.amount();
+
         // Transfer tokens from maker to vault
-        Transfer {
-            from: accounts.maker_ata_a,
-            to: accounts.vault,
-            authority: accounts.maker,
-            amount: instruction_data.amount,
-        }   
-        .invoke()?;
+        log_compute_units!("make: deposit");
+        cpi::transfer(
+            accounts.token_program,
+            accounts.maker_ata_a,
+            accounts.vault,
+            accounts.maker,
+            instruction_data.amount.get(),
+            &[],
+        )?;
+
+        // Takes are priced against the vault balance: a fee-charging or otherwise
+        // short deposit is rejected now rather than mispricing the escrow later
+        let vault_after = TokenAccountInterface::from_account_view(accounts.vault)?.amount();
+        if vault_after.checked_sub(vault_before) != Some(instruction_data.amount.get()) {
+            return Err(EscrowError::ReceivedAmountMismatch.into());
+        }
 
+        log_compute_units!("make: done");
         Ok(())
     }
 }
 
+/// Position of each fixed Make account, in the order `MakeAccounts::try_from`
+/// destructures them
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(usize)]
+pub enum MakeAccount {
+    Maker = 0,
+    Escrow = 1,
+    MintA = 2,
+    MintB = 3,
+    MakerAtaA = 4,
+    Vault = 5,
+    SystemProgram = 6,
+    TokenProgram = 7,
+    Config = 8,
+    AssociatedTokenProgram = 9,
+}
+
+impl MakeAccount {
+    /// Number of fixed accounts, the optional vault authority and payer start at this position
+    pub const COUNT: usize = Self::AssociatedTokenProgram as usize + 1;
+}
+
 /// Accounts required for the Make instruction
 pub struct MakeAccounts<'a> {
     /// Maker's wallet account (signer)
@@ -149,6 +290,22 @@ pub struct MakeAccounts<'a> {
     pub system_program: &'a AccountView,
     /// Token program
     pub token_program: &'a AccountView,
+    /// Program config PDA
+    pub config: &'a AccountView,
+    /// Associated token account program
+    pub associated_token_program: &'a AccountView,
+    /// Vault authority PDA, passed only for a keypair escrow
+    pub vault_authority: Option<&'a AccountView>,
+    /// Account funding the escrow and vault rent (the maker unless a payer is passed)
+    pub payer: &'a AccountView,
+}
+
+impl<'a> MakeAccounts<'a> {
+    /// Owner of the vault: the escrow, or the vault authority of a keypair escrow
+    #[inline(always)]
+    pub fn vault_wallet(&self) -> &'a AccountView {
+        self.vault_authority.unwrap_or(self.escrow)
+    }
 }
 
 impl<'a> TryFrom<&'a [AccountView]> for MakeAccounts<'a> {
@@ -159,20 +316,61 @@ impl<'a> TryFrom<&'a [AccountView]> for MakeAccounts<'a> {
     /// Validates that all required accounts are present and have correct properties.
     fn try_from(accounts: &'a [AccountView]) -> Result<Self, Self::Error> {
         // Extract accounts from the slice
-        let [maker, escrow, mint_a, mint_b, maker_ata_a, vault, system_program, token_program] = accounts else {
+        let [maker, escrow, mint_a, mint_b, maker_ata_a, vault, system_program, token_program, config, associated_token_program, rest @ ..] = accounts else {
             return Err(ProgramError::NotEnoughAccountKeys);
         };
 
-        // Validate that the maker account is a signer
+        // No new escrows while the program is paused
+        ConfigAccount::check_not_paused(config)?;
+
+        // The escrow and vault are created fresh and the deposit moves from
+        // maker_ata_a to the vault, so none of them may alias another or the maker
+        assert_distinct(&[maker, escrow, maker_ata_a, vault])?;
+
+        // Validate that the maker account is a signer: it pays the rent unless a
+        // payer does, and signs the deposit
         SignerAccount::check(maker)?;
-        
-        // Validate that mint accounts are owned by the system program
-        if !mint_a.owned_by(&pinocchio_system::ID) {
-            return Err(ProgramError::InvalidAccountOwner);
-        }
-        if !mint_b.owned_by(&pinocchio_system::ID) {
-            return Err(ProgramError::InvalidAccountOwner);
+
+        // Every account Make writes to: the maker pays, the escrow and vault are
+        // created, and the deposit moves from maker_ata_a to the vault
+        for (position, account) in [
+            (MakeAccount::Maker, maker),
+            (MakeAccount::Escrow, escrow),
+            (MakeAccount::MakerAtaA, maker_ata_a),
+            (MakeAccount::Vault, vault),
+        ] {
+            WritableAccount::check(account, position as usize)?;
         }
+
+        // The escrow and vault are created through these programs with the payer's signature
+        SystemProgram::check(system_program)?;
+        AssociatedTokenProgram::check(associated_token_program)?;
+
+        // A signing escrow is a keypair escrow, followed by its vault authority
+        let (vault_authority, rest) = if escrow.is_signer() {
+            let [vault_authority, rest @ ..] = rest else {
+                return Err(ProgramError::NotEnoughAccountKeys);
+            };
+            (Some(vault_authority), rest)
+        } else {
+            (None, rest)
+        };
+
+        // An optional trailing payer funds the rent instead of the maker
+        let payer = match rest {
+            [] => maker,
+            [payer] => {
+                SignerAccount::check(payer)?;
+                WritableAccount::check(payer, MakeAccount::COUNT + vault_authority.is_some() as usize)?;
+                payer
+            }
+            _ => return Err(ProgramError::InvalidArgument),
+        };
+        
+        // Validate that both mints are real token mints before anything is checked
+        // against them, so a bad mint isn't reported as a bad maker_ata_a
+        TokenProgram::check(token_program)?;
+        MintInterface::check_pair(mint_a, mint_b, token_program)?;
         
         // Validate that the maker's ATA is correctly configured
         AssociatedTokenAccount::check(maker_ata_a, maker, mint_a, token_program)?;
@@ -185,19 +383,95 @@ impl<'a> TryFrom<&'a [AccountView]> for MakeAccounts<'a> {
             maker_ata_a,
             vault,
             system_program,
-            token_program
+            token_program,
+            config,
+            associated_token_program,
+            vault_authority,
+            payer,
         })
     }
 }
 
+/// Smallest seed accepted by the Make instruction.
+///
+/// Seed policy: `Make` only accepts seeds in `MIN_SEED..=MAX_SEED` and rejects
+/// anything else before any account is created. A deployment that wants to
+/// reserve more of the seed namespace narrows the range here and rebuilds the program.
+pub const MIN_SEED: u64 = 0;
+
+/// Largest seed accepted by the Make instruction (see [`MIN_SEED`])
+///
+/// Seeds with the top bit set are reserved for protocol-owned escrows, so no
+/// maker can occupy the addresses they derive.
+pub const MAX_SEED: u64 = i64::MAX as u64;
+
+/// Length of the required Make instruction data: seed, receive and amount
+pub const MAKE_DATA_LEN: usize = core::mem::size_of::<u64>() * 3;
+
+/// Tag of the optional deadline entry, value: `i64` unix timestamp
+pub const MAKE_TAG_DEADLINE: u8 = 1;
+/// Tag of the optional min_fill entry, value: `u64`
+pub const MAKE_TAG_MIN_FILL: u8 = 2;
+/// Tag of the optional oracle entry, value: 32-byte oracle address then `u64` multiplier
+pub const MAKE_TAG_ORACLE: u8 = 3;
+/// Tag of the optional delegate entry, value: 32-byte delegate address
+pub const MAKE_TAG_DELEGATE: u8 = 4;
+/// Tag of the optional flags entry, value: `u8` of `Escrow::FLAG_*` bits
+pub const MAKE_TAG_FLAGS: u8 = 5;
+/// Tag of the optional cosigner entry, value: 32-byte cosigner address
+pub const MAKE_TAG_COSIGNER: u8 = 6;
+/// Tag of the optional prefunded entry, no value: the vault already holds the deposit
+pub const MAKE_TAG_PREFUNDED: u8 = 7;
+
+/// Mints `Make` refuses on either side of an escrow, e.g. known-malicious or
+/// non-compliant tokens. Empty by default; a deployment lists them and rebuilds.
+pub const BLOCKED_MINTS: &[Address] = &[];
+
+/// Deposits of more than this amount of mint_a must name a cosigner signing every
+/// take (see `Escrow::cosigner`). `None` leaves cosigners optional for all escrows.
+pub const COSIGNER_THRESHOLD: Option<u64> = None;
+
+/// Size of an optional entry's header: tag and length
+const MAKE_ENTRY_HEADER_LEN: usize = 2;
+
+/// Length of the Make instruction data with every optional entry present once
+pub const MAKE_DATA_MAX_LEN: usize = MAKE_DATA_LEN
+    + MAKE_ENTRY_HEADER_LEN + core::mem::size_of::<i64>()
+    + MAKE_ENTRY_HEADER_LEN + core::mem::size_of::<u64>()
+    + MAKE_ENTRY_HEADER_LEN + core::mem::size_of::<Address>() + core::mem::size_of::<u64>()
+    + MAKE_ENTRY_HEADER_LEN + core::mem::size_of::<Address>()
+    + MAKE_ENTRY_HEADER_LEN + core::mem::size_of::<u8>()
+    + MAKE_ENTRY_HEADER_LEN + core::mem::size_of::<Address>()
+    + MAKE_ENTRY_HEADER_LEN;
+
+// Changing a field of `MakeInstructionData` must update these lengths too
+const _: () = assert!(MAKE_DATA_LEN == 24);
+const _: () = assert!(MAKE_DATA_MAX_LEN == 159);
+
 /// Instruction data for the Make instruction
 pub struct MakeInstructionData {
     /// Unique seed for escrow account derivation
     pub seed: u64,
     /// Amount of mint_b tokens to receive
-    pub receive: u64,
-    /// Amount of mint_a tokens to deposit
-    pub amount: u64,
+    pub receive: ReceiveAmount,
+    /// Amount of mint_a tokens to deposit, 0 for a prefunded escrow
+    pub amount: DepositAmount,
+    /// Unix timestamp after which the escrow can't be taken (0 = never expires)
+    pub deadline: i64,
+    /// Smallest partial fill a taker may take, except for the final remainder
+    pub min_fill: u64,
+    /// Oracle pricing the escrow at take time (default address = fixed `receive`)
+    pub oracle: Address,
+    /// Multiplier applied to the oracle price, in basis points
+    pub multiplier: u64,
+    /// Account allowed to sign Take on behalf of takers (default address = none)
+    pub delegate: Address,
+    /// Escrow mode bits (`Escrow::FLAG_*`)
+    pub flags: u8,
+    /// Second signer required on every take (default address = none)
+    pub cosigner: Address,
+    /// The vault already holds the deposit, which Make records instead of transferring
+    pub prefunded: bool,
 }
 
 impl<'a> TryFrom<&'a [u8]> for MakeInstructionData {
@@ -205,31 +479,157 @@ impl<'a> TryFrom<&'a [u8]> for MakeInstructionData {
 
     /// Creates `MakeInstructionData` from raw bytes.
     /// 
+    /// Wire format (little endian):
+    /// - `[0..8]`: seed (u64)
+    /// - `[8..16]`: receive (u64)
+    /// - `[16..24]`: amount (u64)
+    /// - then zero or more optional entries, each `[tag: u8][len: u8][value; len]`:
+    ///   - `MAKE_TAG_DEADLINE` (8 bytes): deadline, defaults to 0 (never expires)
+    ///   - `MAKE_TAG_MIN_FILL` (8 bytes): min_fill, defaults to 0 (any fill size)
+    ///   - `MAKE_TAG_ORACLE` (40 bytes): oracle and multiplier, defaults to a fixed price
+    ///   - `MAKE_TAG_DELEGATE` (32 bytes): delegate, defaults to none
+    ///   - `MAKE_TAG_FLAGS` (1 byte): mode flags, defaults to 0 (one take drains the vault).
+    ///     Bits outside `Escrow::FLAGS_ALL` are reserved, for flags the program sets
+    ///     itself or future modes, and must be zero
+    ///   - `MAKE_TAG_COSIGNER` (32 bytes): cosigner, defaults to none
+    ///   - `MAKE_TAG_PREFUNDED` (0 bytes): the vault is already funded, amount must be 0.
+    ///     Without it amount must be positive
+    /// 
+    /// Entries may come in any order, but each tag at most once. Unknown tags and
+    /// entries of the wrong length are rejected, so new tags can be added later
+    /// without old programs misreading them.
+    ///
     /// Validates that:
-    /// 1. The data length is correct (24 bytes)
-    /// 2. The amount is non-zero
+    /// 1. The required fields are present and the entries are well-formed
+    /// 2. The seed is within the `MIN_SEED..=MAX_SEED` policy
     fn try_from(data: &'a [u8]) -> Result<Self, Self::Error> {
         // Validate data length
-        if data.len() != core::mem::size_of::<u64>() * 3 {
+        if data.len() < MAKE_DATA_LEN || data.len() > MAKE_DATA_MAX_LEN {
             return Err(ProgramError::InvalidInstructionData);
         }
         
         // Parse instruction data
         let seed = u64::from_le_bytes(data[0..8].try_into().map_err(|_| ProgramError::InvalidInstructionData)?);
-        let receive = u64::from_le_bytes(data[8..16].try_into().map_err(|_| ProgramError::InvalidInstructionData)?);
-        let amount = u64::from_le_bytes(data[16..24].try_into().map_err(|_| ProgramError::InvalidInstructionData)?);
+        let receive = ReceiveAmount(u64::from_le_bytes(data[8..16].try_into().map_err(|_| ProgramError::InvalidInstructionData)?));
+        let amount = DepositAmount(u64::from_le_bytes(data[16..24].try_into().map_err(|_| ProgramError::InvalidInstructionData)?));
+
+        // Parse the optional entries
+        let mut deadline = None;
+        let mut min_fill = None;
+        let mut oracle = None;
+        let mut delegate = None;
+        let mut flags = None;
+        let mut cosigner = None;
+        let mut prefunded = false;
+        let mut entries = &data[MAKE_DATA_LEN..];
+        while let [tag, len, rest @ ..] = entries {
+            let Some((value, rest)) = rest.split_at_checked(*len as usize) else {
+                return Err(ProgramError::InvalidInstructionData);
+            };
+            match *tag {
+                MAKE_TAG_DEADLINE if deadline.is_none() => {
+                    deadline = Some(i64::from_le_bytes(value.try_into().map_err(|_| ProgramError::InvalidInstructionData)?));
+                }
+                MAKE_TAG_MIN_FILL if min_fill.is_none() => {
+                    min_fill = Some(u64::from_le_bytes(value.try_into().map_err(|_| ProgramError::InvalidInstructionData)?));
+                }
+                MAKE_TAG_ORACLE if oracle.is_none() && value.len() == core::mem::size_of::<Address>() + core::mem::size_of::<u64>() => {
+                    let address: [u8; 32] = value[..32].try_into().map_err(|_| ProgramError::InvalidInstructionData)?;
+                    let multiplier = u64::from_le_bytes(value[32..].try_into().map_err(|_| ProgramError::InvalidInstructionData)?);
+                    oracle = Some((Address::new_from_array(address), multiplier));
+                }
+                MAKE_TAG_DELEGATE if delegate.is_none() => {
+                    let address: [u8; 32] = value.try_into().map_err(|_| ProgramError::InvalidInstructionData)?;
+                    delegate = Some(Address::new_from_array(address));
+                }
+                MAKE_TAG_FLAGS if flags.is_none() => {
+                    let [value] = value else {
+                        return Err(ProgramError::InvalidInstructionData);
+                    };
+                    flags = Some(*value);
+                }
+                MAKE_TAG_COSIGNER if cosigner.is_none() => {
+                    let address: [u8; 32] = value.try_into().map_err(|_| ProgramError::InvalidInstructionData)?;
+                    cosigner = Some(Address::new_from_array(address));
+                }
+                MAKE_TAG_PREFUNDED if !prefunded && value.is_empty() => {
+                    prefunded = true;
+                }
+                _ => return Err(ProgramError::InvalidInstructionData),
+            }
+            entries = rest;
+        }
+        // A lone trailing byte can't be an entry header
+        if !entries.is_empty() {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        let deadline = deadline.unwrap_or(0);
+        let min_fill = min_fill.unwrap_or(0);
+        let (oracle, multiplier) = oracle.unwrap_or((Address::default(), 0));
+        let delegate = delegate.unwrap_or_default();
+        let flags = flags.unwrap_or(0);
+        let cosigner = cosigner.unwrap_or_default();
         
-        // Validate that amount is non-zero
-        if amount == 0 {
+        // A deposit of 0 is only a prefunded escrow when explicitly flagged as one,
+        // so a client bug zeroing the amount can't pass as one
+        if prefunded != (amount.get() == 0) {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+
+        // Validate that the seed is within the configured policy
+        if !(MIN_SEED..=MAX_SEED).contains(&seed) {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+
+        // Reserved flag bits must stay clear, so they can gain a meaning later
+        // without old clients' data being misread
+        if flags & !Escrow::FLAGS_ALL != 0 {
+            return Err(EscrowError::ReservedBitsSet.into());
+        }
+
+        // A single NFT can't be split over partial fills
+        if flags & Escrow::FLAG_NFT != 0 && flags & Escrow::FLAG_PARTIAL_FILL != 0 {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+
+        // Validate that an oracle-priced escrow has a usable multiplier
+        if oracle != Address::default() && multiplier == 0 {
             return Err(ProgramError::InvalidInstructionData);
         }
         
-        Ok(Self { seed, receive, amount })
+        Ok(Self { seed, receive, amount, deadline, min_fill, oracle, multiplier, delegate, flags, cosigner, prefunded })
+    }
+}
+
+impl MakeInstructionData {
+    /// Returns true if the escrow records an already funded vault instead of depositing
+    #[inline(always)]
+    pub fn is_prefunded(&self) -> bool {
+        self.prefunded
+    }
+
+    /// Returns true if the escrow trades a single NFT, see `Escrow::FLAG_NFT`
+    #[inline(always)]
+    pub fn is_nft(&self) -> bool {
+        self.flags & Escrow::FLAG_NFT != 0
     }
 }
 
 // Account validation utilities
 
+/// Validates that no two of `accounts` share an address
+///
+/// For accounts an instruction writes or closes separately, where passing the
+/// same account twice would let one operation clobber another.
+pub fn assert_distinct(accounts: &[&AccountView]) -> Result<(), ProgramError> {
+    for (i, account) in accounts.iter().enumerate() {
+        if accounts[i + 1..].iter().any(|other| other.address() == account.address()) {
+            return Err(ProgramError::InvalidArgument);
+        }
+    }
+    Ok(())
+}
+
 /// Validator for signer accounts
 pub struct SignerAccount;
 
@@ -241,30 +641,422 @@ impl SignerAccount {
         }
         Ok(())
     }
+
+}
+
+/// Validator for accounts the instruction writes to
+pub struct WritableAccount;
+
+impl WritableAccount {
+    /// Validates that the account at position `index` is writable
+    ///
+    /// A read-only account would only fail later, inside the CPI writing to it, so
+    /// this fails first with `readonly_account(index)` naming it.
+    pub fn check(account: &AccountView, index: usize) -> Result<(), ProgramError> {
+        if !account.is_writable() {
+            return Err(readonly_account(index));
+        }
+        Ok(())
+    }
+}
+
+/// Whether `Take` and `Refund` close the vault and escrow to the treasury PDA.
+///
+/// Off by default: rent goes back to the maker (see `ESCROW_RENT_TO_TAKER`). A
+/// governed deployment flips this and rebuilds; `Take` and `Refund` then expect
+/// the treasury as an extra account and the admin sweeps it with `SweepTreasury`.
+pub const RENT_TO_TREASURY: bool = false;
+
+/// Whether escrows may trade Token-2022 mints, and mint_a and mint_b of different
+/// token programs, set by the `multi-token-program` feature.
+///
+/// Off by default: both mints must belong to the legacy token program passed in.
+/// With it `Take` expects mint_b's token program as an extra account, paying the
+/// maker through it (see `TakeAccounts::token_program_b`).
+pub const MULTI_TOKEN_PROGRAM: bool = cfg!(feature = "multi-token-program");
+
+/// Validator for the treasury PDA
+pub struct TreasuryAccount;
+
+impl TreasuryAccount {
+    /// Validates that the account is the treasury PDA, returning its bump
+    pub fn check(account: &AccountView) -> Result<u8, ProgramError> {
+        let (treasury_address, bump) = Address::find_program_address(&[TREASURY_SEED], &crate::ID);
+        if account.address() != &treasury_address {
+            return Err(ProgramError::InvalidSeeds);
+        }
+        Ok(bump)
+    }
+}
+
+/// Validator for the program config account
+pub struct ConfigAccount;
+
+impl ConfigAccount {
+    /// Validates that the account is the config PDA and returns its settings
+    ///
+    /// An uninitialized config reads as `Config::DEFAULT`, so instructions behave
+    /// the same before and after `InitConfig` until the admin changes a setting.
+    pub fn load(account: &AccountView) -> Result<Config, ProgramError> {
+        let (config_address, _) = Address::find_program_address(&[Config::SEED], &crate::ID);
+        if account.address() != &config_address {
+            return Err(ProgramError::InvalidSeeds);
+        }
+        if account.is_data_empty() {
+            return Ok(Config::DEFAULT);
+        }
+        if !account.owned_by(&crate::ID) {
+            return Err(ProgramError::InvalidAccountOwner);
+        }
+
+        let data = account.try_borrow()?;
+        Ok(Config::load(&data)?.clone())
+    }
+
+    /// Validates that the account is the config PDA and the program isn't paused
+    /// 
+    /// An uninitialized config means the program has never been paused.
+    pub fn check_not_paused(account: &AccountView) -> Result<(), ProgramError> {
+        if Self::load(account)?.is_paused() {
+            return Err(EscrowError::ProgramPaused.into());
+        }
+        Ok(())
+    }
+
+    /// Creates the config PDA with `Config::DEFAULT` settings, paid by `admin`
+    ///
+    /// Only the deploy admin (`crate::ADMIN`) may create it.
+    pub fn init(admin: &AccountView, account: &AccountView) -> ProgramResult {
+        let (config_address, bump) = Address::find_program_address(&[Config::SEED], &crate::ID);
+        if account.address() != &config_address {
+            return Err(ProgramError::InvalidSeeds);
+        }
+        if admin.address() != &crate::ADMIN {
+            return Err(ProgramError::IncorrectAuthority);
+        }
+        if !account.is_data_empty() {
+            return Err(ProgramError::AccountAlreadyInitialized);
+        }
+
+        let bump_binding = [bump];
+        let seeds = [
+            Seed::from(Config::SEED),
+            Seed::from(&bump_binding),
+        ];
+        cpi::create_account(
+            admin,
+            account,
+            Config::LEN as u64,
+            &crate::ID,
+            &[Signer::from(&seeds)],
+        )?;
+
+        let mut data = account.try_borrow_mut()?;
+        let config = Config::load_mut(&mut data)?;
+        *config = Config::DEFAULT;
+        config.set_bump(bump_binding);
+        Ok(())
+    }
+}
+
+/// Validator for the token program account
+pub struct TokenProgram;
+
+impl TokenProgram {
+    /// Validates that the account is a token program the token CPIs may target: the
+    /// legacy token program, or Token-2022 with `MULTI_TOKEN_PROGRAM`
+    pub fn check(account: &AccountView) -> Result<(), ProgramError> {
+        if account.address() != &TOKEN_PROGRAM_ID
+            && !(MULTI_TOKEN_PROGRAM && account.address() == &TOKEN_2022_PROGRAM_ID)
+        {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        Ok(())
+    }
+}
+
+/// Validator for the system program account
+pub struct SystemProgram;
+
+impl SystemProgram {
+    /// Validates that the account is the system program, which creates the escrow
+    /// with the maker's signature
+    pub fn check(account: &AccountView) -> Result<(), ProgramError> {
+        if account.address() != &SYSTEM_PROGRAM_ID {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        Ok(())
+    }
+}
+
+/// Validator for the associated token account program
+pub struct AssociatedTokenProgram;
+
+impl AssociatedTokenProgram {
+    /// Validates that the account is the associated token account program
+    ///
+    /// Any other program, executable or not, could create whatever account it
+    /// likes at the ATA's address, so only `ASSOCIATED_TOKEN_PROGRAM_ID` is invoked.
+    pub fn check(account: &AccountView) -> Result<(), ProgramError> {
+        if account.address() != &ASSOCIATED_TOKEN_PROGRAM_ID {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        Ok(())
+    }
+}
+
+/// Create or CreateIdempotent CPI to the passed associated token account program
+///
+/// Unlike `pinocchio_associated_token_account`'s instructions, this invokes the
+/// program account it is given instead of that crate's hardcoded program ID.
+pub struct CreateAssociatedTokenAccount<'a> {
+    /// Associated token account program, see `AssociatedTokenProgram::check`
+    pub associated_token_program: &'a AccountView,
+    /// Funding account (signer)
+    pub funding_account: &'a AccountView,
+    /// Associated token account to create
+    pub account: &'a AccountView,
+    /// Wallet owning the new account
+    pub wallet: &'a AccountView,
+    /// Mint of the new account
+    pub mint: &'a AccountView,
+    /// System program
+    pub system_program: &'a AccountView,
+    /// Token program
+    pub token_program: &'a AccountView,
+    /// Use CreateIdempotent, which succeeds if the account already exists
+    pub idempotent: bool,
+}
+
+impl CreateAssociatedTokenAccount<'_> {
+    pub fn invoke(&self) -> ProgramResult {
+        let instruction_accounts = [
+            InstructionAccount::writable_signer(self.funding_account.address()),
+            InstructionAccount::writable(self.account.address()),
+            InstructionAccount::readonly(self.wallet.address()),
+            InstructionAccount::readonly(self.mint.address()),
+            InstructionAccount::readonly(self.system_program.address()),
+            InstructionAccount::readonly(self.token_program.address()),
+        ];
+        // Create is discriminator 0, CreateIdempotent 1
+        let instruction_data = [self.idempotent as u8];
+        let instruction = InstructionView {
+            program_id: self.associated_token_program.address(),
+            accounts: &instruction_accounts,
+            data: &instruction_data,
+        };
+        invoke(
+            &instruction,
+            &[
+                self.funding_account,
+                self.account,
+                self.wallet,
+                self.mint,
+                self.system_program,
+                self.token_program,
+            ],
+        )
+    }
 }
 
 /// Validator for mint accounts
 pub struct MintInterface;
 
+/// Token-2022 extension type of `TransferFeeConfig`
+const TRANSFER_FEE_CONFIG_EXTENSION: u16 = 1;
+
+/// Token-2022 extension type of `TransferHook`
+pub(crate) const TRANSFER_HOOK_EXTENSION: u16 = 14;
+
+/// Token-2022 account type of a mint, the byte following the padded base mint
+const MINT_ACCOUNT_TYPE: u8 = 1;
+
+/// Offset of the first Token-2022 extension: the base mint is padded to the
+/// token account length, followed by a one byte account type
+const MINT_EXTENSIONS_OFFSET: usize = TokenAccount::LEN + 1;
+
 impl MintInterface {
-    /// Validates that the account is owned by the token program
+    /// Validates that the account is an initialized mint of the token program or
+    /// Token-2022
+    ///
+    /// Which of the two a mint may belong to is up to the instruction, see
+    /// `check_token_program`. Token accounts are owned by the token programs too, so
+    /// the data is told apart by its length: a legacy mint is exactly `Mint::LEN`
+    /// long, a Token-2022 mint with extensions carries the mint account type right
+    /// after the base account, padded to the token account length.
     pub fn check(account: &AccountView) -> Result<(), ProgramError> {
-        if !account.owned_by(&pinocchio_token::ID) {
+        if !account.owned_by(&TOKEN_PROGRAM_ID) && !account.owned_by(&TOKEN_2022_PROGRAM_ID) {
             return Err(ProgramError::InvalidAccountOwner);
         }
+        let data = account.try_borrow()?;
+        let is_mint = data.len() == Mint::LEN
+            || (data.len() > TokenAccount::LEN && data[TokenAccount::LEN] == MINT_ACCOUNT_TYPE);
+        if !is_mint {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        // The base mint leads either layout
+        if !unsafe { Mint::from_bytes_unchecked(&data) }.is_initialized() {
+            return Err(ProgramError::UninitializedAccount);
+        }
         Ok(())
     }
+
+    /// Returns true if the mint carries the given Token-2022 extension
+    /// 
+    /// Walks the extension TLV entries (`u16` type, `u16` length, value).
+    /// Legacy mints have no extension area and always return false.
+    pub fn has_extension(account: &AccountView, extension_type: u16) -> Result<bool, ProgramError> {
+        let data = account.try_borrow()?;
+        let mut offset = MINT_EXTENSIONS_OFFSET;
+        while let Some(header) = data.get(offset..offset + 4) {
+            let entry_type = u16::from_le_bytes([header[0], header[1]]);
+            let entry_len = u16::from_le_bytes([header[2], header[3]]) as usize;
+            // An uninitialized entry marks the end of the extensions
+            if entry_type == 0 {
+                break;
+            }
+            if entry_type == extension_type {
+                return Ok(true);
+            }
+            offset += 4 + entry_len;
+        }
+        Ok(false)
+    }
+
+    /// Validates the two mints of a swap, as `Make` and `Propose` require them
+    ///
+    /// Validates that:
+    /// 1. Both are initialized mints of two different addresses
+    /// 2. Neither is listed in `BLOCKED_MINTS`
+    /// 3. Neither charges a transfer fee, so the vault would receive less than
+    ///    `amount` and the maker less than `receive`, nor runs a transfer hook
+    /// 4. Both belong to `token_program`, checked last so a Token-2022 mint reports
+    ///    the extension it is refused for. With `MULTI_TOKEN_PROGRAM` only mint_a
+    ///    must, mint_b being paid through its own program at take time
+    pub fn check_pair(mint_a: &AccountView, mint_b: &AccountView, token_program: &AccountView) -> Result<(), ProgramError> {
+        Self::check_pair_with(mint_a, mint_b, token_program, BLOCKED_MINTS)
+    }
+
+    /// `check_pair` against the given list of blocked mints
+    fn check_pair_with(mint_a: &AccountView, mint_b: &AccountView, token_program: &AccountView, blocked: &[Address]) -> Result<(), ProgramError> {
+        Self::check(mint_a)?;
+        Self::check(mint_b)?;
+        if mint_a.address() == mint_b.address() {
+            return Err(ProgramError::InvalidArgument);
+        }
+        for mint in [mint_a, mint_b] {
+            if blocked.contains(mint.address()) {
+                return Err(EscrowError::MintBlocked.into());
+            }
+            Self::check_no_transfer_fee(mint)?;
+            Self::check_no_transfer_hook(mint)?;
+        }
+        Self::check_token_program(mint_a, token_program.address())?;
+        if MULTI_TOKEN_PROGRAM {
+            return Ok(());
+        }
+        Self::check_token_program(mint_b, token_program.address())
+    }
+
+    /// Validates that the mint isn't listed in `BLOCKED_MINTS`
+    pub fn check_not_blocked(account: &AccountView) -> Result<(), ProgramError> {
+        if BLOCKED_MINTS.contains(account.address()) {
+            return Err(EscrowError::MintBlocked.into());
+        }
+        Ok(())
+    }
+
+    /// Validates that the mint belongs to `program`, the token program its
+    /// transfers are sent to
+    ///
+    /// A mint of another token program would have its transfers sent to the wrong
+    /// program, so each side of the swap is checked against its own program.
+    pub fn check_token_program(mint: &AccountView, program: &Address) -> Result<(), ProgramError> {
+        if !mint.owned_by(program) {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        Ok(())
+    }
+
+    /// Validates that the mint is an NFT: no decimals and a supply of exactly 1
+    pub fn check_nft(account: &AccountView) -> Result<(), ProgramError> {
+        Self::check(account)?;
+        let data = account.try_borrow()?;
+        // The base mint leads either layout
+        let mint = unsafe { Mint::from_bytes_unchecked(&data) };
+        if mint.decimals() != 0 || mint.supply() != 1 {
+            return Err(EscrowError::NotAnNft.into());
+        }
+        Ok(())
+    }
+
+    /// Validates that the mint doesn't charge a Token-2022 transfer fee
+    pub fn check_no_transfer_fee(account: &AccountView) -> Result<(), ProgramError> {
+        if Self::has_extension(account, TRANSFER_FEE_CONFIG_EXTENSION)? {
+            return Err(EscrowError::TransferFeeNotSupported.into());
+        }
+        Ok(())
+    }
+
+    /// Validates that the mint doesn't run a Token-2022 transfer hook
+    ///
+    /// Hooked transfers need the hook program's extra accounts, which the escrow
+    /// doesn't resolve or forward, so such mints are rejected up front.
+    pub fn check_no_transfer_hook(account: &AccountView) -> Result<(), ProgramError> {
+        if Self::has_extension(account, TRANSFER_HOOK_EXTENSION)? {
+            return Err(EscrowError::TransferHookNotSupported.into());
+        }
+        Ok(())
+    }
+}
+
+/// Reader for token accounts of either token program
+pub struct TokenAccountInterface;
+
+/// Token-2022 account type of a token account, the byte following the base account
+const TOKEN_ACCOUNT_ACCOUNT_TYPE: u8 = 2;
+
+impl TokenAccountInterface {
+    /// Borrows the token account, validating it like `TokenAccount::from_account_view`
+    /// but for Token-2022 too
+    ///
+    /// A legacy token account is exactly `TokenAccount::LEN` long. A Token-2022 one
+    /// may carry extensions after the base account, marked by the token account
+    /// account type, as the Token-2022 associated token accounts do.
+    pub fn from_account_view(account: &AccountView) -> Result<Ref<'_, TokenAccount>, ProgramError> {
+        let is_token_account = if account.owned_by(&TOKEN_PROGRAM_ID) {
+            account.data_len() == TokenAccount::LEN
+        } else if account.owned_by(&TOKEN_2022_PROGRAM_ID) {
+            account.data_len() == TokenAccount::LEN
+                || (account.data_len() > TokenAccount::LEN && account.try_borrow()?[TokenAccount::LEN] == TOKEN_ACCOUNT_ACCOUNT_TYPE)
+        } else {
+            return Err(ProgramError::InvalidAccountOwner);
+        };
+        if !is_token_account {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        // The base account leads either layout
+        Ok(Ref::map(account.try_borrow()?, |data| unsafe { TokenAccount::from_bytes_unchecked(data) }))
+    }
 }
 
 /// Validator for associated token accounts
 pub struct AssociatedTokenAccount;
 
 impl AssociatedTokenAccount {
+    /// Validates that the token account's close authority is unset or `authority`
+    pub fn check_close_authority(ata: &AccountView, authority: &AccountView) -> Result<(), ProgramError> {
+        match TokenAccountInterface::from_account_view(ata)?.close_authority() {
+            Some(close_authority) if close_authority != authority.address() => Err(ProgramError::InvalidAccountData),
+            _ => Ok(()),
+        }
+    }
+
     /// Validates that an associated token account is correctly configured
     /// 
     /// Validates:
     /// 1. The account is owned by the token program
-    /// 2. The account has the correct data length
+    /// 2. The account has the correct data length (see `TokenAccountInterface`)
     /// 3. The account's mint matches the provided mint
     /// 4. The account's owner matches the provided authority
     pub fn check(
@@ -278,13 +1070,8 @@ impl AssociatedTokenAccount {
             return Err(ProgramError::InvalidAccountOwner);
         }
 
-        // Validate data length
-        if ata.data_len() != TokenAccount::LEN {
-            return Err(ProgramError::InvalidAccountData);
-        }
-        
-        // Validate token account data
-        let token_account = TokenAccount::from_account_view(ata)?;
+        // Validate data length and token account data
+        let token_account = TokenAccountInterface::from_account_view(ata)?;
         if token_account.mint() != mint.address() {
             return Err(ProgramError::InvalidAccountData);
         }
@@ -293,6 +1080,12 @@ impl AssociatedTokenAccount {
         }
         Ok(())
     }
+    /// Creates the associated token account if it doesn't exist, then validates it
+    /// 
+    /// An account that already holds data is only validated, skipping the
+    /// CreateIdempotent CPI in the common case of a repeat maker or taker. One to
+    /// create must be the address the associated token account program derives
+    /// for the authority and mint.
     pub fn init_if_needed(
         ata: &AccountView,
         mint: &AccountView,
@@ -300,24 +1093,30 @@ impl AssociatedTokenAccount {
         payer: &AccountView,
         system_program: &AccountView,
         token_program: &AccountView,
+        associated_token_program: &AccountView,
     ) -> ProgramResult {
         
-        if ata.data_len() == TokenAccount::LEN {
-            let token_account = TokenAccount::from_account_view(ata)?;
-
-            if token_account.is_initialized() {
-                return Ok(Self::check(ata, authority, mint, token_program)?)
-            }
+        if !ata.is_data_empty() {
+            return Self::check(ata, authority, mint, token_program);
+        }
+        AssociatedTokenProgram::check(associated_token_program)?;
+        let (ata_address, _) = Address::find_program_address(
+            &[authority.address().as_ref(), token_program.address().as_ref(), mint.address().as_ref()],
+            associated_token_program.address(),
+        );
+        if ata.address() != &ata_address {
+            return Err(ProgramError::InvalidSeeds);
         }
-        CreateIdempotent{
+        cpi::create_associated_token_account(&CreateAssociatedTokenAccount {
+            associated_token_program,
             funding_account: payer,
             account: ata,
             wallet: authority,
             mint,
             system_program,
             token_program,
-        }
-        .invoke()?;
+            idempotent: true,
+        })?;
         Self::check(ata, authority, mint, token_program)
     }
 }
@@ -326,14 +1125,14 @@ pub struct ProgramAccount;
 
 impl ProgramAccount{
      
-    /// Validates that the account is owned by the system program
+    /// Validates that the account is owned by this program
     /// 
     /// Validates:
     /// 1. Owner matches the program ID
     /// 2. account is not the signer
     /// 3. data can't be empty
     pub fn check(account: &AccountView) -> Result<(), ProgramError> {
-        if !account.owned_by(&pinocchio_system::ID) {
+        if !account.owned_by(&crate::ID) {
             return Err(ProgramError::InvalidAccountOwner);
         }
         if account.is_signer() {
@@ -344,12 +1143,25 @@ impl ProgramAccount{
         }
         Ok(())
     }
-    /// Closes the account
+    /// Validates that the account is still owned by this program with `len` bytes of data
+    ///
+    /// Re-checked after CPIs, right before acting on an account validated earlier in
+    /// the instruction, so a reassignment or realloc in between can't go unnoticed.
+    pub fn check_unchanged(account: &AccountView, len: usize) -> Result<(), ProgramError> {
+        if !account.owned_by(&crate::ID) {
+            return Err(ProgramError::InvalidAccountOwner);
+        }
+        if account.data_len() != len {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(())
+    }
+
+    /// Closes the account: moves its lamports to `destination`, zeroes its data
+    /// and hands it back to the system program
     /// 
     /// Validates:
-    /// 1. The account is owned by the system program
-    /// 2. The account is not a signer
-    /// 3. The account has non-zero data
+    /// 1. The account is owned by this program
     pub fn close(account: &AccountView,destination: &AccountView) -> Result<(), ProgramError> {
         
         if !account.owned_by(&crate::ID){
@@ -362,7 +1174,616 @@ impl ProgramAccount{
             destination.set_lamports(destination.lamports()+lamports);
         }
 
-        unsafe{account.assign(&pinocchio_system::ID)};
+        // Wipe the data so an account revived in the same slot can't be read as
+        // the closed escrow (callers must have dropped their borrows by now)
+        account.try_borrow_mut()?.fill(0);
+
+        unsafe{account.assign(&SYSTEM_PROGRAM_ID)};
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    /// Make accounts in `MakeAccount` order: maker 1 offers mint_a 3 for mint_b 4
+    fn make_accounts(mint_a: TestAccount, mint_b: TestAccount) -> Vec<TestAccount> {
+        let maker = address(1);
+        let (escrow, _) = escrow_address(&maker, 7);
+        let mint_a_address = mint_a.view().address().clone();
+        let vault = ata_address(&escrow, &mint_a_address);
+        vec![
+            TestAccount::wallet(maker.clone()).signer().writable(),
+            TestAccount::new(escrow, Address::default(), 0, &[]).writable(),
+            mint_a,
+            mint_b,
+            token_account(address(5), &mint_a_address, &maker, 10),
+            TestAccount::new(vault, Address::default(), 0, &[]).writable(),
+            TestAccount::program(SYSTEM_PROGRAM_ID),
+            TestAccount::program(TOKEN_PROGRAM_ID),
+            config(),
+            TestAccount::program(ASSOCIATED_TOKEN_PROGRAM_ID),
+        ]
+    }
+
+    fn check_make_accounts(accounts: &[TestAccount]) -> Result<(), ProgramError> {
+        let views: Vec<AccountView> = accounts.iter().map(TestAccount::view).collect();
+        MakeAccounts::try_from(&views[..]).map(|_| ())
+    }
+
+    /// Required Make data: seed, receive and amount
+    fn make_data(seed: u64, receive: u64, amount: u64) -> Vec<u8> {
+        [seed.to_le_bytes(), receive.to_le_bytes(), amount.to_le_bytes()].concat()
+    }
+
+    /// An optional Make entry: `[tag][len][value]`
+    fn entry(tag: u8, value: &[u8]) -> Vec<u8> {
+        [&[tag, value.len() as u8][..], value].concat()
+    }
+
+    #[test]
+    fn data_lengths_match_the_encoded_data() {
+        let data = make_data(7, 20, 0);
+        assert_eq!(data.len(), MAKE_DATA_LEN);
+
+        // Every optional entry once is the longest data Make accepts
+        let data = [
+            data,
+            entry(MAKE_TAG_DEADLINE, &1i64.to_le_bytes()),
+            entry(MAKE_TAG_MIN_FILL, &1u64.to_le_bytes()),
+            entry(MAKE_TAG_ORACLE, &[address(9).as_ref(), &1u64.to_le_bytes()].concat()),
+            entry(MAKE_TAG_DELEGATE, address(10).as_ref()),
+            entry(MAKE_TAG_FLAGS, &[Escrow::FLAG_PARTIAL_FILL]),
+            entry(MAKE_TAG_COSIGNER, address(11).as_ref()),
+            entry(MAKE_TAG_PREFUNDED, &[]),
+        ]
+        .concat();
+        assert_eq!(data.len(), MAKE_DATA_MAX_LEN);
+        assert!(MakeInstructionData::try_from(&data[..]).unwrap().is_prefunded());
+        assert_eq!(
+            MakeInstructionData::try_from(&[&data[..], &[0]].concat()[..]).err(),
+            Some(ProgramError::InvalidInstructionData),
+        );
+    }
+
+    #[test]
+    fn parses_any_combination_of_optional_entries() {
+        let parsed = MakeInstructionData::try_from(&make_data(7, 20, 10)[..]).unwrap();
+        assert_eq!((parsed.deadline, parsed.min_fill, parsed.delegate.clone(), parsed.flags), (0, 0, Address::default(), 0));
+
+        let data = [make_data(7, 20, 10), entry(MAKE_TAG_DEADLINE, &100i64.to_le_bytes())].concat();
+        let parsed = MakeInstructionData::try_from(&data[..]).unwrap();
+        assert_eq!((parsed.deadline, parsed.min_fill), (100, 0));
+
+        // In any order
+        let data = [
+            make_data(7, 20, 10),
+            entry(MAKE_TAG_DELEGATE, address(10).as_ref()),
+            entry(MAKE_TAG_MIN_FILL, &5u64.to_le_bytes()),
+        ]
+        .concat();
+        let parsed = MakeInstructionData::try_from(&data[..]).unwrap();
+        assert_eq!((parsed.deadline, parsed.min_fill, parsed.delegate.clone()), (0, 5, address(10)));
+
+        // A repeated tag, an unknown one, a wrong length and a truncated value
+        for entries in [
+            [entry(MAKE_TAG_DEADLINE, &1i64.to_le_bytes()), entry(MAKE_TAG_DEADLINE, &2i64.to_le_bytes())].concat(),
+            entry(0xff, &[]),
+            entry(MAKE_TAG_DEADLINE, &1u32.to_le_bytes()),
+            vec![MAKE_TAG_MIN_FILL, 8, 1],
+        ] {
+            let data = [make_data(7, 20, 10), entries].concat();
+            assert_eq!(MakeInstructionData::try_from(&data[..]).err(), Some(ProgramError::InvalidInstructionData));
+        }
+    }
+
+    #[test]
+    fn accepts_seeds_up_to_max_seed() {
+        for seed in [MIN_SEED, 42, MAX_SEED] {
+            let data = MakeInstructionData::try_from(&make_data(seed, 20, 10)[..]).unwrap();
+            assert_eq!(data.seed, seed);
+        }
+    }
+
+    #[test]
+    fn rejects_reserved_seeds() {
+        for seed in [MAX_SEED + 1, u64::MAX] {
+            assert_eq!(
+                MakeInstructionData::try_from(&make_data(seed, 20, 10)[..]).err(),
+                Some(ProgramError::InvalidInstructionData),
+            );
+        }
+    }
+
+    #[test]
+    fn prefunded_escrows_are_flagged_explicitly() {
+        let mut data = make_data(7, 20, 0);
+        data.extend_from_slice(&[MAKE_TAG_PREFUNDED, 0]);
+        let parsed = MakeInstructionData::try_from(&data[..]).unwrap();
+        assert!(parsed.is_prefunded());
+        assert_eq!(parsed.amount, DepositAmount(0));
+
+        // No deposit without the flag, and no deposit with it
+        assert_eq!(MakeInstructionData::try_from(&make_data(7, 20, 0)[..]).err(), Some(ProgramError::InvalidInstructionData));
+        let mut data = make_data(7, 20, 10);
+        data.extend_from_slice(&[MAKE_TAG_PREFUNDED, 0]);
+        assert_eq!(MakeInstructionData::try_from(&data[..]).err(), Some(ProgramError::InvalidInstructionData));
+        assert!(!MakeInstructionData::try_from(&make_data(7, 20, 10)[..]).unwrap().is_prefunded());
+    }
+
+    #[test]
+    fn records_a_prefunded_vault() {
+        let mut accounts = make_accounts(mint(address(3)), mint(address(4)));
+        let escrow = accounts[MakeAccount::Escrow as usize].view().address().clone();
+        accounts[MakeAccount::Vault as usize] = token_account(ata_address(&escrow, &address(3)), &address(3), &escrow, 10);
+        let views: Vec<AccountView> = accounts.iter().map(TestAccount::view).collect();
+        let mut data = make_data(7, 20, 0);
+        data.extend_from_slice(&[MAKE_TAG_PREFUNDED, 0]);
+
+        let make = Make::try_from((&views[..], &data[..])).unwrap();
+        assert!(make.instruction_data.is_prefunded());
+
+        // An empty vault has nothing to record
+        accounts[MakeAccount::Vault as usize] = token_account(ata_address(&escrow, &address(3)), &address(3), &escrow, 0);
+        let views: Vec<AccountView> = accounts.iter().map(TestAccount::view).collect();
+        assert_eq!(Make::try_from((&views[..], &data[..])).err(), Some(ProgramError::InsufficientFunds));
+    }
+
+    #[test]
+    fn a_separate_payer_funds_the_rent() {
+        let mut accounts = make_accounts(mint(address(3)), mint(address(4)));
+        accounts.push(TestAccount::wallet(address(10)).signer().writable());
+        let views: Vec<AccountView> = accounts.iter().map(TestAccount::view).collect();
+        let (maker_lamports, payer_lamports) = (views[MakeAccount::Maker as usize].lamports(), views[MakeAccount::COUNT].lamports());
+
+        Make::try_from((&views[..], &make_data(7, 20, 10)[..])).unwrap().process().unwrap();
+
+        // The payer funded both accounts, the maker only the deposit
+        assert_eq!(views[MakeAccount::Maker as usize].lamports(), maker_lamports);
+        assert_eq!(views[MakeAccount::COUNT].lamports(), payer_lamports - rent(Escrow::LEN) - rent(TokenAccount::LEN));
+        assert_eq!(TokenAccount::from_account_view(&views[MakeAccount::MakerAtaA as usize]).unwrap().amount(), 0);
+        assert_eq!(TokenAccount::from_account_view(&views[MakeAccount::Vault as usize]).unwrap().amount(), 10);
+        let data = views[MakeAccount::Escrow as usize].try_borrow().unwrap();
+        assert_eq!(Escrow::load(&data).unwrap().maker, address(1));
+    }
+
+    #[cfg(feature = "client")]
+    #[test]
+    fn derives_the_escrow_the_client_derives() {
+        let (escrow, bump) = crate::client::derive_escrow(&address(1), 7);
+        let accounts = make_accounts(mint(address(3)), mint(address(4)));
+        let views: Vec<AccountView> = accounts.iter().map(TestAccount::view).collect();
+        assert_eq!(views[MakeAccount::Escrow as usize].address(), &escrow);
+        Make::try_from((&views[..], &make_data(7, 20, 10)[..])).unwrap().process().unwrap();
+        let data = views[MakeAccount::Escrow as usize].try_borrow().unwrap();
+        assert_eq!(Escrow::load(&data).unwrap().bump, [bump]);
+
+        // The escrow of another seed
+        let mut accounts = make_accounts(mint(address(3)), mint(address(4)));
+        accounts[MakeAccount::Escrow as usize] = TestAccount::new(crate::client::derive_escrow(&address(1), 8).0, Address::default(), 0, &[]).writable();
+        let views: Vec<AccountView> = accounts.iter().map(TestAccount::view).collect();
+        assert_eq!(Make::try_from((&views[..], &make_data(7, 20, 10)[..])).err(), Some(ProgramError::InvalidInstructionData));
+    }
+
+    #[cfg(feature = "client")]
+    #[test]
+    fn a_refund_reclaims_the_rent_the_client_estimates() {
+        let accounts = make_accounts(mint(address(3)), mint(address(4)));
+        let views: Vec<AccountView> = accounts.iter().map(TestAccount::view).collect();
+        let maker_lamports = views[MakeAccount::Maker as usize].lamports();
+
+        Make::try_from((&views[..], &make_data(7, 20, 10)[..])).unwrap().process().unwrap();
+        assert_eq!(views[MakeAccount::Maker as usize].lamports(), maker_lamports - crate::client::reclaimable_rent());
+
+        let refund = [MakeAccount::Maker, MakeAccount::Escrow, MakeAccount::MintA, MakeAccount::Vault, MakeAccount::MakerAtaA, MakeAccount::TokenProgram]
+            .map(|account| accounts[account as usize].view());
+        crate::instructions::Refund::try_from(&refund[..]).unwrap().process().unwrap();
+        assert_eq!(views[MakeAccount::Maker as usize].lamports(), maker_lamports);
+    }
+
+    #[test]
+    fn rejects_an_escrow_account_already_in_use() {
+        let make = |escrow: TestAccount| {
+            let mut accounts = make_accounts(mint(address(3)), mint(address(4)));
+            accounts[MakeAccount::Escrow as usize] = escrow;
+            let views: Vec<AccountView> = accounts.iter().map(TestAccount::view).collect();
+            Make::try_from((&views[..], &make_data(7, 20, 10)[..])).err()
+        };
+        let (escrow, _) = escrow_address(&address(1), 7);
+        assert_eq!(make(TestAccount::new(escrow.clone(), address(9), 1, &[]).writable()), Some(ProgramError::IllegalOwner));
+        assert_eq!(make(TestAccount::new(escrow, Address::default(), 1, &[1]).writable()), Some(ProgramError::AccountAlreadyInitialized));
+    }
+
+    #[test]
+    fn checks_the_mints_before_maker_ata_a() {
+        // A maker_ata_a of mint_b is a bad maker_ata_a
+        let mut accounts = make_accounts(mint(address(3)), mint(address(4)));
+        accounts[MakeAccount::MakerAtaA as usize] = token_account(address(5), &address(4), &address(1), 10);
+        assert_eq!(check_make_accounts(&accounts), Err(ProgramError::InvalidAccountData));
+
+        // But a mint_a that isn't a mint is reported first, whatever maker_ata_a holds
+        accounts[MakeAccount::MintA as usize] = TestAccount::wallet(address(3));
+        assert_eq!(check_make_accounts(&accounts), Err(ProgramError::InvalidAccountOwner));
+    }
+
+    #[test]
+    fn rejects_a_deposit_above_the_makers_balance() {
+        // maker_ata_a holds 10
+        let accounts = make_accounts(mint(address(3)), mint(address(4)));
+        let views: Vec<AccountView> = accounts.iter().map(TestAccount::view).collect();
+        assert_eq!(Make::try_from((&views[..], &make_data(7, 20, 11)[..])).err(), Some(ProgramError::InsufficientFunds));
+        assert!(Make::try_from((&views[..], &make_data(7, 20, 10)[..])).is_ok());
+    }
+
+    #[test]
+    fn stores_the_mode_flags() {
+        for (entries, partial_fill) in [(vec![], false), (entry(MAKE_TAG_FLAGS, &[Escrow::FLAG_PARTIAL_FILL]), true)] {
+            let accounts = make_accounts(mint(address(3)), mint(address(4)));
+            let views: Vec<AccountView> = accounts.iter().map(TestAccount::view).collect();
+            let data = [make_data(7, 20, 10), entries].concat();
+            Make::try_from((&views[..], &data[..])).unwrap().process().unwrap();
+
+            let data = views[MakeAccount::Escrow as usize].try_borrow().unwrap();
+            let escrow = Escrow::load(&data).unwrap();
+            assert_eq!(escrow.is_partial_fillable(), partial_fill);
+            assert_eq!(escrow.flags, if partial_fill { Escrow::FLAG_PARTIAL_FILL } else { 0 });
+        }
+    }
+
+    #[test]
+    fn records_the_creation_time() {
+        set_now(1_000);
+        let accounts = make_accounts(mint(address(3)), mint(address(4)));
+        let views: Vec<AccountView> = accounts.iter().map(TestAccount::view).collect();
+        Make::try_from((&views[..], &make_data(7, 20, 10)[..])).unwrap().process().unwrap();
+
+        let data = views[MakeAccount::Escrow as usize].try_borrow().unwrap();
+        let escrow = Escrow::load(&data).unwrap();
+        assert_eq!(escrow.created_at, 1_000);
+        assert_eq!(escrow.age(1_060), 60);
+    }
+
+    #[test]
+    fn rejects_reserved_flag_bits() {
+        let parse = |flags: u8| MakeInstructionData::try_from(&[make_data(7, 20, 10), entry(MAKE_TAG_FLAGS, &[flags])].concat()[..]).map(|data| data.flags);
+        assert_eq!(parse(Escrow::FLAG_PARTIAL_FILL), Ok(Escrow::FLAG_PARTIAL_FILL));
+        for bit in 0..8 {
+            let flag = 1 << bit;
+            if flag & Escrow::FLAGS_ALL == 0 {
+                assert_eq!(parse(flag), Err(EscrowError::ReservedBitsSet.into()), "bit {bit}");
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_a_bogus_system_program() {
+        let mut accounts = make_accounts(mint(address(3)), mint(address(4)));
+        accounts[MakeAccount::SystemProgram as usize] = TestAccount::program(address(9));
+        assert_eq!(check_make_accounts(&accounts), Err(ProgramError::IncorrectProgramId));
+    }
+
+    #[test]
+    fn nft_escrows_deposit_exactly_one_nft() {
+        let nft = || TestAccount::new(address(3), TOKEN_PROGRAM_ID, 1, &mint_data(0, 1));
+        let make = |mint_a: TestAccount, amount: u64| {
+            let accounts = make_accounts(mint_a, mint(address(4)));
+            let views: Vec<AccountView> = accounts.iter().map(TestAccount::view).collect();
+            let data = [make_data(7, 20, amount), entry(MAKE_TAG_FLAGS, &[Escrow::FLAG_NFT])].concat();
+            Make::try_from((&views[..], &data[..]))?.process()?;
+            let data = views[MakeAccount::Escrow as usize].try_borrow()?;
+            Ok::<bool, ProgramError>(Escrow::load(&data)?.is_nft())
+        };
+        assert_eq!(make(nft(), 1), Ok(true));
+        assert_eq!(make(nft(), 2), Err(EscrowError::NotAnNft.into()));
+        // A fungible mint isn't an NFT
+        assert_eq!(make(mint(address(3)), 1), Err(EscrowError::NotAnNft.into()));
+    }
+
+    #[test]
+    fn detects_an_escrow_reallocated_or_reassigned_after_loading() {
+        let account = escrow(&address(1), 7, |_| {});
+        let view = account.view();
+        assert_eq!(ProgramAccount::check_unchanged(&view, Escrow::LEN), Ok(()));
+
+        // As a CPI in between could leave it
+        view.resize(Escrow::LEN + 1).unwrap();
+        assert_eq!(ProgramAccount::check_unchanged(&view, Escrow::LEN), Err(ProgramError::InvalidAccountData));
+        view.resize(Escrow::LEN).unwrap();
+        unsafe { view.assign(&address(9)) };
+        assert_eq!(ProgramAccount::check_unchanged(&view, Escrow::LEN), Err(ProgramError::InvalidAccountOwner));
+    }
+
+    #[test]
+    fn rejects_a_read_only_maker() {
+        let mut accounts = make_accounts(mint(address(3)), mint(address(4)));
+        accounts[MakeAccount::Maker as usize] = TestAccount::wallet(address(1)).signer();
+        assert_eq!(check_make_accounts(&accounts), Err(readonly_account(MakeAccount::Maker as usize)));
+    }
+
+    #[test]
+    fn rejects_aliased_accounts() {
+        let (one, two) = (TestAccount::wallet(address(1)), TestAccount::wallet(address(2)));
+        assert_eq!(assert_distinct(&[&one.view(), &two.view()]), Ok(()));
+        assert_eq!(assert_distinct(&[&one.view(), &two.view(), &one.view()]), Err(ProgramError::InvalidArgument));
+
+        // maker_ata_a passed again as the vault
+        let mut accounts = make_accounts(mint(address(3)), mint(address(4)));
+        accounts[MakeAccount::Vault as usize] = token_account(address(5), &address(3), &address(1), 10);
+        assert_eq!(check_make_accounts(&accounts), Err(ProgramError::InvalidArgument));
+    }
+
+    #[test]
+    fn closing_zeroes_the_escrow_data() {
+        let (account, destination) = (escrow(&address(1), 7, |_| {}), TestAccount::wallet(address(2)));
+        let (view, destination) = (account.view(), destination.view());
+        ProgramAccount::close(&view, &destination).unwrap();
+
+        assert_eq!(view.lamports(), 0);
+        assert!(view.owned_by(&SYSTEM_PROGRAM_ID));
+        assert!(view.try_borrow().unwrap().iter().all(|byte| *byte == 0));
+    }
+
+    #[test]
+    fn deposits_into_a_vault_created_ahead_of_time() {
+        // Anyone may have created the vault ATA before Make
+        let mut accounts = make_accounts(mint(address(3)), mint(address(4)));
+        let escrow = accounts[MakeAccount::Escrow as usize].view().address().clone();
+        accounts[MakeAccount::Vault as usize] = token_account(ata_address(&escrow, &address(3)), &address(3), &escrow, 0);
+        let views: Vec<AccountView> = accounts.iter().map(TestAccount::view).collect();
+        let maker_lamports = views[MakeAccount::Maker as usize].lamports();
+
+        Make::try_from((&views[..], &make_data(7, 20, 10)[..])).unwrap().process().unwrap();
+
+        assert_eq!(TokenAccount::from_account_view(&views[MakeAccount::Vault as usize]).unwrap().amount(), 10);
+        // Only the escrow's rent was paid
+        assert_eq!(views[MakeAccount::Maker as usize].lamports(), maker_lamports - rent(Escrow::LEN));
+    }
+
+    #[test]
+    fn makes_a_keypair_escrow_with_a_vault_authority_vault() {
+        let mut accounts = make_accounts(mint(address(3)), mint(address(4)));
+        let (vault_authority, bump) = Escrow::vault_authority(&address(30));
+        accounts[MakeAccount::Escrow as usize] = TestAccount::new(address(30), Address::default(), 0, &[]).signer().writable();
+        accounts[MakeAccount::Vault as usize] = TestAccount::new(ata_address(&vault_authority, &address(3)), Address::default(), 0, &[]).writable();
+        accounts.push(TestAccount::wallet(vault_authority.clone()));
+        let views: Vec<AccountView> = accounts.iter().map(TestAccount::view).collect();
+
+        // Not derived from the seed, but the vault authority must be the escrow's
+        let data = make_data(7, 20, 10);
+        let mut make = Make::try_from((&views[..], &data[..])).unwrap();
+        assert_eq!(make.bump, bump);
+        make.process().unwrap();
+
+        let data = views[MakeAccount::Escrow as usize].try_borrow().unwrap();
+        let escrow = Escrow::load(&data).unwrap();
+        assert!(escrow.is_keypair());
+        assert_eq!(escrow.bump, [bump]);
+        assert_eq!(escrow.vault_amount(&views[MakeAccount::COUNT], &views[MakeAccount::Vault as usize]), Ok(10));
+
+        let mut accounts = make_accounts(mint(address(3)), mint(address(4)));
+        accounts[MakeAccount::Escrow as usize] = TestAccount::new(address(30), Address::default(), 0, &[]).signer().writable();
+        accounts.push(TestAccount::wallet(address(31)));
+        let views: Vec<AccountView> = accounts.iter().map(TestAccount::view).collect();
+        assert_eq!(Make::try_from((&views[..], &make_data(7, 20, 10)[..])).err(), Some(ProgramError::InvalidSeeds));
+    }
+
+    #[test]
+    fn make_account_positions_match_the_accounts() {
+        let accounts = make_accounts(mint(address(3)), mint(address(4)));
+        let views: Vec<AccountView> = accounts.iter().map(TestAccount::view).collect();
+        let make = MakeAccounts::try_from(&views[..]).unwrap();
+        for (position, account) in [
+            (MakeAccount::Maker, make.maker),
+            (MakeAccount::Escrow, make.escrow),
+            (MakeAccount::MintA, make.mint_a),
+            (MakeAccount::MintB, make.mint_b),
+            (MakeAccount::MakerAtaA, make.maker_ata_a),
+            (MakeAccount::Vault, make.vault),
+            (MakeAccount::SystemProgram, make.system_program),
+            (MakeAccount::TokenProgram, make.token_program),
+            (MakeAccount::Config, make.config),
+            (MakeAccount::AssociatedTokenProgram, make.associated_token_program),
+        ] {
+            assert_eq!(account.address(), views[position as usize].address(), "{position:?}");
+        }
+        assert_eq!(views.len(), MakeAccount::COUNT);
+    }
+
+    #[test]
+    fn measures_the_deposit_against_the_vaults_prior_balance() {
+        // Someone sent tokens to the vault ahead of time
+        let mut accounts = make_accounts(mint(address(3)), mint(address(4)));
+        let escrow = accounts[MakeAccount::Escrow as usize].view().address().clone();
+        accounts[MakeAccount::Vault as usize] = token_account(ata_address(&escrow, &address(3)), &address(3), &escrow, 5);
+        let views: Vec<AccountView> = accounts.iter().map(TestAccount::view).collect();
+
+        Make::try_from((&views[..], &make_data(7, 20, 10)[..])).unwrap().process().unwrap();
+        assert_eq!(TokenAccount::from_account_view(&views[MakeAccount::Vault as usize]).unwrap().amount(), 15);
+    }
+
+    #[test]
+    fn rejects_a_vault_closable_by_a_third_party() {
+        let make = |close_authority: Option<Address>| {
+            let mut accounts = make_accounts(mint(address(3)), mint(address(4)));
+            let escrow = accounts[MakeAccount::Escrow as usize].view().address().clone();
+            let mut data = token_account_data(&address(3), &escrow, 0);
+            if let Some(close_authority) = close_authority {
+                data[129] = 1;
+                data[133..165].copy_from_slice(close_authority.as_ref());
+            }
+            accounts[MakeAccount::Vault as usize] = TestAccount::new(ata_address(&escrow, &address(3)), TOKEN_PROGRAM_ID, 1, &data).writable();
+            let views: Vec<AccountView> = accounts.iter().map(TestAccount::view).collect();
+            Make::try_from((&views[..], &make_data(7, 20, 10)[..])).map(|_| ())
+        };
+        assert_eq!(make(None), Ok(()));
+        assert_eq!(make(Some(escrow_address(&address(1), 7).0)), Ok(()));
+        assert_eq!(make(Some(address(9))), Err(ProgramError::InvalidAccountData));
+    }
+
+    #[test]
+    fn rejects_a_payer_that_doesnt_sign() {
+        let mut accounts = make_accounts(mint(address(3)), mint(address(4)));
+        accounts.push(TestAccount::wallet(address(10)).writable());
+        assert_eq!(check_make_accounts(&accounts), Err(ProgramError::InvalidInstructionData));
+    }
+
+    #[test]
+    fn rejects_a_transfer_hook_mint() {
+        let accounts = make_accounts(mint_2022(address(3), &[TRANSFER_HOOK_EXTENSION]), mint(address(4)));
+        assert_eq!(check_make_accounts(&accounts), Err(EscrowError::TransferHookNotSupported.into()));
+        let accounts = make_accounts(mint(address(3)), mint_2022(address(4), &[TRANSFER_HOOK_EXTENSION]));
+        assert_eq!(check_make_accounts(&accounts), Err(EscrowError::TransferHookNotSupported.into()));
+    }
+
+    #[test]
+    fn rejects_another_associated_token_program() {
+        let mut accounts = make_accounts(mint(address(3)), mint(address(4)));
+        // An executable program, just not the associated token account program
+        accounts[MakeAccount::AssociatedTokenProgram as usize] = TestAccount::program(address(9));
+        assert_eq!(check_make_accounts(&accounts), Err(ProgramError::IncorrectProgramId));
+        accounts[MakeAccount::AssociatedTokenProgram as usize] = TestAccount::program(ASSOCIATED_TOKEN_PROGRAM_ID);
+        assert_eq!(check_make_accounts(&accounts), Ok(()));
+    }
+
+    #[test]
+    fn creates_associated_token_accounts_only_through_the_program() {
+        let (wallet, mint_address, custom) = (address(1), address(3), address(9));
+        let (wallet, mint, token_program, system_program) = (
+            TestAccount::wallet(wallet).signer().writable(),
+            mint(mint_address),
+            TestAccount::program(TOKEN_PROGRAM_ID),
+            TestAccount::program(SYSTEM_PROGRAM_ID),
+        );
+        let init = |ata: &TestAccount, program: &TestAccount| AssociatedTokenAccount::init_if_needed(
+            &ata.view(), &mint.view(), &wallet.view(), &wallet.view(), &system_program.view(), &token_program.view(), &program.view(),
+        );
+
+        // Not even at the address a fake program derives for itself
+        let (derived, _) = Address::find_program_address(
+            &[wallet.view().address().as_ref(), TOKEN_PROGRAM_ID.as_ref(), mint.view().address().as_ref()],
+            &custom,
+        );
+        let derived = TestAccount::new(derived, Address::default(), 0, &[]).writable();
+        assert_eq!(init(&derived, &TestAccount::program(custom)), Err(ProgramError::IncorrectProgramId));
+        assert!(derived.view().is_data_empty());
+
+        // Only at the canonical address
+        let program = TestAccount::program(ASSOCIATED_TOKEN_PROGRAM_ID);
+        assert_eq!(init(&derived, &program), Err(ProgramError::InvalidSeeds));
+        let canonical = TestAccount::new(ata_address(wallet.view().address(), mint.view().address()), Address::default(), 0, &[]).writable();
+        assert_eq!(init(&canonical, &program), Ok(()));
+        assert!(canonical.view().owned_by(&TOKEN_PROGRAM_ID));
+    }
+
+    #[test]
+    fn rejects_makes_while_paused() {
+        let mut accounts = make_accounts(mint(address(3)), mint(address(4)));
+        accounts[MakeAccount::Config as usize] = initialized_config(true);
+        assert_eq!(check_make_accounts(&accounts), Err(EscrowError::ProgramPaused.into()));
+
+        accounts[MakeAccount::Config as usize] = initialized_config(false);
+        assert_eq!(check_make_accounts(&accounts), Ok(()));
+    }
+
+    #[test]
+    fn rejects_the_same_mint_on_both_sides() {
+        let accounts = make_accounts(mint(address(3)), mint(address(3)));
+        assert_eq!(check_make_accounts(&accounts), Err(ProgramError::InvalidArgument));
+    }
+
+    #[test]
+    fn rejects_a_blocked_mint() {
+        let (mint_a, mint_b, token_program) = (mint(address(3)), mint(address(4)), TestAccount::program(TOKEN_PROGRAM_ID));
+        let check = |blocked: &[Address]| MintInterface::check_pair_with(&mint_a.view(), &mint_b.view(), &token_program.view(), blocked);
+        assert_eq!(check(&[address(9)]), Ok(()));
+        assert_eq!(check(&[address(3)]), Err(EscrowError::MintBlocked.into()));
+        assert_eq!(check(&[address(4)]), Err(EscrowError::MintBlocked.into()));
+    }
+
+    #[test]
+    fn accepts_legacy_mints() {
+        assert_eq!(check_make_accounts(&make_accounts(mint(address(3)), mint(address(4)))), Ok(()));
+    }
+
+    #[test]
+    fn takes_mints_of_different_token_programs_only_with_multi_token_program() {
+        // Without the feature mint_b's transfers would go to mint_a's token program
+        let accounts = make_accounts(mint(address(3)), mint_2022(address(4), &[]));
+        let expected = if MULTI_TOKEN_PROGRAM { Ok(()) } else { Err(ProgramError::IncorrectProgramId) };
+        assert_eq!(check_make_accounts(&accounts), expected);
+        // The deposit goes to the token program passed in, which mint_a always belongs to
+        let accounts = make_accounts(mint_2022(address(3), &[]), mint(address(4)));
+        assert_eq!(check_make_accounts(&accounts), Err(ProgramError::IncorrectProgramId));
+    }
+
+    #[test]
+    fn accepts_token_2022_only_with_multi_token_program() {
+        let token_2022 = TestAccount::program(TOKEN_2022_PROGRAM_ID);
+        let expected = if MULTI_TOKEN_PROGRAM { Ok(()) } else { Err(ProgramError::IncorrectProgramId) };
+        assert_eq!(TokenProgram::check(&token_2022.view()), expected);
+        assert_eq!(TokenProgram::check(&TestAccount::program(TOKEN_PROGRAM_ID).view()), Ok(()));
+    }
+
+    #[cfg(feature = "multi-token-program")]
+    #[test]
+    fn deposits_through_token_2022() {
+        let maker = address(1);
+        let (escrow, _) = escrow_address(&maker, 7);
+        let mut accounts = make_accounts(mint_2022(address(3), &[]), mint(address(4)));
+        accounts[MakeAccount::MakerAtaA as usize] = token_account_2022(address(5), &address(3), &maker, 10);
+        accounts[MakeAccount::Vault as usize] = TestAccount::new(ata_address_of(&escrow, &address(3), &TOKEN_2022_PROGRAM_ID), Address::default(), 0, &[]).writable();
+        accounts[MakeAccount::TokenProgram as usize] = TestAccount::program(TOKEN_2022_PROGRAM_ID);
+        let views: Vec<AccountView> = accounts.iter().map(TestAccount::view).collect();
+
+        Make::try_from((&views[..], &make_data(7, 20, 10)[..])).unwrap().process().unwrap();
+
+        // The vault is a Token-2022 account the deposit went through Token-2022 into
+        let vault = &views[MakeAccount::Vault as usize];
+        assert!(vault.owned_by(&TOKEN_2022_PROGRAM_ID));
+        assert_eq!(balance(vault), 10);
+        assert_eq!(balance(&views[MakeAccount::MakerAtaA as usize]), 0);
+    }
+
+    #[test]
+    fn reads_token_accounts_of_either_token_program() {
+        let data = token_account_data(&address(3), &address(1), 10);
+        let legacy = token_account(address(5), &address(3), &address(1), 10);
+        assert_eq!(TokenAccountInterface::from_account_view(&legacy.view()).unwrap().amount(), 10);
+
+        // A Token-2022 account may carry extensions after its account type
+        let extended = [&data[..], &[TOKEN_ACCOUNT_ACCOUNT_TYPE, 7, 0, 0, 0]].concat();
+        let token_2022 = TestAccount::new(address(5), TOKEN_2022_PROGRAM_ID, 1, &extended);
+        assert_eq!(TokenAccountInterface::from_account_view(&token_2022.view()).unwrap().amount(), 10);
+        // A legacy one may not, nor may any account without the token account type
+        let legacy = TestAccount::new(address(5), TOKEN_PROGRAM_ID, 1, &extended);
+        assert_eq!(TokenAccountInterface::from_account_view(&legacy.view()).err(), Some(ProgramError::InvalidAccountData));
+        let token_2022 = TestAccount::new(address(5), TOKEN_2022_PROGRAM_ID, 1, &mint_2022_data(&[]));
+        assert_eq!(TokenAccountInterface::from_account_view(&token_2022.view()).err(), Some(ProgramError::InvalidAccountData));
+
+        let token_2022 = token_account_2022(address(5), &address(3), &address(1), 10);
+        assert_eq!(TokenAccountInterface::from_account_view(&token_2022.view()).unwrap().amount(), 10);
+
+        let other = TestAccount::new(address(5), address(9), 1, &data);
+        assert_eq!(TokenAccountInterface::from_account_view(&other.view()).err(), Some(ProgramError::InvalidAccountOwner));
+    }
+
+    #[test]
+    fn mint_check_reads_token_2022_mints_with_extensions() {
+        let mint = mint_2022(address(3), &[TRANSFER_HOOK_EXTENSION, TRANSFER_FEE_CONFIG_EXTENSION]);
+        assert_eq!(MintInterface::check(&mint.view()), Ok(()));
+        assert_eq!(MintInterface::has_extension(&mint.view(), TRANSFER_FEE_CONFIG_EXTENSION), Ok(true));
+        // A token account has the same owner, but isn't a mint
+        let account = token_account(address(5), &address(3), &address(1), 10);
+        assert_eq!(MintInterface::check(&account.view()), Err(ProgramError::InvalidAccountData));
+    }
+
+    #[test]
+    fn rejects_a_transfer_fee_mint() {
+        let accounts = make_accounts(mint_2022(address(3), &[TRANSFER_FEE_CONFIG_EXTENSION]), mint(address(4)));
+        assert_eq!(check_make_accounts(&accounts), Err(EscrowError::TransferFeeNotSupported.into()));
+        let accounts = make_accounts(mint(address(3)), mint_2022(address(4), &[TRANSFER_FEE_CONFIG_EXTENSION]));
+        assert_eq!(check_make_accounts(&accounts), Err(EscrowError::TransferFeeNotSupported.into()));
+    }
+}
@@ -0,0 +1,165 @@
+use pinocchio::{AccountView, ProgramResult, error::ProgramError};
+use super::make::{Make, MakeAccount, MAKE_DATA_LEN};
+
+/// Number of accounts in each escrow group: `escrow`, `mint_a`, `mint_b`, `maker_ata_a`, `vault`
+pub const MAKE_MANY_GROUP_LEN: usize = 5;
+
+/// Maximum number of escrows created in a single instruction
+pub const MAX_MAKE_MANY_GROUPS: usize = 8;
+
+/// Creates several escrows of the same maker in one instruction.
+///
+/// Each group goes through the full `Make` validation and processing, as if sent
+/// as its own Make with the shared accounts. Any invalid group fails the whole
+/// instruction. Groups only carry the required Make data, so every escrow is a
+/// plain PDA escrow without deadline, oracle, delegate or flags, funded by the maker.
+///
+/// Instruction data: one `MAKE_DATA_LEN` entry (seed, receive, amount) per group,
+/// in group order. Errors naming an account position refer to the Make layout.
+///
+/// # Accounts
+/// - `maker`: Maker's wallet account (signer)
+/// - `system_program`: System program
+/// - `token_program`: Token program
+/// - `config`: Program config PDA (may be uninitialized)
+/// - `associated_token_program`: Associated token account program
+/// - then for each escrow: `escrow`, `mint_a`, `mint_b`, `maker_ata_a`, `vault`
+pub struct MakeMany<'a> {
+    pub maker: &'a AccountView,
+    pub system_program: &'a AccountView,
+    pub token_program: &'a AccountView,
+    pub config: &'a AccountView,
+    pub associated_token_program: &'a AccountView,
+    pub groups: &'a [AccountView],
+    pub data: &'a [u8],
+}
+
+impl<'a> TryFrom<(&'a [AccountView], &'a [u8])> for MakeMany<'a> {
+    type Error = ProgramError;
+    fn try_from((accounts, data): (&'a [AccountView], &'a [u8])) -> Result<Self, Self::Error> {
+        let [maker, system_program, token_program, config, associated_token_program, groups @ ..] = accounts else {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        };
+
+        if groups.is_empty() || groups.len() % MAKE_MANY_GROUP_LEN != 0 {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+        let count = groups.len() / MAKE_MANY_GROUP_LEN;
+        if count > MAX_MAKE_MANY_GROUPS {
+            return Err(ProgramError::InvalidArgument);
+        }
+        if data.len() != count * MAKE_DATA_LEN {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+
+        Ok(Self { maker, system_program, token_program, config, associated_token_program, groups, data })
+    }
+}
+
+impl<'a> MakeMany<'a> {
+    pub const DISCRIMINATOR: &'a u8 = &crate::constants::MAKE_MANY_DISCRIMINATOR;
+
+    pub fn process(&self) -> ProgramResult {
+        let groups = self.groups.chunks_exact(MAKE_MANY_GROUP_LEN);
+        for (group, data) in groups.zip(self.data.chunks_exact(MAKE_DATA_LEN)) {
+            let accounts = self.make_accounts(group)?;
+            Make::try_from((&accounts[..], data))?.process()?;
+        }
+        Ok(())
+    }
+
+    /// The accounts of `group`'s Make, in Make's order
+    fn make_accounts(&self, group: &[AccountView]) -> Result<[AccountView; MakeAccount::COUNT], ProgramError> {
+        let [escrow, mint_a, mint_b, maker_ata_a, vault] = group else {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        };
+        Ok([
+            self.maker.clone(),
+            escrow.clone(),
+            mint_a.clone(),
+            mint_b.clone(),
+            maker_ata_a.clone(),
+            vault.clone(),
+            self.system_program.clone(),
+            self.token_program.clone(),
+            self.config.clone(),
+            self.associated_token_program.clone(),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pinocchio::Address;
+    use crate::{constants::{ASSOCIATED_TOKEN_PROGRAM_ID, SYSTEM_PROGRAM_ID, TOKEN_PROGRAM_ID}, test_utils::*};
+
+    /// Maker 1's shared accounts, then a group per `(seed, mint_a, mint_b)`, each
+    /// funded with 10 mint_a
+    fn make_many_accounts(groups: &[(u64, u8, u8)]) -> Vec<TestAccount> {
+        let maker = address(1);
+        let mut accounts = vec![
+            TestAccount::wallet(maker.clone()).signer().writable(),
+            TestAccount::program(SYSTEM_PROGRAM_ID),
+            TestAccount::program(TOKEN_PROGRAM_ID),
+            config(),
+            TestAccount::program(ASSOCIATED_TOKEN_PROGRAM_ID),
+        ];
+        for &(seed, mint_a, mint_b) in groups {
+            let (escrow, _) = escrow_address(&maker, seed);
+            accounts.extend([
+                TestAccount::new(escrow, Address::default(), 0, &[]).writable(),
+                mint(address(mint_a)),
+                mint(address(mint_b)),
+                token_account(address(mint_a + 100), &address(mint_a), &maker, 10),
+                TestAccount::new(address(mint_a + 200), Address::default(), 0, &[]).writable(),
+            ]);
+        }
+        accounts
+    }
+
+    /// One group's Make data: seed, receive and amount
+    fn make_data(seed: u64, receive: u64, amount: u64) -> Vec<u8> {
+        [seed.to_le_bytes(), receive.to_le_bytes(), amount.to_le_bytes()].concat()
+    }
+
+    #[test]
+    fn validates_each_group_as_its_own_make() {
+        let accounts = make_many_accounts(&[(7, 3, 4), (8, 5, 6)]);
+        let views: Vec<AccountView> = accounts.iter().map(TestAccount::view).collect();
+        let data = [make_data(7, 20, 10), make_data(8, 30, 5)].concat();
+        let make_many = MakeMany::try_from((&views[..], &data[..])).unwrap();
+
+        let groups = make_many.groups.chunks_exact(MAKE_MANY_GROUP_LEN).zip(make_many.data.chunks_exact(MAKE_DATA_LEN));
+        let escrows: Vec<(Address, u64)> = groups
+            .map(|(group, data)| {
+                let accounts = make_many.make_accounts(group).unwrap();
+                let make = Make::try_from((&accounts[..], data)).unwrap();
+                (make.accounts.escrow.address().clone(), make.instruction_data.seed)
+            })
+            .collect();
+        assert_eq!(escrows, [(escrow_address(&address(1), 7).0, 7), (escrow_address(&address(1), 8).0, 8)]);
+    }
+
+    #[test]
+    fn an_invalid_group_fails_its_make() {
+        let accounts = make_many_accounts(&[(7, 3, 4), (8, 5, 5)]);
+        let views: Vec<AccountView> = accounts.iter().map(TestAccount::view).collect();
+        let data = [make_data(7, 20, 10), make_data(8, 30, 5)].concat();
+        let make_many = MakeMany::try_from((&views[..], &data[..])).unwrap();
+
+        // The second group trades a mint against itself
+        let accounts = make_many.make_accounts(&make_many.groups[MAKE_MANY_GROUP_LEN..]).unwrap();
+        assert_eq!(Make::try_from((&accounts[..], &data[MAKE_DATA_LEN..])).err(), Some(ProgramError::InvalidArgument));
+    }
+
+    #[test]
+    fn rejects_data_not_matching_the_groups() {
+        let accounts = make_many_accounts(&[(7, 3, 4), (8, 5, 6)]);
+        let views: Vec<AccountView> = accounts.iter().map(TestAccount::view).collect();
+        let data = make_data(7, 20, 10);
+        assert_eq!(MakeMany::try_from((&views[..], &data[..])).err(), Some(ProgramError::InvalidInstructionData));
+        // Nor a partial group
+        assert_eq!(MakeMany::try_from((&views[..views.len() - 1], &data[..])).err(), Some(ProgramError::NotEnoughAccountKeys));
+    }
+}
@@ -0,0 +1,284 @@
+use pinocchio::{AccountView, ProgramResult, cpi::{Seed,Signer}, error::ProgramError};
+use super::make::{MintInterface,AssociatedTokenAccount,ProgramAccount,TokenProgram,ConfigAccount};
+use crate::{error::EscrowError, state::Escrow};
+use crate::cpi;
+
+/// Settles two complementary escrows against each other, without a taker.
+///
+/// Escrow 1 offers `mint_a` for `mint_b` and escrow 2 offers `mint_b` for
+/// `mint_a`. The match is accepted only if each vault covers what the other
+/// maker asks for; each vault is then drained to the other maker and both
+/// escrows are closed, returning their rent to their makers. Oracle-priced
+/// escrows can't be matched, nor persistent ones, which must stay open.
+///
+/// # Accounts
+/// - `maker_1`: Maker of escrow 1
+/// - `escrow_1`: Escrow offering mint_a for mint_b
+/// - `vault_1`: Vault of escrow 1 (mint_a)
+/// - `maker_1_ata_b`: Maker 1's token account for mint_b
+/// - `maker_2`: Maker of escrow 2
+/// - `escrow_2`: Escrow offering mint_b for mint_a
+/// - `vault_2`: Vault of escrow 2 (mint_b)
+/// - `maker_2_ata_a`: Maker 2's token account for mint_a
+/// - `mint_a`: Mint deposited in escrow 1
+/// - `mint_b`: Mint deposited in escrow 2
+/// - `token_program`: Token program
+/// - `config`: Program config PDA (may be uninitialized)
+pub struct Match<'a> {
+    pub accounts: MatchAccounts<'a>,
+}
+
+impl<'a> TryFrom<&'a [AccountView]> for Match<'a> {
+    type Error = ProgramError;
+    fn try_from(accounts: &'a [AccountView]) -> Result<Self, Self::Error> {
+        Ok(Self {
+            accounts: MatchAccounts::try_from(accounts)?,
+        })
+    }
+}
+
+impl<'a> Match<'a> {
+    pub const DISCRIMINATOR: &'a u8 = &crate::constants::MATCH_DISCRIMINATOR;
+
+    /// 1. both escrows are valid, live, fixed-price, not persistent and without cosigner
+    /// 2. each vault covers the other maker's receive
+    /// 3. vault_1:mint_a -> maker_2_ata_a, vault_2:mint_b -> maker_1_ata_b
+    /// 4. close both vaults and escrows
+    pub fn process(&self) -> ProgramResult {
+        let accounts = &self.accounts;
+
+        let escrow_1 = Escrow::load_owned(&accounts.escrow_1.try_borrow()?)?;
+        let escrow_2 = Escrow::load_owned(&accounts.escrow_2.try_borrow()?)?;
+        Escrow::verify_pda(accounts.escrow_1, accounts.maker_1.address(), escrow_1.seed)?;
+        Escrow::verify_pda(accounts.escrow_2, accounts.maker_2.address(), escrow_2.seed)?;
+
+        let now = crate::unix_timestamp()?;
+        if escrow_1.is_expired(now) || escrow_2.is_expired(now) {
+            return Err(EscrowError::EscrowExpired.into());
+        }
+        if escrow_1.uses_oracle() || escrow_2.uses_oracle() {
+            return Err(EscrowError::IncompatibleEscrows.into());
+        }
+        // A match closes both escrows, which a persistent escrow outlives
+        if escrow_1.is_persistent() || escrow_2.is_persistent() {
+            return Err(EscrowError::IncompatibleEscrows.into());
+        }
+        // Nobody would sign for a cosigned escrow here
+        if escrow_1.has_cosigner() || escrow_2.has_cosigner() {
+            return Err(EscrowError::CosignerRequired.into());
+        }
+
+        let amount_a = escrow_1.vault_amount(accounts.escrow_1, accounts.vault_1)?;
+        let amount_b = escrow_2.vault_amount(accounts.escrow_2, accounts.vault_2)?;
+        if amount_b < escrow_1.receive.get() || amount_a < escrow_2.receive.get() {
+            return Err(EscrowError::IncompatibleEscrows.into());
+        }
+
+        let seed_binding_1 = escrow_1.seed.to_le_bytes();
+        let seed_1 = [
+            Seed::from(Escrow::SEED),
+            Seed::from(accounts.maker_1.address().as_ref()),
+            Seed::from(&seed_binding_1),
+            Seed::from(&escrow_1.bump),
+        ];
+        let signer_1 = [Signer::from(&seed_1)];
+
+        let seed_binding_2 = escrow_2.seed.to_le_bytes();
+        let seed_2 = [
+            Seed::from(Escrow::SEED),
+            Seed::from(accounts.maker_2.address().as_ref()),
+            Seed::from(&seed_binding_2),
+            Seed::from(&escrow_2.bump),
+        ];
+        let signer_2 = [Signer::from(&seed_2)];
+
+        // vault_1:mint_a -> maker_2_ata_a
+        cpi::transfer(
+            accounts.token_program,
+            accounts.vault_1,
+            accounts.maker_2_ata_a,
+            accounts.escrow_1,
+            amount_a,
+            &signer_1,
+        )?;
+
+        // vault_2:mint_b -> maker_1_ata_b
+        cpi::transfer(
+            accounts.token_program,
+            accounts.vault_2,
+            accounts.maker_1_ata_b,
+            accounts.escrow_2,
+            amount_b,
+            &signer_2,
+        )?;
+
+        cpi::close_account(
+            accounts.token_program,
+            accounts.vault_1,
+            accounts.maker_1,
+            accounts.escrow_1,
+            &signer_1,
+        )?;
+
+        cpi::close_account(
+            accounts.token_program,
+            accounts.vault_2,
+            accounts.maker_2,
+            accounts.escrow_2,
+            &signer_2,
+        )?;
+
+        ProgramAccount::close(accounts.escrow_1, accounts.maker_1)?;
+        ProgramAccount::close(accounts.escrow_2, accounts.maker_2)
+    }
+}
+
+pub struct MatchAccounts<'a> {
+    pub maker_1: &'a AccountView,
+    pub escrow_1: &'a AccountView,
+    pub vault_1: &'a AccountView,
+    pub maker_1_ata_b: &'a AccountView,
+    pub maker_2: &'a AccountView,
+    pub escrow_2: &'a AccountView,
+    pub vault_2: &'a AccountView,
+    pub maker_2_ata_a: &'a AccountView,
+    pub mint_a: &'a AccountView,
+    pub mint_b: &'a AccountView,
+    pub token_program: &'a AccountView,
+    pub config: &'a AccountView,
+}
+
+impl<'a> TryFrom<&'a [AccountView]> for MatchAccounts<'a> {
+    type Error = ProgramError;
+    fn try_from(accounts: &'a [AccountView]) -> Result<Self, Self::Error> {
+        let [maker_1, escrow_1, vault_1, maker_1_ata_b, maker_2, escrow_2, vault_2, maker_2_ata_a, mint_a, mint_b, token_program, config] = accounts else {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        };
+
+        ConfigAccount::check_not_paused(config)?;
+
+        // Both escrows and vaults are closed, so none of them may alias another
+        let closed = [escrow_1.address(), vault_1.address(), escrow_2.address(), vault_2.address()];
+        for (index, address) in closed.iter().enumerate() {
+            if closed[index + 1..].contains(address) {
+                return Err(ProgramError::InvalidArgument);
+            }
+        }
+
+        ProgramAccount::check(escrow_1)?;
+        ProgramAccount::check(escrow_2)?;
+        TokenProgram::check(token_program)?;
+        MintInterface::check(mint_a)?;
+        MintInterface::check(mint_b)?;
+
+        // The escrows must trade the same pair of mints in opposite directions
+        {
+            let data_1 = escrow_1.try_borrow()?;
+            let data_2 = escrow_2.try_borrow()?;
+            let escrow_1_state = Escrow::load(&data_1)?;
+            let escrow_2_state = Escrow::load(&data_2)?;
+            if escrow_1_state.maker != *maker_1.address() || escrow_2_state.maker != *maker_2.address() {
+                return Err(ProgramError::InvalidAccountData);
+            }
+            if escrow_1_state.mint_a != *mint_a.address()
+                || escrow_1_state.mint_b != *mint_b.address()
+                || escrow_2_state.mint_a != *mint_b.address()
+                || escrow_2_state.mint_b != *mint_a.address()
+            {
+                return Err(EscrowError::IncompatibleEscrows.into());
+            }
+        }
+
+        AssociatedTokenAccount::check(vault_1, escrow_1, mint_a, token_program)?;
+        AssociatedTokenAccount::check(vault_2, escrow_2, mint_b, token_program)?;
+        AssociatedTokenAccount::check(maker_1_ata_b, maker_1, mint_b, token_program)?;
+        AssociatedTokenAccount::check(maker_2_ata_a, maker_2, mint_a, token_program)?;
+
+        Ok(Self {
+            maker_1,
+            escrow_1,
+            vault_1,
+            maker_1_ata_b,
+            maker_2,
+            escrow_2,
+            vault_2,
+            maker_2_ata_a,
+            mint_a,
+            mint_b,
+            token_program,
+            config,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pinocchio::Address;
+    use pinocchio_token::state::TokenAccount;
+    use crate::{constants::{SYSTEM_PROGRAM_ID, TOKEN_PROGRAM_ID}, state::ReceiveAmount, test_utils::*};
+
+    const ESCROW_RENT: u64 = 2_000_000;
+
+    /// Maker 1 offers 10 of mint 3 for 20 of mint 4, maker 2 offers 20 of mint 4
+    /// for 10 of mint 3, both escrows editable by `edit`. In `MatchAccounts` order
+    fn match_accounts(edit: impl Fn(&mut Escrow)) -> Vec<TestAccount> {
+        let (maker_1, maker_2, mint_a, mint_b) = (address(1), address(2), address(3), address(4));
+        let escrow = |maker: &Address, seed: u64, mint_a: &Address, mint_b: &Address, receive: u64| {
+            let (escrow, bump) = escrow_address(maker, seed);
+            let data = escrow_data(|state| {
+                state.set_inner(seed, maker.clone(), mint_a.clone(), mint_b.clone(), ReceiveAmount(receive), 0, 0, [bump]);
+                edit(state);
+            });
+            TestAccount::new(escrow, crate::ID, ESCROW_RENT, &data).writable()
+        };
+        let escrow_1 = escrow(&maker_1, 7, &mint_a, &mint_b, 20);
+        let escrow_2 = escrow(&maker_2, 8, &mint_b, &mint_a, 10);
+        let (escrow_1_address, escrow_2_address) = (escrow_1.view().address().clone(), escrow_2.view().address().clone());
+        vec![
+            TestAccount::wallet(maker_1.clone()).writable(),
+            escrow_1,
+            vault(&escrow_1_address, &mint_a, 10),
+            token_account(address(6), &mint_b, &maker_1, 0),
+            TestAccount::wallet(maker_2.clone()).writable(),
+            escrow_2,
+            vault(&escrow_2_address, &mint_b, 20),
+            token_account(address(8), &mint_a, &maker_2, 0),
+            mint(mint_a),
+            mint(mint_b),
+            TestAccount::program(TOKEN_PROGRAM_ID),
+            config(),
+        ]
+    }
+
+    #[test]
+    fn settles_complementary_escrows() {
+        let accounts = match_accounts(|_| {});
+        let views: Vec<AccountView> = accounts.iter().map(TestAccount::view).collect();
+        let (maker_1_lamports, maker_2_lamports) = (views[0].lamports(), views[4].lamports());
+
+        Match::try_from(&views[..]).unwrap().process().unwrap();
+
+        // Both escrows and their vaults are closed, their rent back with their makers
+        for escrow in [&views[1], &views[2], &views[5], &views[6]] {
+            assert!(escrow.owned_by(&SYSTEM_PROGRAM_ID));
+            assert_eq!(escrow.lamports(), 0);
+        }
+        assert_eq!(views[0].lamports(), maker_1_lamports + ESCROW_RENT + 1);
+        assert_eq!(views[4].lamports(), maker_2_lamports + ESCROW_RENT + 1);
+        // Each maker got what they asked for
+        assert_eq!(TokenAccount::from_account_view(&views[3]).unwrap().amount(), 20);
+        assert_eq!(TokenAccount::from_account_view(&views[7]).unwrap().amount(), 10);
+    }
+
+    #[test]
+    fn rejects_persistent_escrows() {
+        let accounts = match_accounts(|escrow| escrow.set_flags(Escrow::FLAG_PERSISTENT));
+        let views: Vec<AccountView> = accounts.iter().map(TestAccount::view).collect();
+        assert_eq!(
+            Match::try_from(&views[..]).unwrap().process(),
+            Err(EscrowError::IncompatibleEscrows.into()),
+        );
+        assert!(views[1].owned_by(&crate::ID));
+    }
+}
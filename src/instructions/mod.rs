@@ -6,3 +6,57 @@ pub use take::*;
 
 pub mod refund;
 pub use refund::*;
+
+pub mod extend_deadline;
+pub use extend_deadline::*;
+
+pub mod refund_expired;
+pub use refund_expired::*;
+
+pub mod set_paused;
+pub use set_paused::*;
+
+pub mod get_escrow;
+pub use get_escrow::*;
+
+pub mod match_escrows;
+pub use match_escrows::*;
+
+pub mod propose;
+pub use propose::*;
+
+pub mod accept;
+pub use accept::*;
+
+pub mod cancel_proposal;
+pub use cancel_proposal::*;
+
+pub mod sweep_treasury;
+pub use sweep_treasury::*;
+
+pub mod permit;
+pub use permit::*;
+
+pub mod set_mint_b;
+pub use set_mint_b::*;
+
+pub mod verify_vault;
+pub use verify_vault::*;
+
+pub mod crank;
+pub use crank::*;
+
+pub mod rebalance;
+pub use rebalance::*;
+
+pub mod make_many;
+pub use make_many::*;
+
+pub mod deposit;
+pub use deposit::*;
+
+pub mod init_config;
+pub use init_config::*;
+
+pub mod init_permit_nonce;
+pub use init_permit_nonce::*;
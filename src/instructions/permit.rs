@@ -0,0 +1,334 @@
+use pinocchio::{AccountView, Address, ProgramResult, cpi::{Seed, Signer}, error::ProgramError, sysvars::instructions::Instructions};
+use crate::{error::EscrowError, state::PermitNonce};
+use crate::cpi;
+
+/// Ed25519 signature verification program `Ed25519SigVerify111111111111111111111111111`
+pub const ED25519_PROGRAM_ID: Address = Address::new_from_array([
+    0x03, 0x7d, 0x46, 0xd6, 0x7c, 0x93, 0xfb, 0xbe,
+    0x12, 0xf9, 0x42, 0x8f, 0x83, 0x8d, 0x40, 0xff,
+    0x05, 0x70, 0x74, 0x49, 0x27, 0xf4, 0x8a, 0x64,
+    0xfc, 0xca, 0x70, 0x44, 0x80, 0x00, 0x00, 0x00,
+]);
+
+/// Prefix of every permit message, so a signature over anything else can't pass as a permit
+pub const TAKE_PERMIT_DOMAIN: &[u8] = b"escrow-take-permit";
+
+/// Length of a permit message: domain, escrow, mint_a, mint_b, fill, max_receive,
+/// valid_until and nonce
+pub const TAKE_PERMIT_MESSAGE_LEN: usize = TAKE_PERMIT_DOMAIN.len()
+    + core::mem::size_of::<Address>() * 3
+    + core::mem::size_of::<u64>() * 2
+    + core::mem::size_of::<i64>()
+    + core::mem::size_of::<u64>();
+
+/// Size of the ed25519 instruction header (signature count and padding)
+const ED25519_HEADER_LEN: usize = 2;
+/// Size of one ed25519 signature offsets entry: seven `u16`s
+const ED25519_OFFSETS_LEN: usize = 14;
+/// Instruction index the ed25519 program reads as "this instruction"
+const ED25519_CURRENT_INSTRUCTION: u16 = u16::MAX;
+
+/// A taker's off-chain authorization of one specific take, verified on-chain
+/// through an ed25519 instruction placed right before the Take.
+///
+/// Message (little endian): `TAKE_PERMIT_DOMAIN`, escrow address, mint_a, mint_b,
+/// fill amount of mint_a, most mint_b the taker pays, the unix timestamp it is valid
+/// until, and the taker's permit nonce. The nonce must be the one stored in the
+/// taker's `PermitNonce` PDA, which the take advances, so a permit is used once.
+pub struct TakePermit {
+    pub escrow: Address,
+    pub mint_a: Address,
+    pub mint_b: Address,
+    pub fill: u64,
+    pub max_receive: u64,
+    pub valid_until: i64,
+    pub nonce: u64,
+}
+
+impl TakePermit {
+    /// Reads the permit `taker` signed from the ed25519 instruction preceding the current one
+    ///
+    /// The signature, public key and message must all live in that ed25519 instruction,
+    /// which the runtime has already verified by the time this instruction runs.
+    pub fn load(instructions_sysvar: &AccountView, taker: &Address) -> Result<Self, ProgramError> {
+        let instructions = Instructions::try_from(instructions_sysvar)?;
+        let instruction = instructions.get_instruction_relative(-1)?;
+        if instruction.get_program_id() != &ED25519_PROGRAM_ID {
+            return Err(EscrowError::InvalidPermit.into());
+        }
+        let data = instruction.get_instruction_data();
+
+        // Exactly one signature, whose offsets all point into this instruction
+        let (Some(&1), Some(offsets)) = (data.first(), data.get(ED25519_HEADER_LEN..ED25519_HEADER_LEN + ED25519_OFFSETS_LEN)) else {
+            return Err(EscrowError::InvalidPermit.into());
+        };
+        // Offsets entry: signature offset and instruction, public key offset and
+        // instruction, message offset, size and instruction
+        let offset = |index: usize| u16::from_le_bytes([offsets[index * 2], offsets[index * 2 + 1]]);
+        if offset(1) != ED25519_CURRENT_INSTRUCTION
+            || offset(3) != ED25519_CURRENT_INSTRUCTION
+            || offset(6) != ED25519_CURRENT_INSTRUCTION
+        {
+            return Err(EscrowError::InvalidPermit.into());
+        }
+        let public_key_offset = offset(2) as usize;
+        let message_offset = offset(4) as usize;
+        let message_len = offset(5) as usize;
+
+        // Signed by the taker, over a permit message
+        if data.get(public_key_offset..public_key_offset + 32) != Some(taker.as_ref()) {
+            return Err(EscrowError::InvalidPermit.into());
+        }
+        let Some(message) = data.get(message_offset..message_offset + message_len) else {
+            return Err(EscrowError::InvalidPermit.into());
+        };
+        let Some(message) = message.strip_prefix(TAKE_PERMIT_DOMAIN) else {
+            return Err(EscrowError::InvalidPermit.into());
+        };
+        if message_len != TAKE_PERMIT_MESSAGE_LEN {
+            return Err(EscrowError::InvalidPermit.into());
+        }
+
+        let address = |offset: usize| -> Result<Address, ProgramError> {
+            let bytes: [u8; 32] = message[offset..offset + 32].try_into().map_err(|_| ProgramError::InvalidInstructionData)?;
+            Ok(Address::new_from_array(bytes))
+        };
+        let number = |offset: usize| -> Result<[u8; 8], ProgramError> {
+            message[offset..offset + 8].try_into().map_err(|_| ProgramError::InvalidInstructionData)
+        };
+        Ok(Self {
+            escrow: address(0)?,
+            mint_a: address(32)?,
+            mint_b: address(64)?,
+            fill: u64::from_le_bytes(number(96)?),
+            max_receive: u64::from_le_bytes(number(104)?),
+            valid_until: i64::from_le_bytes(number(112)?),
+            nonce: u64::from_le_bytes(number(120)?),
+        })
+    }
+
+    /// Validates that the permit covers this take of `escrow`, trading `mint_a` for `mint_b`, at `now`
+    pub fn check(&self, escrow: &Address, mint_a: &Address, mint_b: &Address, fill: u64, receive: u64, now: i64) -> Result<(), ProgramError> {
+        if self.escrow != *escrow
+            || self.mint_a != *mint_a
+            || self.mint_b != *mint_b
+            || self.fill != fill
+            || receive > self.max_receive
+            || now > self.valid_until
+        {
+            return Err(EscrowError::InvalidPermit.into());
+        }
+        Ok(())
+    }
+
+    /// Uses up the permit's nonce: it must be the taker's current one, which advances by one
+    pub fn consume_nonce(&self, permit_nonce: &AccountView) -> ProgramResult {
+        let mut data = permit_nonce.try_borrow_mut()?;
+        let state = PermitNonce::load_mut(&mut data)?;
+        if state.nonce != self.nonce {
+            return Err(EscrowError::PermitNonceMismatch.into());
+        }
+        state.set_nonce(self.nonce.checked_add(1).ok_or(ProgramError::ArithmeticOverflow)?);
+        Ok(())
+    }
+}
+
+/// Validator for a taker's permit nonce PDA
+pub struct PermitNonceAccount;
+
+impl PermitNonceAccount {
+    /// Validates that the account is the program-owned permit nonce PDA of `taker`, writable
+    pub fn check(account: &AccountView, taker: &Address) -> Result<(), ProgramError> {
+        if account.address() != &PermitNonce::address(taker).0 {
+            return Err(ProgramError::InvalidSeeds);
+        }
+        if !account.owned_by(&crate::ID) {
+            return Err(ProgramError::InvalidAccountOwner);
+        }
+        if !account.is_writable() {
+            return Err(ProgramError::Immutable);
+        }
+        Ok(())
+    }
+
+    /// Creates the permit nonce PDA of `taker`, starting at nonce 0, paid by the taker
+    pub fn init(taker: &AccountView, account: &AccountView) -> ProgramResult {
+        let (nonce_address, bump) = PermitNonce::address(taker.address());
+        if account.address() != &nonce_address {
+            return Err(ProgramError::InvalidSeeds);
+        }
+        if !account.is_data_empty() {
+            return Err(ProgramError::AccountAlreadyInitialized);
+        }
+
+        let bump_binding = [bump];
+        let seeds = [
+            Seed::from(PermitNonce::SEED),
+            Seed::from(taker.address().as_ref()),
+            Seed::from(&bump_binding),
+        ];
+        cpi::create_account(
+            taker,
+            account,
+            PermitNonce::LEN as u64,
+            &crate::ID,
+            &[Signer::from(&seeds)],
+        )?;
+
+        let mut data = account.try_borrow_mut()?;
+        let state = PermitNonce::load_mut(&mut data)?;
+        state.set_taker(taker.address().clone());
+        state.set_nonce(0);
+        state.set_bump(bump_binding);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pinocchio::sysvars::instructions::INSTRUCTIONS_ID;
+    use crate::test_utils::*;
+
+    /// Offsets of the public key, signature and message in `ed25519_data`
+    const PUBLIC_KEY_OFFSET: u16 = (ED25519_HEADER_LEN + ED25519_OFFSETS_LEN) as u16;
+    const SIGNATURE_OFFSET: u16 = PUBLIC_KEY_OFFSET + 32;
+    const MESSAGE_OFFSET: u16 = SIGNATURE_OFFSET + 64;
+
+    /// Taker 2's permit to fill 10 of escrow 1 (mint_a 3, mint_b 4) for at most 20
+    fn permit(nonce: u64) -> TakePermit {
+        TakePermit {
+            escrow: address(1),
+            mint_a: address(3),
+            mint_b: address(4),
+            fill: 10,
+            max_receive: 20,
+            valid_until: now() + 60,
+            nonce,
+        }
+    }
+
+    fn message(permit: &TakePermit) -> Vec<u8> {
+        let mut message = TAKE_PERMIT_DOMAIN.to_vec();
+        message.extend_from_slice(permit.escrow.as_ref());
+        message.extend_from_slice(permit.mint_a.as_ref());
+        message.extend_from_slice(permit.mint_b.as_ref());
+        message.extend_from_slice(&permit.fill.to_le_bytes());
+        message.extend_from_slice(&permit.max_receive.to_le_bytes());
+        message.extend_from_slice(&permit.valid_until.to_le_bytes());
+        message.extend_from_slice(&permit.nonce.to_le_bytes());
+        message
+    }
+
+    /// Data of an ed25519 instruction verifying `signer`'s signature over `message`,
+    /// everything inline. The signature itself is left zeroed: the runtime checks it
+    fn ed25519_data(signer: &Address, message: &[u8]) -> Vec<u8> {
+        let mut data = vec![1, 0];
+        for offset in [
+            SIGNATURE_OFFSET,
+            ED25519_CURRENT_INSTRUCTION,
+            PUBLIC_KEY_OFFSET,
+            ED25519_CURRENT_INSTRUCTION,
+            MESSAGE_OFFSET,
+            message.len() as u16,
+            ED25519_CURRENT_INSTRUCTION,
+        ] {
+            data.extend_from_slice(&offset.to_le_bytes());
+        }
+        data.extend_from_slice(signer.as_ref());
+        data.extend_from_slice(&[0; 64]);
+        data.extend_from_slice(message);
+        data
+    }
+
+    /// Instructions sysvar of a transaction running `program_id` with `data`, then
+    /// the current instruction (an account-less call of this program)
+    fn instructions_sysvar(program_id: &Address, data: &[u8]) -> TestAccount {
+        let instruction = |program_id: &Address, data: &[u8]| {
+            let mut instruction = 0u16.to_le_bytes().to_vec();
+            instruction.extend_from_slice(program_id.as_ref());
+            instruction.extend_from_slice(&(data.len() as u16).to_le_bytes());
+            instruction.extend_from_slice(data);
+            instruction
+        };
+        let (previous, current) = (instruction(program_id, data), instruction(&crate::ID, &[]));
+
+        // Instruction count, then the offset of each instruction
+        let header_len = 2 + 2 * 2;
+        let mut sysvar = 2u16.to_le_bytes().to_vec();
+        sysvar.extend_from_slice(&(header_len as u16).to_le_bytes());
+        sysvar.extend_from_slice(&((header_len + previous.len()) as u16).to_le_bytes());
+        sysvar.extend_from_slice(&previous);
+        sysvar.extend_from_slice(&current);
+        // Index of the current instruction
+        sysvar.extend_from_slice(&1u16.to_le_bytes());
+        TestAccount::new(INSTRUCTIONS_ID, Address::default(), 1, &sysvar)
+    }
+
+    /// Taker 2's permit nonce PDA, at `nonce`
+    fn permit_nonce(nonce: u64) -> TestAccount {
+        let (nonce_address, bump) = PermitNonce::address(&address(2));
+        let mut data = [0; PermitNonce::LEN];
+        data[..32].copy_from_slice(address(2).as_ref());
+        data[32..40].copy_from_slice(&nonce.to_le_bytes());
+        data[40] = bump;
+        TestAccount::new(nonce_address, crate::ID, 1, &data).writable()
+    }
+
+    #[test]
+    fn loads_and_checks_a_signed_permit() {
+        let sysvar = instructions_sysvar(&ED25519_PROGRAM_ID, &ed25519_data(&address(2), &message(&permit(0))));
+        let loaded = TakePermit::load(&sysvar.view(), &address(2)).unwrap();
+        assert_eq!((loaded.escrow.clone(), loaded.mint_a.clone(), loaded.mint_b.clone()), (address(1), address(3), address(4)));
+        assert_eq!((loaded.fill, loaded.max_receive, loaded.valid_until, loaded.nonce), (10, 20, now() + 60, 0));
+        assert!(loaded.check(&address(1), &address(3), &address(4), 10, 20, now()).is_ok());
+    }
+
+    #[test]
+    fn rejects_a_permit_signed_by_another_key() {
+        let sysvar = instructions_sysvar(&ED25519_PROGRAM_ID, &ed25519_data(&address(9), &message(&permit(0))));
+        assert_eq!(TakePermit::load(&sysvar.view(), &address(2)).err(), Some(EscrowError::InvalidPermit.into()));
+    }
+
+    #[test]
+    fn rejects_a_permit_for_another_escrow_or_mint() {
+        let permit = permit(0);
+        for (escrow, mint_a, mint_b) in [
+            (address(9), address(3), address(4)),
+            (address(1), address(9), address(4)),
+            (address(1), address(3), address(9)),
+        ] {
+            assert_eq!(permit.check(&escrow, &mint_a, &mint_b, 10, 20, now()).err(), Some(EscrowError::InvalidPermit.into()));
+        }
+    }
+
+    #[test]
+    fn rejects_a_permit_not_verified_by_the_ed25519_program() {
+        let sysvar = instructions_sysvar(&address(9), &ed25519_data(&address(2), &message(&permit(0))));
+        assert_eq!(TakePermit::load(&sysvar.view(), &address(2)).err(), Some(EscrowError::InvalidPermit.into()));
+    }
+
+    #[test]
+    fn a_permit_is_used_once() {
+        let account = permit_nonce(0);
+        let view = account.view();
+        permit(0).consume_nonce(&view).unwrap();
+        assert_eq!(PermitNonce::load(&view.try_borrow().unwrap()).unwrap().nonce, 1);
+
+        // Replaying it, or a permit signed ahead of the current nonce, fails
+        assert_eq!(permit(0).consume_nonce(&view).err(), Some(EscrowError::PermitNonceMismatch.into()));
+        assert_eq!(permit(2).consume_nonce(&view).err(), Some(EscrowError::PermitNonceMismatch.into()));
+        assert!(permit(1).consume_nonce(&view).is_ok());
+    }
+
+    #[test]
+    fn permit_nonce_account_must_be_the_takers_pda() {
+        let account = permit_nonce(0);
+        assert!(PermitNonceAccount::check(&account.view(), &address(2)).is_ok());
+        assert_eq!(PermitNonceAccount::check(&account.view(), &address(9)).err(), Some(ProgramError::InvalidSeeds));
+
+        let (nonce_address, _) = PermitNonce::address(&address(2));
+        let foreign = TestAccount::new(nonce_address, address(9), 1, &[0; PermitNonce::LEN]).writable();
+        assert_eq!(PermitNonceAccount::check(&foreign.view(), &address(2)).err(), Some(ProgramError::InvalidAccountOwner));
+    }
+}
@@ -0,0 +1,220 @@
+use pinocchio::{AccountView, Address, ProgramResult, cpi::{Seed,Signer}, error::ProgramError};
+use super::make::{SignerAccount,MintInterface,AssociatedTokenAccount,TokenProgram,ConfigAccount};
+use crate::state::Proposal;
+use crate::cpi;
+
+/// Records negotiated terms for a designated taker, without moving any tokens.
+///
+/// Instead of a deposit, the proposal PDA is approved as delegate of `maker_ata_a`
+/// for `amount`, which `Accept` draws on. Approving replaces any earlier delegate of
+/// that account, and the maker can revoke it through the token program to back out.
+///
+/// # Accounts
+/// - `maker`: Maker's wallet account (signer)
+/// - `proposal`: Proposal account to be created
+/// - `mint_a`: Mint of the token offered
+/// - `mint_b`: Mint of the token asked for
+/// - `maker_ata_a`: Maker's token account for mint_a
+/// - `system_program`: System program
+/// - `token_program`: Token program
+/// - `config`: Program config PDA (may be uninitialized)
+pub struct Propose<'a> {
+    pub accounts: ProposeAccounts<'a>,
+    pub instruction_data: ProposeInstructionData,
+    pub bump: u8,
+}
+
+impl<'a> TryFrom<(&'a [AccountView], &'a [u8])> for Propose<'a> {
+    type Error = ProgramError;
+    fn try_from((accounts, data): (&'a [AccountView], &'a [u8])) -> Result<Self, Self::Error> {
+        let accounts = ProposeAccounts::try_from(accounts)?;
+        let instruction_data = ProposeInstructionData::try_from(data)?;
+
+        let (proposal_address, bump) = Address::find_program_address(&[
+            Proposal::SEED,
+            accounts.maker.address().as_ref(),
+            &instruction_data.seed.to_le_bytes(),
+        ], &crate::ID);
+        if accounts.proposal.address() != &proposal_address {
+            return Err(ProgramError::InvalidSeeds);
+        }
+
+        Ok(Self { accounts, instruction_data, bump })
+    }
+}
+
+impl<'a> Propose<'a> {
+    pub const DISCRIMINATOR: &'a u8 = &crate::constants::PROPOSE_DISCRIMINATOR;
+
+    /// 1. create the proposal account
+    /// 2. record the terms and the designated taker
+    /// 3. approve the proposal as delegate of maker_ata_a for `amount`
+    pub fn process(&self) -> ProgramResult {
+        let seed_binding = self.instruction_data.seed.to_le_bytes();
+        let bump_binding = [self.bump];
+        let seed = [
+            Seed::from(Proposal::SEED),
+            Seed::from(self.accounts.maker.address().as_ref()),
+            Seed::from(&seed_binding),
+            Seed::from(&bump_binding),
+        ];
+        cpi::create_account(
+            self.accounts.maker,
+            self.accounts.proposal,
+            Proposal::LEN as u64,
+            &crate::ID,
+            &[Signer::from(&seed)],
+        )?;
+
+        let mut data = self.accounts.proposal.try_borrow_mut()?;
+        let proposal = Proposal::load_mut(&mut data)?;
+        proposal.seed = self.instruction_data.seed;
+        proposal.maker = self.accounts.maker.address().clone();
+        proposal.taker = self.instruction_data.taker.clone();
+        proposal.mint_a = self.accounts.mint_a.address().clone();
+        proposal.mint_b = self.accounts.mint_b.address().clone();
+        proposal.amount = self.instruction_data.amount;
+        proposal.receive = self.instruction_data.receive;
+        proposal.bump = [self.bump];
+        drop(data);
+
+        cpi::approve(
+            self.accounts.token_program,
+            self.accounts.maker_ata_a,
+            self.accounts.proposal,
+            self.accounts.maker,
+            self.instruction_data.amount,
+        )
+    }
+}
+
+pub struct ProposeAccounts<'a> {
+    pub maker: &'a AccountView,
+    pub proposal: &'a AccountView,
+    pub mint_a: &'a AccountView,
+    pub mint_b: &'a AccountView,
+    pub maker_ata_a: &'a AccountView,
+    pub system_program: &'a AccountView,
+    pub token_program: &'a AccountView,
+    pub config: &'a AccountView,
+}
+
+impl<'a> TryFrom<&'a [AccountView]> for ProposeAccounts<'a> {
+    type Error = ProgramError;
+    fn try_from(accounts: &'a [AccountView]) -> Result<Self, Self::Error> {
+        let [maker, proposal, mint_a, mint_b, maker_ata_a, system_program, token_program, config] = accounts else {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        };
+
+        ConfigAccount::check_not_paused(config)?;
+        SignerAccount::check(maker)?;
+        TokenProgram::check(token_program)?;
+        // Accept settles like a take, so the pair must qualify for an escrow
+        MintInterface::check_pair(mint_a, mint_b, token_program)?;
+        AssociatedTokenAccount::check(maker_ata_a, maker, mint_a, token_program)?;
+
+        Ok(Self { maker, proposal, mint_a, mint_b, maker_ata_a, system_program, token_program, config })
+    }
+}
+
+/// Length of the Propose instruction data
+pub const PROPOSE_DATA_LEN: usize = core::mem::size_of::<u64>() * 3 + core::mem::size_of::<Address>();
+
+pub struct ProposeInstructionData {
+    /// Seed of the proposal PDA
+    pub seed: u64,
+    /// Amount of mint_a offered
+    pub amount: u64,
+    /// Amount of mint_b asked for
+    pub receive: u64,
+    /// The only taker allowed to accept
+    pub taker: Address,
+}
+
+impl<'a> TryFrom<&'a [u8]> for ProposeInstructionData {
+    type Error = ProgramError;
+
+    /// Wire format (little endian): seed (u64), amount (u64), receive (u64), taker (32 bytes)
+    fn try_from(data: &'a [u8]) -> Result<Self, Self::Error> {
+        if data.len() != PROPOSE_DATA_LEN {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        let seed = u64::from_le_bytes(data[0..8].try_into().map_err(|_| ProgramError::InvalidInstructionData)?);
+        let amount = u64::from_le_bytes(data[8..16].try_into().map_err(|_| ProgramError::InvalidInstructionData)?);
+        let receive = u64::from_le_bytes(data[16..24].try_into().map_err(|_| ProgramError::InvalidInstructionData)?);
+        let taker: [u8; 32] = data[24..56].try_into().map_err(|_| ProgramError::InvalidInstructionData)?;
+        let taker = Address::new_from_array(taker);
+
+        // Nothing to swap, or nobody to swap with
+        if amount == 0 || taker == Address::default() {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+
+        Ok(Self { seed, amount, receive, taker })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pinocchio_token::state::TokenAccount;
+    use crate::{constants::{SYSTEM_PROGRAM_ID, TOKEN_PROGRAM_ID}, test_utils::*};
+
+    /// Maker 1 proposes 10 of mint_a for 20 of `mint_b` to taker 2, in
+    /// `ProposeAccounts` order
+    fn propose_accounts(mint_b: TestAccount) -> Vec<TestAccount> {
+        let maker = address(1);
+        let (proposal, _) = Address::find_program_address(&[Proposal::SEED, maker.as_ref(), &7u64.to_le_bytes()], &crate::ID);
+        vec![
+            TestAccount::wallet(maker.clone()).signer().writable(),
+            TestAccount::new(proposal, SYSTEM_PROGRAM_ID, 0, &[]).writable(),
+            mint(address(3)),
+            mint_b,
+            token_account(address(5), &address(3), &maker, 10),
+            TestAccount::program(SYSTEM_PROGRAM_ID),
+            TestAccount::program(TOKEN_PROGRAM_ID),
+            config(),
+        ]
+    }
+
+    fn propose_data() -> Vec<u8> {
+        [&7u64.to_le_bytes()[..], &10u64.to_le_bytes(), &20u64.to_le_bytes(), address(2).as_ref()].concat()
+    }
+
+    #[test]
+    fn accepts_a_proposal_for_the_designated_taker() {
+        let accounts = propose_accounts(mint(address(4)));
+        let views: Vec<AccountView> = accounts.iter().map(TestAccount::view).collect();
+        let data = propose_data();
+        let propose = Propose::try_from((&views[..], &data[..])).unwrap();
+        assert_eq!(propose.instruction_data.taker, address(2));
+        assert_eq!((propose.instruction_data.amount, propose.instruction_data.receive), (10, 20));
+    }
+
+    #[test]
+    fn records_the_terms_and_approves_the_proposal() {
+        let accounts = propose_accounts(mint(address(4)));
+        let views: Vec<AccountView> = accounts.iter().map(TestAccount::view).collect();
+        let data = propose_data();
+        Propose::try_from((&views[..], &data[..])).unwrap().process().unwrap();
+
+        let proposal_data = views[1].try_borrow().unwrap();
+        let proposal = Proposal::load(&proposal_data).unwrap();
+        assert_eq!((proposal.maker.clone(), proposal.taker.clone()), (address(1), address(2)));
+        assert_eq!((proposal.mint_a.clone(), proposal.mint_b.clone()), (address(3), address(4)));
+        assert_eq!((proposal.amount, proposal.receive), (10, 20));
+
+        // No tokens moved, the proposal may draw them on acceptance
+        let maker_ata_a = TokenAccount::from_account_view(&views[4]).unwrap();
+        assert_eq!(maker_ata_a.amount(), 10);
+        assert_eq!(maker_ata_a.delegate(), Some(views[1].address()));
+        assert_eq!(maker_ata_a.delegated_amount(), 10);
+    }
+
+    #[test]
+    fn rejects_the_same_mint_on_both_sides() {
+        let accounts = propose_accounts(mint(address(3)));
+        let views: Vec<AccountView> = accounts.iter().map(TestAccount::view).collect();
+        assert_eq!(ProposeAccounts::try_from(&views[..]).err(), Some(ProgramError::InvalidArgument));
+    }
+}
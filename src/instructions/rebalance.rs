@@ -0,0 +1,217 @@
+use pinocchio::{AccountView, ProgramResult, cpi::{Seed,Signer}, error::ProgramError};
+use super::make::{assert_distinct,SignerAccount,AssociatedTokenAccount,ProgramAccount,TokenProgram};
+use crate::{error::EscrowError, state::{DepositAmount, Escrow, ReceiveAmount}};
+use crate::cpi;
+
+/// Resizes an escrow's deposit and price in one go.
+///
+/// The vault is topped up from, or partly refunded to, `maker_ata_a` until it
+/// holds the new `amount`, and `receive` is replaced. Like `SetMintB` this is
+/// only possible before a partial fill seals the terms, and not for oracle-priced
+/// escrows, whose oracle quotes the price instead of `receive`. Keypair escrows
+/// are rejected: their vault authority would have to sign the refund.
+///
+/// # Accounts
+/// - `maker`: Maker's wallet account (signer)
+/// - `escrow`: Escrow account to update
+/// - `mint_a`: Mint of the deposited token
+/// - `vault`: Vault token account holding the deposit
+/// - `maker_ata_a`: Maker's token account the difference moves from or to
+/// - `token_program`: Token program
+pub struct Rebalance<'a> {
+    pub accounts: RebalanceAccounts<'a>,
+    pub instruction_data: RebalanceInstructionData,
+}
+
+impl<'a> TryFrom<(&'a [AccountView], &'a [u8])> for Rebalance<'a> {
+    type Error = ProgramError;
+    fn try_from((accounts, data): (&'a [AccountView], &'a [u8])) -> Result<Self, Self::Error> {
+        Ok(Self {
+            accounts: RebalanceAccounts::try_from(accounts)?,
+            instruction_data: RebalanceInstructionData::try_from(data)?,
+        })
+    }
+}
+
+impl<'a> Rebalance<'a> {
+    pub const DISCRIMINATOR: &'a u8 = &crate::constants::REBALANCE_DISCRIMINATOR;
+
+    /// 1. escrow belongs to the maker, isn't sealed by a partial fill nor oracle-priced
+    /// 2. maker_ata_a:mint_a -> vault, or vault:mint_a -> maker_ata_a, by the difference
+    /// 3. store the new receive
+    pub fn process(&self) -> ProgramResult {
+        let escrow = Escrow::load_owned(&self.accounts.escrow.try_borrow()?)?;
+        if escrow.maker != *self.accounts.maker.address() {
+            return Err(ProgramError::IncorrectAuthority);
+        }
+        if escrow.is_sealed() {
+            return Err(EscrowError::PartiallyFilled.into());
+        }
+        if escrow.uses_oracle() {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if escrow.mint_a != *self.accounts.mint_a.address() {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let amount = self.instruction_data.amount.get();
+        if escrow.is_nft() && amount != 1 {
+            return Err(EscrowError::NotAnNft.into());
+        }
+
+        let vault_amount = escrow.vault_amount(self.accounts.escrow, self.accounts.vault)?;
+        if amount > vault_amount {
+            // maker_ata_a:mint_a -> vault
+            cpi::transfer(
+                self.accounts.token_program,
+                self.accounts.maker_ata_a,
+                self.accounts.vault,
+                self.accounts.maker,
+                amount - vault_amount,
+                &[],
+            )?;
+        } else if amount < vault_amount {
+            let seed_binding = escrow.seed.to_le_bytes();
+            let seed = [
+                Seed::from(Escrow::SEED),
+                Seed::from(self.accounts.maker.address().as_ref()),
+                Seed::from(&seed_binding),
+                Seed::from(&escrow.bump),
+            ];
+
+            // vault:mint_a -> maker_ata_a
+            cpi::transfer(
+                self.accounts.token_program,
+                self.accounts.vault,
+                self.accounts.maker_ata_a,
+                self.accounts.escrow,
+                vault_amount - amount,
+                &[Signer::from(&seed)],
+            )?;
+        }
+
+        let mut data = self.accounts.escrow.try_borrow_mut()?;
+        Escrow::load_mut(&mut data)?.set_receive(self.instruction_data.receive);
+        Ok(())
+    }
+}
+
+pub struct RebalanceAccounts<'a> {
+    pub maker: &'a AccountView,
+    pub escrow: &'a AccountView,
+    pub mint_a: &'a AccountView,
+    pub vault: &'a AccountView,
+    pub maker_ata_a: &'a AccountView,
+    pub token_program: &'a AccountView,
+}
+
+impl<'a> TryFrom<&'a [AccountView]> for RebalanceAccounts<'a> {
+    type Error = ProgramError;
+    fn try_from(accounts: &'a [AccountView]) -> Result<Self, Self::Error> {
+        let [maker, escrow, mint_a, vault, maker_ata_a, token_program] = accounts else {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        };
+
+        SignerAccount::check(maker)?;
+        ProgramAccount::check(escrow)?;
+        // Checked ahead of the vault, which a keypair escrow's vault authority owns
+        if Escrow::load(&escrow.try_borrow()?)?.is_keypair() {
+            return Err(EscrowError::KeypairEscrowNotSupported.into());
+        }
+        TokenProgram::check(token_program)?;
+        assert_distinct(&[escrow, vault, maker_ata_a])?;
+
+        AssociatedTokenAccount::check(vault, escrow, mint_a, token_program)?;
+        AssociatedTokenAccount::check(maker_ata_a, maker, mint_a, token_program)?;
+
+        Ok(Self { maker, escrow, mint_a, vault, maker_ata_a, token_program })
+    }
+}
+
+pub struct RebalanceInstructionData {
+    /// Amount of mint_a the vault holds afterwards
+    pub amount: DepositAmount,
+    /// New amount of mint_b to receive
+    pub receive: ReceiveAmount,
+}
+
+impl<'a> TryFrom<&'a [u8]> for RebalanceInstructionData {
+    type Error = ProgramError;
+
+    /// Wire format (little endian): `[0..8]` amount (u64), `[8..16]` receive (u64)
+    fn try_from(data: &'a [u8]) -> Result<Self, Self::Error> {
+        if data.len() != 16 {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        let amount = DepositAmount(u64::from_le_bytes(data[0..8].try_into().map_err(|_| ProgramError::InvalidInstructionData)?));
+        let receive = ReceiveAmount(u64::from_le_bytes(data[8..16].try_into().map_err(|_| ProgramError::InvalidInstructionData)?));
+
+        // An empty vault or a free deposit would leave a broken escrow behind
+        if amount.get() == 0 || receive.get() == 0 {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+
+        Ok(Self { amount, receive })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{constants::TOKEN_PROGRAM_ID, test_utils::*};
+
+    /// Rebalances maker 1's escrow of the 10 mint_a (3) in its vault for 20 mint_b,
+    /// edited by `edit`, to `amount` for `receive`. Returns the stored receive
+    fn rebalance(amount: u64, receive: u64, edit: impl FnOnce(&mut Escrow)) -> Result<ReceiveAmount, ProgramError> {
+        let (maker, mint_a) = (address(1), address(3));
+        let (escrow, bump) = escrow_address(&maker, 7);
+        let data = escrow_data(|state| {
+            state.set_inner(7, maker.clone(), mint_a.clone(), address(4), ReceiveAmount(20), 0, 0, [bump]);
+            edit(state);
+        });
+        let accounts = [
+            TestAccount::wallet(maker.clone()).signer(),
+            TestAccount::new(escrow.clone(), crate::ID, 1, &data).writable(),
+            mint(mint_a.clone()),
+            vault(&escrow, &mint_a, 10),
+            token_account(address(6), &mint_a, &maker, 100),
+            TestAccount::program(TOKEN_PROGRAM_ID),
+        ];
+        let views: Vec<AccountView> = accounts.iter().map(TestAccount::view).collect();
+        let data = [amount.to_le_bytes(), receive.to_le_bytes()].concat();
+
+        Rebalance::try_from((&views[..], &data[..]))?.process()?;
+        let data = views[1].try_borrow()?;
+        Ok(Escrow::load(&data)?.receive)
+    }
+
+    #[test]
+    fn rejects_an_escrow_sealed_by_a_partial_fill() {
+        let sealed = |escrow: &mut Escrow| {
+            escrow.set_filled(4);
+            escrow.set_flags(Escrow::FLAG_PARTIAL_FILL | Escrow::FLAG_SEALED);
+        };
+        assert_eq!(rebalance(15, 30, sealed), Err(EscrowError::PartiallyFilled.into()));
+    }
+
+    #[test]
+    fn increases_the_deposit() {
+        assert_eq!(rebalance(15, 30, |_| {}), Ok(ReceiveAmount(30)));
+    }
+
+    #[test]
+    fn decreases_the_deposit() {
+        assert_eq!(rebalance(5, 10, |_| {}), Ok(ReceiveAmount(10)));
+    }
+
+    #[test]
+    fn rejects_an_oracle_priced_escrow() {
+        assert_eq!(rebalance(15, 30, |escrow| escrow.set_oracle(address(9), 1)), Err(ProgramError::InvalidAccountData));
+    }
+
+    #[test]
+    fn rejects_a_keypair_escrow() {
+        let keypair = |escrow: &mut Escrow| escrow.set_flags(Escrow::FLAG_KEYPAIR);
+        assert_eq!(rebalance(15, 30, keypair), Err(EscrowError::KeypairEscrowNotSupported.into()));
+    }
+}
@@ -0,0 +1,310 @@
+use pinocchio::{AccountView, Address, ProgramResult, cpi::{Seed,Signer}, error::ProgramError};
+use super::make::{SignerAccount,AssociatedTokenAccount,ProgramAccount,TreasuryAccount,RENT_TO_TREASURY};
+use crate::{constants::VAULT_AUTHORITY_SEED, state::Escrow};
+use crate::cpi;
+
+/// Seconds after `Escrow::created_at` during which the maker can't refund. 0 disables it.
+///
+/// Keeps a freshly made escrow takeable for a while, so makers can't flash offers
+/// at delegates and takers and cancel them right away. An expired escrow is always
+/// refundable, and escrows without a recorded `created_at` have no grace period.
+pub const REFUND_GRACE_PERIOD: i64 = 0;
+
+/// Cancels an escrow, returning the deposit to the maker.
+///
+/// # Accounts
+/// - `maker`: Maker's wallet account (signer)
+/// - `escrow`: Escrow account to close
+/// - `mint_a`: Mint of the deposited token
+/// - `vault`: Vault token account holding the deposit
+/// - `maker_ata_a`: Maker's token account receiving the deposit back
+/// - `token_program`: Token program
+/// - `treasury`: Treasury PDA receiving the rent, only with `RENT_TO_TREASURY`
+/// - `vault_authority`: Vault authority PDA, only for a keypair escrow
+/// - `destination`: Optional maker-owned token account of mint_a receiving the
+///   deposit instead of `maker_ata_a`
+pub struct Refund<'a> {
+    pub accounts: RefundAccounts<'a>,
+}
+
+impl<'a> TryFrom<&'a [AccountView]> for Refund<'a> {
+    type Error = ProgramError;
+    fn try_from(accounts: &'a [AccountView]) -> Result<Self, Self::Error> {
+        Ok(Self {
+            accounts: RefundAccounts::try_from(accounts)?,
+        })
+    }
+}
+
+impl<'a> Refund<'a> {
+    pub const DISCRIMINATOR: &'a u8 = &crate::constants::REFUND_DISCRIMINATOR;
+
+    /// 1. escrow is valid and past its grace period (see `REFUND_GRACE_PERIOD`)
+    /// 2. vault:mint_a -> maker_ata_a
+    /// 3. close vault to the rent destination
+    /// 4. close escrow to the rent destination
+    pub fn process(&self) -> ProgramResult {
+        // check escrow is valid
+        let data = self.accounts.escrow.try_borrow()?;
+        let escrow = Escrow::load(&data)?;
+        // A keypair escrow isn't derived from the maker, whose match was checked with the accounts
+        if !escrow.is_keypair() {
+            let (escrow_address, _) = Address::find_program_address(&[
+                Escrow::SEED,
+                self.accounts.maker.address().as_ref(),
+                &escrow.seed.to_le_bytes(),
+            ], &crate::ID);
+            if escrow_address != *self.accounts.escrow.address() {
+                return Err(ProgramError::InvalidAccountData);
+            }
+        }
+
+        escrow.check_grace_period(crate::unix_timestamp()?, REFUND_GRACE_PERIOD)?;
+
+        let seed_binding = escrow.seed.to_le_bytes();
+        let bump_binding = escrow.bump;
+        let seed = [
+            Seed::from(Escrow::SEED),
+            Seed::from(self.accounts.maker.address().as_ref()),
+            Seed::from(&seed_binding),
+            Seed::from(&bump_binding),
+        ];
+        let keypair_seed = [
+            Seed::from(VAULT_AUTHORITY_SEED),
+            Seed::from(self.accounts.escrow.address().as_ref()),
+            Seed::from(&bump_binding),
+        ];
+        // The vault's owner signs: the escrow PDA, or a keypair escrow's vault authority
+        let vault_owner = self.accounts.vault_owner();
+        let signer = if escrow.is_keypair() { Signer::from(&keypair_seed[..]) } else { Signer::from(&seed[..]) };
+
+        let vault_amount = escrow.vault_amount(vault_owner, self.accounts.vault)?;
+
+        // vault:mint_a -> maker_ata_a
+        cpi::transfer(
+            self.accounts.token_program,
+            self.accounts.vault,
+            self.accounts.maker_ata_a,
+            vault_owner,
+            vault_amount,
+            core::slice::from_ref(&signer),
+        )?;
+
+        cpi::close_account(
+            self.accounts.token_program,
+            self.accounts.vault,
+            self.accounts.rent_destination,
+            vault_owner,
+            &[signer],
+        )?;
+
+        drop(data);
+        ProgramAccount::close(self.accounts.escrow, self.accounts.rent_destination)
+    }
+}
+
+pub struct RefundAccounts<'a> {
+    pub maker: &'a AccountView,
+    pub escrow: &'a AccountView,
+    pub mint_a: &'a AccountView,
+    pub vault: &'a AccountView,
+    /// Maker's token account receiving the deposit: maker_ata_a, or the passed destination
+    pub maker_ata_a: &'a AccountView,
+    pub token_program: &'a AccountView,
+    /// Receives the vault and escrow rent: the maker, or the treasury with `RENT_TO_TREASURY`
+    pub rent_destination: &'a AccountView,
+    /// Vault authority PDA owning the vault, only for a keypair escrow
+    pub vault_authority: Option<&'a AccountView>,
+}
+
+impl<'a> RefundAccounts<'a> {
+    /// Owner of the vault: the escrow, or the vault authority of a keypair escrow
+    #[inline(always)]
+    pub fn vault_owner(&self) -> &'a AccountView {
+        self.vault_authority.unwrap_or(self.escrow)
+    }
+
+    /// Validates a single refund account group.
+    ///
+    /// Shared by `Refund` and the batched `RefundExpired`, which doesn't take
+    /// keypair escrows and passes no `vault_authority`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        maker: &'a AccountView,
+        escrow: &'a AccountView,
+        mint_a: &'a AccountView,
+        vault: &'a AccountView,
+        maker_ata_a: &'a AccountView,
+        token_program: &'a AccountView,
+        rent_destination: &'a AccountView,
+        vault_authority: Option<&'a AccountView>,
+    ) -> Result<Self, ProgramError> {
+        if vault.address() == escrow.address() {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        SignerAccount::check(maker)?;
+        ProgramAccount::check(escrow)?;
+
+        // Only the maker can refund, and only against the stored mint
+        {
+            let data = escrow.try_borrow()?;
+            let escrow_state = Escrow::load(&data)?;
+            if escrow_state.maker != *maker.address() {
+                return Err(ProgramError::IncorrectAuthority);
+            }
+            if escrow_state.mint_a != *mint_a.address() {
+                return Err(ProgramError::InvalidAccountData);
+            }
+            // A keypair escrow's vault belongs to its vault authority, passed along
+            match vault_authority {
+                Some(vault_authority) if escrow_state.is_keypair() => {
+                    if vault_authority.address() != &Escrow::vault_authority(escrow.address()).0 {
+                        return Err(ProgramError::InvalidSeeds);
+                    }
+                }
+                None if !escrow_state.is_keypair() => {}
+                _ => return Err(ProgramError::InvalidArgument),
+            }
+        }
+
+        AssociatedTokenAccount::check(vault, vault_authority.unwrap_or(escrow), mint_a, token_program)?;
+        AssociatedTokenAccount::check(maker_ata_a, maker, mint_a, token_program)?;
+
+        Ok(Self {
+            maker,
+            escrow,
+            mint_a,
+            vault,
+            maker_ata_a,
+            token_program,
+            rent_destination,
+            vault_authority,
+        })
+    }
+}
+
+impl<'a> TryFrom<&'a [AccountView]> for RefundAccounts<'a> {
+    type Error = ProgramError;
+    fn try_from(accounts: &'a [AccountView]) -> Result<Self, Self::Error> {
+        let [maker, escrow, mint_a, vault, maker_ata_a, token_program, rest @ ..] = accounts else {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        };
+        let (rent_destination, rest) = if RENT_TO_TREASURY {
+            let [treasury, rest @ ..] = rest else {
+                return Err(ProgramError::NotEnoughAccountKeys);
+            };
+            TreasuryAccount::check(treasury)?;
+            (treasury, rest)
+        } else {
+            (maker, rest)
+        };
+        // A keypair escrow's vault authority comes first, then the optional destination
+        let keypair = Escrow::load(&escrow.try_borrow()?)?.is_keypair();
+        let (vault_authority, rest) = match rest {
+            [vault_authority, rest @ ..] if keypair => (Some(vault_authority), rest),
+            [] if keypair => return Err(ProgramError::NotEnoughAccountKeys),
+            _ => (None, rest),
+        };
+        // Validated like maker_ata_a: any token account of mint_a the maker owns
+        let maker_ata_a = match rest {
+            [] => maker_ata_a,
+            [destination] => destination,
+            _ => return Err(ProgramError::InvalidArgument),
+        };
+
+        Self::new(maker, escrow, mint_a, vault, maker_ata_a, token_program, rent_destination, vault_authority)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pinocchio_token::state::TokenAccount;
+    use crate::{constants::{SYSTEM_PROGRAM_ID, TOKEN_PROGRAM_ID}, test_utils::*};
+
+    /// Refund accounts of maker 1's escrow 7, its vault holding 10 of mint 3
+    fn refund_accounts(edit: impl FnOnce(&mut Escrow)) -> Vec<TestAccount> {
+        let (maker, mint_a) = (address(1), address(3));
+        let escrow = escrow(&maker, 7, edit);
+        let vault = vault(escrow.view().address(), &mint_a, 10);
+        vec![
+            TestAccount::wallet(maker.clone()).signer().writable(),
+            escrow,
+            mint(mint_a.clone()),
+            vault,
+            token_account(ata_address(&maker, &mint_a), &mint_a, &maker, 0),
+            TestAccount::program(TOKEN_PROGRAM_ID),
+        ]
+    }
+
+    #[test]
+    fn returns_the_deposit_and_closes_the_escrow() {
+        // Refund takes no config: pausing the program never blocks it
+        let accounts = refund_accounts(|_| {});
+        let views: Vec<AccountView> = accounts.iter().map(TestAccount::view).collect();
+        let maker_lamports = views[0].lamports();
+
+        Refund::try_from(&views[..]).unwrap().process().unwrap();
+
+        assert_eq!(TokenAccount::from_account_view(&views[4]).unwrap().amount(), 10);
+        assert!(views[1].owned_by(&SYSTEM_PROGRAM_ID));
+        assert!(views[3].owned_by(&SYSTEM_PROGRAM_ID));
+        assert_eq!(views[0].lamports(), maker_lamports + rent(Escrow::LEN) + 1);
+    }
+
+    #[test]
+    fn returns_what_partial_fills_left_in_the_vault() {
+        let mut accounts = refund_accounts(|escrow| escrow.set_filled(4));
+        let escrow = accounts[1].view().address().clone();
+        accounts[3] = vault(&escrow, &address(3), 6);
+        let views: Vec<AccountView> = accounts.iter().map(TestAccount::view).collect();
+
+        Refund::try_from(&views[..]).unwrap().process().unwrap();
+
+        assert_eq!(TokenAccount::from_account_view(&views[4]).unwrap().amount(), 6);
+        assert!(views[3].owned_by(&SYSTEM_PROGRAM_ID));
+    }
+
+    #[test]
+    fn returns_the_deposit_to_a_maker_owned_destination() {
+        let mut accounts = refund_accounts(|_| {});
+        accounts.push(token_account(address(40), &address(3), &address(1), 0));
+        let views: Vec<AccountView> = accounts.iter().map(TestAccount::view).collect();
+
+        Refund::try_from(&views[..]).unwrap().process().unwrap();
+
+        assert_eq!(TokenAccount::from_account_view(&views[6]).unwrap().amount(), 10);
+        assert_eq!(TokenAccount::from_account_view(&views[4]).unwrap().amount(), 0);
+
+        // Not someone else's account
+        let mut accounts = refund_accounts(|_| {});
+        accounts.push(token_account(address(40), &address(3), &address(2), 0));
+        let views: Vec<AccountView> = accounts.iter().map(TestAccount::view).collect();
+        assert_eq!(Refund::try_from(&views[..]).err(), Some(ProgramError::InvalidAccountData));
+    }
+
+    #[test]
+    fn refunds_a_keypair_escrow_through_its_vault_authority() {
+        let (maker, mint_a) = (address(1), address(3));
+        let vault_authority = Escrow::vault_authority(&address(30)).0;
+        let accounts = [
+            TestAccount::wallet(maker.clone()).signer().writable(),
+            keypair_escrow(address(30), &maker, |_| {}),
+            mint(mint_a.clone()),
+            vault(&vault_authority, &mint_a, 10),
+            token_account(ata_address(&maker, &mint_a), &mint_a, &maker, 0),
+            TestAccount::program(TOKEN_PROGRAM_ID),
+            TestAccount::wallet(vault_authority.clone()),
+        ];
+        let views: Vec<AccountView> = accounts.iter().map(TestAccount::view).collect();
+        // Without its vault authority
+        assert_eq!(Refund::try_from(&views[..6]).err(), Some(ProgramError::NotEnoughAccountKeys));
+
+        Refund::try_from(&views[..]).unwrap().process().unwrap();
+
+        assert_eq!(TokenAccount::from_account_view(&views[4]).unwrap().amount(), 10);
+        assert!(views[1].owned_by(&SYSTEM_PROGRAM_ID));
+        assert!(views[3].owned_by(&SYSTEM_PROGRAM_ID));
+    }
+}
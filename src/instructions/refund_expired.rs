@@ -0,0 +1,158 @@
+use pinocchio::{AccountView, ProgramResult, error::ProgramError};
+use super::{make::{SignerAccount, TreasuryAccount, RENT_TO_TREASURY}, refund::{Refund, RefundAccounts}};
+use crate::state::Escrow;
+use crate::cpi;
+
+/// Number of accounts in each escrow group: `escrow`, `mint_a`, `vault`, `maker_ata_a`
+pub const REFUND_EXPIRED_GROUP_LEN: usize = 4;
+
+/// Maximum number of escrows refunded in a single instruction
+pub const MAX_REFUND_EXPIRED_GROUPS: usize = 16;
+
+/// Refunds every expired escrow of a maker in one instruction.
+///
+/// Escrows that haven't expired (or have no deadline) are skipped rather than
+/// failing the whole batch, but an invalid group still fails the instruction.
+/// Keypair escrows are skipped too: their vault authority has no place in a
+/// group, so they are refunded one by one with `Refund`.
+/// The return data holds one byte per group: 1 if it was refunded, 0 if skipped.
+///
+/// # Accounts
+/// - `maker`: Maker's wallet account (signer)
+/// - `token_program`: Token program
+/// - `treasury`: Treasury PDA receiving the rent, only with `RENT_TO_TREASURY`
+/// - then for each escrow: `escrow`, `mint_a`, `vault`, `maker_ata_a`
+pub struct RefundExpired<'a> {
+    pub maker: &'a AccountView,
+    pub token_program: &'a AccountView,
+    /// Receives the vault and escrow rent: the maker, or the treasury with `RENT_TO_TREASURY`
+    pub rent_destination: &'a AccountView,
+    pub groups: &'a [AccountView],
+}
+
+impl<'a> TryFrom<&'a [AccountView]> for RefundExpired<'a> {
+    type Error = ProgramError;
+    fn try_from(accounts: &'a [AccountView]) -> Result<Self, Self::Error> {
+        let [maker, token_program, groups @ ..] = accounts else {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        };
+        let (rent_destination, groups) = if RENT_TO_TREASURY {
+            let [treasury, groups @ ..] = groups else {
+                return Err(ProgramError::NotEnoughAccountKeys);
+            };
+            TreasuryAccount::check(treasury)?;
+            (treasury, groups)
+        } else {
+            (maker, groups)
+        };
+
+        SignerAccount::check(maker)?;
+
+        if groups.is_empty() || groups.len() % REFUND_EXPIRED_GROUP_LEN != 0 {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+        if groups.len() / REFUND_EXPIRED_GROUP_LEN > MAX_REFUND_EXPIRED_GROUPS {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        Ok(Self { maker, token_program, rent_destination, groups })
+    }
+}
+
+impl<'a> RefundExpired<'a> {
+    pub const DISCRIMINATOR: &'a u8 = &crate::constants::REFUND_EXPIRED_DISCRIMINATOR;
+
+    pub fn process(&self) -> ProgramResult {
+        let now = crate::unix_timestamp()?;
+        let mut refunded = [0u8; MAX_REFUND_EXPIRED_GROUPS];
+        let count = self.groups.len() / REFUND_EXPIRED_GROUP_LEN;
+
+        for (index, group) in self.groups.chunks_exact(REFUND_EXPIRED_GROUP_LEN).enumerate() {
+            let [escrow, mint_a, vault, maker_ata_a] = group else {
+                return Err(ProgramError::NotEnoughAccountKeys);
+            };
+            let (keypair, expired) = {
+                let data = escrow.try_borrow()?;
+                let escrow = Escrow::load(&data)?;
+                (escrow.is_keypair(), escrow.is_expired(now))
+            };
+            if keypair {
+                continue;
+            }
+
+            let accounts = RefundAccounts::new(self.maker, escrow, mint_a, vault, maker_ata_a, self.token_program, self.rent_destination, None)?;
+            if expired {
+                Refund { accounts }.process()?;
+                refunded[index] = 1;
+            }
+        }
+
+        cpi::set_return_data(&refunded[..count]);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pinocchio_token::state::TokenAccount;
+    use crate::{constants::{SYSTEM_PROGRAM_ID, TOKEN_PROGRAM_ID}, test_utils::*};
+
+    #[test]
+    fn refunds_the_expired_escrows_and_skips_the_others() {
+        set_now(1_000);
+        let (maker, mint_a) = (address(1), address(3));
+        let (expired, valid) = (escrow(&maker, 7, |state| state.set_deadline(999)), escrow(&maker, 8, |state| state.set_deadline(1_001)));
+        let (expired_vault, valid_vault) = (vault(expired.view().address(), &mint_a, 10), vault(valid.view().address(), &mint_a, 5));
+        let (maker_account, mint, maker_ata_a, token_program) = (
+            TestAccount::wallet(maker.clone()).signer().writable(),
+            self::mint(mint_a.clone()),
+            token_account(ata_address(&maker, &mint_a), &mint_a, &maker, 0),
+            TestAccount::program(TOKEN_PROGRAM_ID),
+        );
+        let views = [
+            &maker_account, &token_program,
+            &expired, &mint, &expired_vault, &maker_ata_a,
+            &valid, &mint, &valid_vault, &maker_ata_a,
+        ]
+        .map(TestAccount::view);
+
+        RefundExpired::try_from(&views[..]).unwrap().process().unwrap();
+
+        assert_eq!(return_data(), [1, 0]);
+        assert!(views[2].owned_by(&SYSTEM_PROGRAM_ID));
+        assert!(views[6].owned_by(&crate::ID));
+        assert_eq!(TokenAccount::from_account_view(&views[5]).unwrap().amount(), 10);
+        assert_eq!(TokenAccount::from_account_view(&views[8]).unwrap().amount(), 5);
+    }
+
+    #[test]
+    fn skips_keypair_escrows() {
+        set_now(1_000);
+        let (maker, mint_a) = (address(1), address(3));
+        let keypair = keypair_escrow(address(30), &maker, |state| state.set_deadline(999));
+        let keypair_vault = vault(&Escrow::vault_authority(&address(30)).0, &mint_a, 10);
+        let expired = escrow(&maker, 7, |state| state.set_deadline(999));
+        let expired_vault = vault(expired.view().address(), &mint_a, 5);
+        let (maker_account, mint, maker_ata_a, token_program) = (
+            TestAccount::wallet(maker.clone()).signer().writable(),
+            self::mint(mint_a.clone()),
+            token_account(ata_address(&maker, &mint_a), &mint_a, &maker, 0),
+            TestAccount::program(TOKEN_PROGRAM_ID),
+        );
+        let views = [
+            &maker_account, &token_program,
+            &keypair, &mint, &keypair_vault, &maker_ata_a,
+            &expired, &mint, &expired_vault, &maker_ata_a,
+        ]
+        .map(TestAccount::view);
+
+        // The keypair escrow doesn't fail the batch, and is left for Refund
+        RefundExpired::try_from(&views[..]).unwrap().process().unwrap();
+
+        assert_eq!(return_data(), [0, 1]);
+        assert!(views[2].owned_by(&crate::ID));
+        assert_eq!(TokenAccount::from_account_view(&views[4]).unwrap().amount(), 10);
+        assert_eq!(TokenAccount::from_account_view(&views[5]).unwrap().amount(), 5);
+    }
+}
@@ -0,0 +1,163 @@
+use pinocchio::{AccountView, ProgramResult, error::ProgramError};
+use super::make::{SignerAccount,ProgramAccount,MintInterface,MULTI_TOKEN_PROGRAM};
+use crate::{constants::TOKEN_PROGRAM_ID, error::EscrowError, state::{Escrow, ReceiveAmount}};
+
+/// Changes the token an escrow accepts in exchange for its deposit.
+///
+/// Version 0 keeps `receive` as is, now counted in the new mint. Version 1
+/// (`SET_MINT_B_V1_DISCRIMINATOR`) also replaces it, priced in the new mint.
+/// Once a partial
+/// fill has sealed the escrow the terms are fixed, since earlier takers paid in
+/// the old mint. Oracle-priced escrows can't switch either, their oracle quotes the old mint.
+///
+/// # Accounts
+/// - `maker`: Maker's wallet account (signer)
+/// - `escrow`: Escrow account to update
+/// - `mint_b`: New mint to receive
+pub struct SetMintB<'a> {
+    pub accounts: SetMintBAccounts<'a>,
+    /// New amount of mint_b to receive, only with version 1
+    pub receive: Option<ReceiveAmount>,
+}
+
+/// Version 0: no instruction data
+impl<'a> TryFrom<&'a [AccountView]> for SetMintB<'a> {
+    type Error = ProgramError;
+    fn try_from(accounts: &'a [AccountView]) -> Result<Self, Self::Error> {
+        Ok(Self {
+            accounts: SetMintBAccounts::try_from(accounts)?,
+            receive: None,
+        })
+    }
+}
+
+/// Version 1, instruction data: the new receive (u64, little endian)
+impl<'a> TryFrom<(&'a [AccountView], &'a [u8])> for SetMintB<'a> {
+    type Error = ProgramError;
+    fn try_from((accounts, data): (&'a [AccountView], &'a [u8])) -> Result<Self, Self::Error> {
+        let receive = u64::from_le_bytes(data.try_into().map_err(|_| ProgramError::InvalidInstructionData)?);
+        if receive == 0 {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        Ok(Self {
+            accounts: SetMintBAccounts::try_from(accounts)?,
+            receive: Some(ReceiveAmount(receive)),
+        })
+    }
+}
+
+impl<'a> SetMintB<'a> {
+    pub const DISCRIMINATOR: &'a u8 = &crate::constants::SET_MINT_B_DISCRIMINATOR;
+
+    /// 1. escrow belongs to the maker and isn't sealed by a partial fill
+    /// 2. new mint differs from mint_a
+    /// 3. store the new mint_b, and the new receive with version 1
+    pub fn process(&self) -> ProgramResult {
+        let mut data = self.accounts.escrow.try_borrow_mut()?;
+        let escrow = &mut *Escrow::load_mut(&mut data)?;
+        if escrow.maker != *self.accounts.maker.address() {
+            return Err(ProgramError::IncorrectAuthority);
+        }
+        if escrow.is_sealed() {
+            return Err(EscrowError::PartiallyFilled.into());
+        }
+        if escrow.uses_oracle() {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        if escrow.mint_a == *self.accounts.mint_b.address() {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        escrow.set_mint_b(self.accounts.mint_b.address().clone());
+        if let Some(receive) = self.receive {
+            escrow.set_receive(receive);
+        }
+        Ok(())
+    }
+}
+
+pub struct SetMintBAccounts<'a> {
+    pub maker: &'a AccountView,
+    pub escrow: &'a AccountView,
+    pub mint_b: &'a AccountView,
+}
+
+impl<'a> TryFrom<&'a [AccountView]> for SetMintBAccounts<'a> {
+    type Error = ProgramError;
+    fn try_from(accounts: &'a [AccountView]) -> Result<Self, Self::Error> {
+        let [maker, escrow, mint_b] = accounts else {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        };
+
+        SignerAccount::check(maker)?;
+        ProgramAccount::check(escrow)?;
+
+        // Same mint requirements as Make
+        MintInterface::check(mint_b)?;
+        MintInterface::check_not_blocked(mint_b)?;
+        MintInterface::check_no_transfer_fee(mint_b)?;
+        MintInterface::check_no_transfer_hook(mint_b)?;
+        // mint_a belongs to the legacy token program its transfers go to, and so must
+        // mint_b, unless `MULTI_TOKEN_PROGRAM` lets Take pay it through its own program
+        if !MULTI_TOKEN_PROGRAM {
+            MintInterface::check_token_program(mint_b, &TOKEN_PROGRAM_ID)?;
+        }
+
+        Ok(Self { maker, escrow, mint_b })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pinocchio::Address;
+    use crate::test_utils::*;
+
+    /// Runs SetMintB v0 on maker 1's escrow of mint 3 for 20 of mint 4, edited by
+    /// `edit`, switching it to mint 5. Returns the stored mint_b
+    fn set_mint_b(edit: impl FnOnce(&mut Escrow)) -> Result<Address, ProgramError> {
+        let maker = address(1);
+        let (escrow, bump) = escrow_address(&maker, 7);
+        let data = escrow_data(|state| {
+            state.set_inner(7, maker.clone(), address(3), address(4), ReceiveAmount(20), 0, 0, [bump]);
+            edit(state);
+        });
+        let accounts = [
+            TestAccount::wallet(maker).signer(),
+            TestAccount::new(escrow, crate::ID, 1, &data).writable(),
+            mint(address(5)),
+        ];
+        let views: Vec<AccountView> = accounts.iter().map(TestAccount::view).collect();
+
+        SetMintB::try_from(&views[..])?.process()?;
+        let data = views[1].try_borrow()?;
+        Ok(Escrow::load(&data)?.mint_b.clone())
+    }
+
+    #[test]
+    fn changes_the_accepted_mint() {
+        assert_eq!(set_mint_b(|_| {}), Ok(address(5)));
+    }
+
+    #[test]
+    fn takes_a_token_2022_mint_b_only_with_multi_token_program() {
+        let accounts = [
+            TestAccount::wallet(address(1)).signer(),
+            escrow(&address(1), 7, |_| {}),
+            mint_2022(address(5), &[]),
+        ];
+        let views: Vec<AccountView> = accounts.iter().map(TestAccount::view).collect();
+        let expected = if MULTI_TOKEN_PROGRAM { Ok(()) } else { Err(ProgramError::IncorrectProgramId) };
+        assert_eq!(SetMintBAccounts::try_from(&views[..]).map(|_| ()), expected);
+    }
+
+    #[test]
+    fn rejects_an_escrow_sealed_by_a_partial_fill() {
+        let sealed = |escrow: &mut Escrow| {
+            escrow.set_filled(4);
+            escrow.set_flags(Escrow::FLAG_PARTIAL_FILL | Escrow::FLAG_SEALED);
+        };
+        assert_eq!(set_mint_b(sealed), Err(EscrowError::PartiallyFilled.into()));
+    }
+}
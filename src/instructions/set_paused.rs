@@ -0,0 +1,115 @@
+use pinocchio::{AccountView, Address, ProgramResult, error::ProgramError};
+use super::make::SignerAccount;
+use crate::state::Config;
+
+/// Pauses or unpauses the program.
+///
+/// While paused, `Make` and `Take` are rejected but `Refund` keeps working so
+/// makers can always recover their deposits. The config PDA must have been
+/// created by `InitConfig` first.
+///
+/// # Accounts
+/// - `admin`: Config admin (signer)
+/// - `config`: Program config PDA
+pub struct SetPaused<'a> {
+    pub accounts: SetPausedAccounts<'a>,
+    /// Whether the program should be paused
+    pub paused: bool,
+}
+
+impl<'a> TryFrom<(&'a [AccountView], &'a [u8])> for SetPaused<'a> {
+    type Error = ProgramError;
+    fn try_from((accounts, data): (&'a [AccountView], &'a [u8])) -> Result<Self, Self::Error> {
+        let accounts = SetPausedAccounts::try_from(accounts)?;
+        let paused = match data {
+            [0] => false,
+            [1] => true,
+            _ => return Err(ProgramError::InvalidInstructionData),
+        };
+
+        Ok(Self { accounts, paused })
+    }
+}
+
+impl<'a> SetPaused<'a> {
+    pub const DISCRIMINATOR: &'a u8 = &crate::constants::SET_PAUSED_DISCRIMINATOR;
+
+    /// 1. the config exists
+    /// 2. admin matches the config
+    /// 3. store the paused flag
+    pub fn process(&self) -> ProgramResult {
+        let (config_address, _) = Address::find_program_address(&[Config::SEED], &crate::ID);
+        if self.accounts.config.address() != &config_address {
+            return Err(ProgramError::InvalidSeeds);
+        }
+
+        if self.accounts.config.is_data_empty() {
+            return Err(ProgramError::UninitializedAccount);
+        }
+        if !self.accounts.config.owned_by(&crate::ID) {
+            return Err(ProgramError::InvalidAccountOwner);
+        }
+
+        let mut data = self.accounts.config.try_borrow_mut()?;
+        let config = Config::load_mut(&mut data)?;
+        if config.admin != *self.accounts.admin.address() {
+            return Err(ProgramError::IncorrectAuthority);
+        }
+        config.set_paused(self.paused);
+        Ok(())
+    }
+}
+
+pub struct SetPausedAccounts<'a> {
+    pub admin: &'a AccountView,
+    pub config: &'a AccountView,
+}
+
+impl<'a> TryFrom<&'a [AccountView]> for SetPausedAccounts<'a> {
+    type Error = ProgramError;
+    fn try_from(accounts: &'a [AccountView]) -> Result<Self, Self::Error> {
+        let [admin, config] = accounts else {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        };
+
+        SignerAccount::check(admin)?;
+
+        Ok(Self { admin, config })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{error::EscrowError, instructions::make::ConfigAccount, test_utils::*};
+
+    fn set_paused(admin: &TestAccount, config: &TestAccount, paused: bool) -> ProgramResult {
+        let views = [admin.view(), config.view()];
+        SetPaused::try_from((&views[..], &[paused as u8][..]))?.process()
+    }
+
+    #[test]
+    fn pauses_and_unpauses_the_program() {
+        let (admin, config) = (TestAccount::wallet(crate::ADMIN).signer(), initialized_config(false));
+
+        set_paused(&admin, &config, true).unwrap();
+        assert_eq!(ConfigAccount::check_not_paused(&config.view()), Err(EscrowError::ProgramPaused.into()));
+
+        set_paused(&admin, &config, false).unwrap();
+        assert_eq!(ConfigAccount::check_not_paused(&config.view()), Ok(()));
+    }
+
+    #[test]
+    fn rejects_an_uninitialized_config() {
+        let admin = TestAccount::wallet(crate::ADMIN).signer();
+        assert_eq!(set_paused(&admin, &config(), true), Err(ProgramError::UninitializedAccount));
+    }
+
+    #[test]
+    fn rejects_another_admin() {
+        let config = initialized_config(false);
+        assert_eq!(set_paused(&TestAccount::wallet(address(9)).signer(), &config, true), Err(ProgramError::IncorrectAuthority));
+        assert_eq!(set_paused(&TestAccount::wallet(crate::ADMIN), &config, true), Err(ProgramError::InvalidInstructionData));
+        assert_eq!(ConfigAccount::check_not_paused(&config.view()), Ok(()));
+    }
+}
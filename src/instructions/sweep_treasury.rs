@@ -0,0 +1,110 @@
+use pinocchio::{AccountView, ProgramResult, cpi::{Seed,Signer}, error::ProgramError};
+use super::make::{SignerAccount,TreasuryAccount,ConfigAccount};
+use crate::constants::TREASURY_SEED;
+use crate::cpi;
+
+/// Moves the rent collected by the treasury PDA to a destination chosen by the admin.
+///
+/// The treasury only fills up when the program is built with `RENT_TO_TREASURY`.
+/// It is a system account, so the whole balance is swept and the account closes.
+///
+/// # Accounts
+/// - `admin`: Config admin (signer)
+/// - `config`: Program config PDA (may be uninitialized, leaving the deploy admin in charge)
+/// - `treasury`: Treasury PDA
+/// - `destination`: Account receiving the swept lamports
+/// - `system_program`: System program
+pub struct SweepTreasury<'a> {
+    pub accounts: SweepTreasuryAccounts<'a>,
+    pub bump: u8,
+}
+
+impl<'a> TryFrom<&'a [AccountView]> for SweepTreasury<'a> {
+    type Error = ProgramError;
+    fn try_from(accounts: &'a [AccountView]) -> Result<Self, Self::Error> {
+        let accounts = SweepTreasuryAccounts::try_from(accounts)?;
+        let bump = TreasuryAccount::check(accounts.treasury)?;
+
+        Ok(Self { accounts, bump })
+    }
+}
+
+impl<'a> SweepTreasury<'a> {
+    pub const DISCRIMINATOR: &'a u8 = &crate::constants::SWEEP_TREASURY_DISCRIMINATOR;
+
+    /// 1. admin matches the config
+    /// 2. treasury:lamports -> destination
+    pub fn process(&self) -> ProgramResult {
+        if ConfigAccount::load(self.accounts.config)?.admin != *self.accounts.admin.address() {
+            return Err(ProgramError::IncorrectAuthority);
+        }
+
+        let bump_binding = [self.bump];
+        let seed = [
+            Seed::from(TREASURY_SEED),
+            Seed::from(&bump_binding),
+        ];
+        cpi::transfer_lamports(
+            self.accounts.treasury,
+            self.accounts.destination,
+            self.accounts.treasury.lamports(),
+            &[Signer::from(&seed)],
+        )
+    }
+}
+
+pub struct SweepTreasuryAccounts<'a> {
+    pub admin: &'a AccountView,
+    pub config: &'a AccountView,
+    pub treasury: &'a AccountView,
+    pub destination: &'a AccountView,
+    pub system_program: &'a AccountView,
+}
+
+impl<'a> TryFrom<&'a [AccountView]> for SweepTreasuryAccounts<'a> {
+    type Error = ProgramError;
+    fn try_from(accounts: &'a [AccountView]) -> Result<Self, Self::Error> {
+        let [admin, config, treasury, destination, system_program] = accounts else {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        };
+
+        SignerAccount::check(admin)?;
+
+        Ok(Self { admin, config, treasury, destination, system_program })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pinocchio::Address;
+    use crate::{constants::SYSTEM_PROGRAM_ID, instructions::make::ProgramAccount, test_utils::*};
+
+    fn treasury() -> TestAccount {
+        TestAccount::new(Address::find_program_address(&[TREASURY_SEED], &crate::ID).0, SYSTEM_PROGRAM_ID, 0, &[]).writable()
+    }
+
+    fn sweep(admin: &TestAccount, treasury: &TestAccount) -> ProgramResult {
+        let (config, destination, system_program) = (config(), TestAccount::wallet(address(8)).writable(), TestAccount::program(SYSTEM_PROGRAM_ID));
+        let accounts = [admin.view(), config.view(), treasury.view(), destination.view(), system_program.view()];
+        SweepTreasury::try_from(&accounts[..])?.process()
+    }
+
+    #[test]
+    fn closed_rent_collects_in_the_treasury() {
+        let (treasury, escrow) = (treasury(), TestAccount::new(address(7), crate::ID, 2_000_000, &[1; 8]).writable());
+        TreasuryAccount::check(&treasury.view()).unwrap();
+        ProgramAccount::close(&escrow.view(), &treasury.view()).unwrap();
+        assert_eq!(treasury.view().lamports(), 2_000_000);
+    }
+
+    #[test]
+    fn deploy_admin_sweeps_without_a_config() {
+        let treasury = treasury();
+        assert_eq!(sweep(&TestAccount::wallet(crate::ADMIN).signer(), &treasury), Ok(()));
+        assert_eq!(
+            sweep(&TestAccount::wallet(address(9)).signer(), &treasury),
+            Err(ProgramError::IncorrectAuthority),
+        );
+    }
+}
@@ -1,108 +1,504 @@
-use pinocchio::{AccountView, Address, ProgramResult, cpi::{Seed,Signer}, error::ProgramError };
-use pinocchio_token::{instructions::{Transfer,CloseAccount},state::TokenAccount};
-use super::make::{MintInterface,SignerAccount,AssociatedTokenAccount,ProgramAccount};
-use crate::state::Escrow;
+use pinocchio::{AccountView, Address, ProgramResult, cpi::{Seed, Signer}, error::ProgramError, instruction::{InstructionAccount, InstructionView}, sysvars::instructions::INSTRUCTIONS_ID};
+use pinocchio_token::state::TokenAccount;
+use super::permit::{PermitNonceAccount,TakePermit};
+use super::make::{assert_distinct,WritableAccount,MintInterface,SignerAccount,SystemProgram,AssociatedTokenAccount,AssociatedTokenProgram,ProgramAccount,TokenAccountInterface,TokenProgram,ConfigAccount,TreasuryAccount,MULTI_TOKEN_PROGRAM,RENT_TO_TREASURY};
+use crate::{error::{at_account, invalid_account, missing_account, EscrowError}, constants::VAULT_AUTHORITY_SEED, state::{Escrow, OraclePrice, TakeHistory}};
+use crate::cpi;
+
+/// Whether a fully filled escrow's rent goes to the taker instead of the maker.
+///
+/// The maker paid the escrow's rent, so by default it returns to them along with
+/// the vault rent. A deployment that wants to reward takers flips this and rebuilds.
+/// `RENT_TO_TREASURY` takes precedence over this.
+pub const ESCROW_RENT_TO_TAKER: bool = false;
+
+/// Flat fee every take pays in `TAKE_FEE_MINT`, on top of the swap. 0 disables it.
+///
+/// With a fee `Take` expects the taker's `TAKE_FEE_MINT` token account and the
+/// fee account (a token account of `TAKE_FEE_MINT` owned by `TAKE_FEE_RECIPIENT`)
+/// after the treasury. The fee is drawn with the same authority as the payment.
+pub const TAKE_FLAT_FEE: u64 = 0;
+
+/// Mint the flat take fee is paid in. Replace before enabling `TAKE_FLAT_FEE`.
+pub const TAKE_FEE_MINT: Address = Address::new_from_array([0; 32]);
+
+/// Owner of the token account collecting the flat take fee. Replace before enabling `TAKE_FLAT_FEE`.
+pub const TAKE_FEE_RECIPIENT: Address = Address::new_from_array([0; 32]);
+
+/// Whether `Take` records its taker in the escrow's `TakeHistory` PDA, which stays
+/// around after the escrow closes.
+///
+/// With it `Take` expects the history PDA after the fee accounts. The taker pays
+/// its rent on the escrow's first take, which the taker must therefore sign.
+/// Every take logs its taker in a `TAKE_EVENT_TAG` event either way.
+pub const RECORD_TAKE_HISTORY: bool = false;
+
+/// First field of the event `Take` logs as program data (`sol_log_data`)
+///
+/// Followed by the escrow address, the taker address, the mint_a filled (u64)
+/// and the mint_b paid (u64), each a separate little-endian field.
+pub const TAKE_EVENT_TAG: &[u8] = b"take";
+
+/// Programs a take may deposit its mint_a into, e.g. a lending market holding the
+/// taker's position (see `PositionDeposit`). Empty by default, disabling the mode.
+///
+/// This list is the only guard on the deposit CPI. A listed program is invoked
+/// with the taker's signature and the forwarded accounts writable as passed, so it
+/// could move anything the taker owns. Take only checks afterwards that the fill
+/// left taker_ata_a: list audited programs whose deposit does no more than that.
+pub const DEPOSIT_PROGRAMS: &[Address] = &[];
+
+/// Most accounts forwarded to a `DEPOSIT_PROGRAMS` deposit instruction
+pub const MAX_DEPOSIT_ACCOUNTS: usize = 16;
+
+/// Position of each fixed Take account, in the order `TakeAccounts::try_from`
+/// destructures them. Position-coded errors (see `at_account`) use these too.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(usize)]
+pub enum TakeAccount {
+    Taker = 0,
+    Maker = 1,
+    Escrow = 2,
+    MintA = 3,
+    MintB = 4,
+    Vault = 5,
+    TakerAtaA = 6,
+    TakerAtaB = 7,
+    MakerAtaB = 8,
+    SystemProgram = 9,
+    TokenProgram = 10,
+    Config = 11,
+    AssociatedTokenProgram = 12,
+}
+
+impl TakeAccount {
+    /// Number of fixed accounts, the optional ones start at this position
+    pub const COUNT: usize = Self::AssociatedTokenProgram as usize + 1;
+}
+
+/// Position of mint_b's token program, the first optional account when `MULTI_TOKEN_PROGRAM` is set
+const TAKE_TOKEN_PROGRAM_B_INDEX: usize = TakeAccount::COUNT;
+
+/// Position of the treasury, after mint_b's token program when `MULTI_TOKEN_PROGRAM` is set
+const TAKE_TREASURY_INDEX: usize = TAKE_TOKEN_PROGRAM_B_INDEX + MULTI_TOKEN_PROGRAM as usize;
+
+/// Position of the optional fee accounts, after the treasury when `RENT_TO_TREASURY` is set
+const TAKE_FEE_INDEX: usize = TAKE_TREASURY_INDEX + RENT_TO_TREASURY as usize;
+
+/// Position of the take history PDA, after the fee accounts when `TAKE_FLAT_FEE` is set
+const TAKE_HISTORY_INDEX: usize = TAKE_FEE_INDEX + 2 * (TAKE_FLAT_FEE != 0) as usize;
+
+/// Position of a keypair escrow's vault authority, after the take history when
+/// `RECORD_TAKE_HISTORY` is set. The optional oracle follows it, or takes its place.
+const TAKE_VAULT_AUTHORITY_INDEX: usize = TAKE_HISTORY_INDEX + RECORD_TAKE_HISTORY as usize;
+
+/// Logs the `TAKE_EVENT_TAG` event of a take, on-chain only
+#[allow(unused_variables)]
+fn emit_take_event(escrow: &Address, taker: &Address, fill: u64, paid: u64) {
+    #[cfg(target_os = "solana")]
+    {
+        let fill = fill.to_le_bytes();
+        let paid = paid.to_le_bytes();
+        let fields: [&[u8]; 5] = [TAKE_EVENT_TAG, escrow.as_ref(), taker.as_ref(), &fill, &paid];
+        unsafe {
+            pinocchio::syscalls::sol_log_data(fields.as_ptr() as *const u8, fields.len() as u64);
+        }
+    }
+}
+
+/// Validator for the flat take fee accounts
+pub struct FeeAccount;
+
+impl FeeAccount {
+    /// Validates that the account is a `TAKE_FEE_MINT` token account owned by `TAKE_FEE_RECIPIENT`
+    pub fn check(account: &AccountView) -> Result<(), ProgramError> {
+        let fee_account = TokenAccount::from_account_view(account)?;
+        if fee_account.mint() != &TAKE_FEE_MINT {
+            return Err(EscrowError::MintMismatch.into());
+        }
+        if fee_account.owner() != &TAKE_FEE_RECIPIENT {
+            return Err(ProgramError::IllegalOwner);
+        }
+        Ok(())
+    }
+
+    /// Validates that the taker's account pays the fee in `TAKE_FEE_MINT`
+    pub fn check_source(account: &AccountView) -> Result<(), ProgramError> {
+        if TokenAccount::from_account_view(account)?.mint() != &TAKE_FEE_MINT {
+            return Err(EscrowError::MintMismatch.into());
+        }
+        Ok(())
+    }
+}
 
 pub struct Take<'a> {
     pub accounts: TakeAccounts<'a>,
+    pub instruction_data: TakeInstructionData<'a>,
+    /// With a `PositionDeposit`, the deposit program then the accounts forwarded to
+    /// it, passed after every other account. Empty otherwise
+    pub deposit_accounts: &'a [AccountView],
 }
 
-impl<'a> TryFrom<&'a [AccountView]> for Take<'a> {
+impl<'a> TryFrom<(&'a [AccountView], &'a [u8])> for Take<'a> {
     type Error = ProgramError;
-    fn try_from(accounts: &'a [AccountView]) -> Result<Self, Self::Error> {
+    fn try_from((accounts, data): (&'a [AccountView], &'a [u8])) -> Result<Self, Self::Error> {
+        let instruction_data = TakeInstructionData::try_from(data)?;
+        let (accounts, deposit_accounts) = split_deposit_accounts(accounts, instruction_data.deposit, DEPOSIT_PROGRAMS)?;
 
         Ok(Self{
             accounts: TakeAccounts::try_from(accounts)?,
+            instruction_data,
+            deposit_accounts,
         })
     }
 }
 
+/// Checks that a balance went from `before` to `after` by exactly `expected`, as
+/// a transfer-fee mint or any short transfer wouldn't
+fn check_received(before: u64, after: u64, expected: u64) -> ProgramResult {
+    if after.checked_sub(before)!=Some(expected) {
+        return Err(EscrowError::ReceivedAmountMismatch.into());
+    }
+    Ok(())
+}
+
+/// Splits a position deposit's accounts off the end of `accounts`, ahead of them
+/// being the usual Take accounts: the deposit program, which must be one of
+/// `programs`, then the accounts forwarded to it. Without a deposit there are none
+fn split_deposit_accounts<'a>(
+    accounts: &'a [AccountView],
+    deposit: Option<PositionDeposit>,
+    programs: &[Address],
+) -> Result<(&'a [AccountView], &'a [AccountView]), ProgramError> {
+    let Some(deposit) = deposit else {
+        return Ok((accounts, &[]));
+    };
+    let split = accounts.len().checked_sub(1 + deposit.account_count as usize).ok_or(missing_account(accounts.len()))?;
+    let (accounts, deposit_accounts) = accounts.split_at(split);
+    if !programs.contains(deposit_accounts[0].address()) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    Ok((accounts, deposit_accounts))
+}
+
 impl<'a> Take<'a> {
-    pub const DISCRIMINATOR: &'a u8=&1;
+    pub const DISCRIMINATOR: &'a u8 = &crate::constants::TAKE_DISCRIMINATOR;
     
-    /// 1. receive / pay ATA is existed
-    /// 2. escrow is valid
-    /// 3. vault:mint_a -> taker_ata_a
-    /// 4. close vault
-    /// 5. taker:mint_b -> maker_ata_b
-    /// 6. close escrow
+    /// 1. receive / pay ATA is existed (created at the taker's expense), maker_ata_b
+    ///    being the maker's mint_b account
+    /// 2. escrow is valid and not expired, nor past the taker's `valid_until`
+    /// 3. fill is at least the escrow's min_fill, unless it takes the remainder
+    /// 4. vault:mint_a -> taker_ata_a, then into the taker's position with a `PositionDeposit`
+    /// 5. taker:mint_b -> maker_ata_b (authorized by the delegate or a permit instead of the taker), proportional to the fill or quoted by the oracle,
+    ///    verified against maker_ata_b's balance change
+    /// 6. taker:`TAKE_FEE_MINT` -> fee account, when `TAKE_FLAT_FEE` is set
+    /// 7. log the take event, and record the taker with `RECORD_TAKE_HISTORY`
+    /// 8. close vault and escrow once fully filled, unless the escrow is persistent
+    /// 
+    /// Steps 1-3 (every ATA creation and validation) must stay ahead of the first
+    /// token movement, so a take that can't complete fails before any transfer.
     pub fn process(&self) -> ProgramResult {
         
+        // The taker funds the rent of any receiving ATA still to create, maker_ata_b
+        // included: the maker isn't part of the transaction. A take signed by a
+        // delegate or authorized by a permit can only pay into existing ATAs.
+        // Only then is the system program needed: with both ATAs existing its slot
+        // may hold a placeholder (see `client::take_addresses`)
+        if self.accounts.taker_ata_a.is_data_empty() || self.accounts.maker_ata_b.is_data_empty() {
+            SignerAccount::check(self.accounts.taker).map_err(at_account(TakeAccount::Taker as usize))?;
+            SystemProgram::check(self.accounts.system_program).map_err(at_account(TakeAccount::SystemProgram as usize))?;
+        }
+
+        // A position deposit moves the fill on with the taker's signature, which a
+        // delegate or permit take doesn't have (see `deposit_position`)
+        if self.instruction_data.deposit.is_some() {
+            SignerAccount::check(self.accounts.taker).map_err(at_account(TakeAccount::Taker as usize))?;
+        }
+
+        // The taker's receiving account must hold the same mint as the vault, checked
+        // ahead of the ATA validation so the mismatch is reported as such
+        if !self.accounts.taker_ata_a.is_data_empty()
+            && TokenAccountInterface::from_account_view(self.accounts.taker_ata_a)?.mint()!=TokenAccountInterface::from_account_view(self.accounts.vault)?.mint() {
+            return Err(EscrowError::MintMismatch.into());
+        }
+
+        // Create and validate both receiving ATAs up front, before any tokens move
+        log_compute_units!("take: init taker_ata_a");
         AssociatedTokenAccount::init_if_needed(
             self.accounts.taker_ata_a,
-            self.accounts.taker,
             self.accounts.mint_a,
-            self.accounts.system_program,
+            self.accounts.taker,
+            self.accounts.taker,
             self.accounts.system_program,
             self.accounts.token_program,
+            self.accounts.associated_token_program,
         )?;
 
+        log_compute_units!("take: init maker_ata_b");
         AssociatedTokenAccount::init_if_needed(
             self.accounts.maker_ata_b,
             self.accounts.mint_b,
-            self.accounts.taker,                
             self.accounts.maker,
+            self.accounts.taker,
             self.accounts.system_program,
-            self.accounts.token_program,
+            self.accounts.token_program_b,
+            self.accounts.associated_token_program,
         )?;
 
         // check escrow is valid
         let data =self.accounts.escrow.try_borrow()?;
         let escrow=Escrow::load(&data)?;
-        let (escrow_address,_)=Address::find_program_address(&[
-            b"escrow",
-            self.accounts.maker.address().as_ref(),
-            &escrow.seed.to_le_bytes(),
-            &escrow.bump,
-        ],&crate::ID);
-        if escrow_address!=*self.accounts.escrow.address() {
-            return Err(ProgramError::InvalidAccountData);
+        if escrow.is_keypair() {
+            // Not derived from the maker: the stored maker is all there is to check
+            if escrow.maker!=*self.accounts.maker.address() {
+                return Err(ProgramError::InvalidAccountData);
+            }
+        } else {
+            // From the stored bump: a bump that doesn't derive a PDA fails here
+            let escrow_address=escrow.address_with_bump(self.accounts.maker.address())?;
+            if escrow_address!=*self.accounts.escrow.address() {
+                return Err(ProgramError::InvalidAccountData);
+            }
+        }
+
+        // check escrow hasn't expired
+        let now=crate::unix_timestamp()?;
+        if escrow.is_expired(now) {
+            return Err(EscrowError::EscrowExpired.into());
+        }
+
+        // Nor the taker's own deadline, should the transaction land late
+        if self.instruction_data.valid_until!=0 && now>self.instruction_data.valid_until {
+            return Err(EscrowError::TakeDeadlinePassed.into());
         }
 
         let seed_binding=escrow.seed.to_le_bytes();
         let bump_binding=escrow.bump;
         let seed=[
-            Seed::from(b"escrow"),
+            Seed::from(Escrow::SEED),
             Seed::from(self.accounts.maker.address().as_ref()),
             Seed::from(&seed_binding),
             Seed::from(&bump_binding),
         ];
-        let signer=Signer::from(&seed);
+        let keypair_seed=[
+            Seed::from(VAULT_AUTHORITY_SEED),
+            Seed::from(self.accounts.escrow.address().as_ref()),
+            Seed::from(&bump_binding),
+        ];
+        // The vault's owner signs: the escrow PDA, or a keypair escrow's vault authority
+        let vault_owner=self.accounts.vault_authority.unwrap_or(self.accounts.escrow);
+        let signer=if escrow.is_keypair() { Signer::from(&keypair_seed[..]) } else { Signer::from(&seed[..]) };
 
-        let amount=TokenAccount::from_account_view(self.accounts.vault)?.amount();
-        
+        let vault_amount=escrow.vault_amount(vault_owner, self.accounts.vault)?;
+
+        // An NFT escrow still trades exactly one token of an NFT mint
+        if escrow.is_nft() {
+            MintInterface::check_nft(self.accounts.mint_a)?;
+            if vault_amount!=1 {
+                return Err(EscrowError::NotAnNft.into());
+            }
+        }
+
+        // Without an explicit fill the taker takes everything left in the vault
+        let fill_amount=self.instruction_data.fill.unwrap_or(vault_amount);
+        let is_final_fill=escrow.check_fill(fill_amount, vault_amount)?;
+
+        // The taker's slippage guard on what actually leaves the vault
+        if fill_amount<self.instruction_data.min_amount_a_out {
+            return Err(EscrowError::OutputBelowMinimum.into());
+        }
+
+        // Oracle-priced escrows quote the fill at the current oracle price, others
+        // at their fixed price (see `Escrow::fixed_price_quote`)
+        let receive_amount=if escrow.uses_oracle() {
+            let oracle=self.accounts.oracle.ok_or(missing_account(TAKE_VAULT_AUTHORITY_INDEX + escrow.is_keypair() as usize))?;
+            if *oracle.address()!=escrow.oracle {
+                return Err(ProgramError::InvalidAccountData);
+            }
+            let price=OraclePrice::load(&oracle.try_borrow()?)?;
+            price.quote(fill_amount, escrow.multiplier)?
+        } else {
+            escrow.fixed_price_quote(fill_amount, vault_amount)?
+        };
+
+        // The taker must be able to pay in full, checked here rather than failing
+        // deep inside the payment transfer
+        if TokenAccountInterface::from_account_view(self.accounts.taker_ata_b)?.amount()<receive_amount {
+            return Err(ProgramError::InsufficientFunds);
+        }
+
+        // A relayed take must match what the taker signed off-chain, and uses up its nonce
+        if let (Some(instructions_sysvar), Some(permit_nonce))=(self.accounts.instructions_sysvar, self.accounts.permit_nonce) {
+            let permit=TakePermit::load(instructions_sysvar, self.accounts.taker.address())?;
+            permit.check(self.accounts.escrow.address(), &escrow.mint_a, &escrow.mint_b, fill_amount, receive_amount, now)?;
+            permit.consume_nonce(permit_nonce)?;
+        }
+
+        // The payment must land in the maker's own mint_b account, whether it
+        // existed already or was just created above
+        AssociatedTokenAccount::check(self.accounts.maker_ata_b, self.accounts.maker, self.accounts.mint_b, self.accounts.token_program_b)
+            .map_err(at_account(TakeAccount::MakerAtaB as usize))?;
+
+        // Read before the transfer, to verify what the maker actually received
+        let maker_b_before=TokenAccountInterface::from_account_view(self.accounts.maker_ata_b)?.amount();
+        // and that a position deposit took the whole fill back out of taker_ata_a
+        let taker_a_before=TokenAccountInterface::from_account_view(self.accounts.taker_ata_a)?.amount();
+
+        // Everything is validated: no check may be added below this point,
+        // only token movements, the received-amount check and account closes
         // Transfer from vault to taker_ata_a
         // vault:mint_a -> taker_ata_a
-        Transfer{
-            from: self.accounts.vault,
-            to: self.accounts.taker_ata_a,
-            authority: self.accounts.escrow,
-            amount,
-        }.invoke_signed(&[signer.clone()])?;
-        
-        // After transfer, the vault is empty
+        log_compute_units!("take: transfer mint_a");
+        cpi::transfer(
+            self.accounts.token_program,
+            self.accounts.vault,
+            self.accounts.taker_ata_a,
+            vault_owner,
+            fill_amount,
+            core::slice::from_ref(&signer),
+        )?;
+
+        // Under a permit the escrow, approved as token delegate by the taker, signs
+        // the taker's transfers
+        let pay=|token_program: &AccountView, from: &AccountView, to: &AccountView, amount: u64| -> ProgramResult {
+            if self.accounts.instructions_sysvar.is_some() {
+                cpi::transfer(token_program, from, to, vault_owner, amount, core::slice::from_ref(&signer))
+            } else {
+                cpi::transfer(token_program, from, to, self.accounts.delegate.unwrap_or(self.accounts.taker), amount, &[])
+            }
+        };
+
+        // taker:mint_b -> maker_ata_b
+        log_compute_units!("take: transfer mint_b");
+        pay(self.accounts.token_program_b, self.accounts.taker_ata_b, self.accounts.maker_ata_b, receive_amount)?;
+
+        // taker:fee mint -> fee account
+        if let (Some(fee_source), Some(fee_account))=(self.accounts.fee_source, self.accounts.fee_account) {
+            log_compute_units!("take: transfer fee");
+            pay(self.accounts.token_program, fee_source, fee_account, TAKE_FLAT_FEE)?;
+        }
+
+        // Safety net: a fee-charging or otherwise short transfer aborts the whole take
+        let maker_b_after=TokenAccountInterface::from_account_view(self.accounts.maker_ata_b)?.amount();
+        check_received(maker_b_before, maker_b_after, receive_amount)?;
+
+        // taker_ata_a:mint_a -> the taker's position, moved by the deposit program
+        // under the taker's signature
+        if let Some(deposit)=self.instruction_data.deposit {
+            log_compute_units!("take: position deposit");
+            self.deposit_position(deposit)?;
+            if TokenAccountInterface::from_account_view(self.accounts.taker_ata_a)?.amount()!=taker_a_before {
+                return Err(EscrowError::DepositIncomplete.into());
+            }
+        }
+
+        // Leave a trace of who took, the escrow's own data may be gone after this take
+        emit_take_event(self.accounts.escrow.address(), self.accounts.taker.address(), fill_amount, receive_amount);
+        if let Some(take_history)=self.accounts.take_history {
+            self.record_taker(take_history, now)?;
+        }
+
+        // A partial fill keeps the escrow open, asking only for the unpaid part (see
+        // `Escrow::apply_fill`). The next fill may come from any taker, validated and
+        // priced on its own against what is left. A persistent escrow also stays open
+        // after the final fill, with an empty vault awaiting `Deposit`
+        if !is_final_fill || escrow.is_persistent() {
+            drop(data);
+            let mut data=self.accounts.escrow.try_borrow_mut()?;
+            Escrow::load_mut(&mut data)?.apply_fill(fill_amount, receive_amount)?;
+            log_compute_units!("take: done");
+            return Ok(());
+        }
+
+        // After the final fill, the vault is empty
         // Close the vault
-        CloseAccount{
-            account: self.accounts.vault,
-            destination: self.accounts.maker,
-            authority: self.accounts.escrow,
-        }.invoke_signed(&[signer.clone()])?;
-        
-        // The vault is closing, so taker should tranfer mint_b to maker
-        Transfer{
-            from: self.accounts.taker_ata_b,
-            to: self.accounts.maker_ata_b,
-            authority: self.accounts.taker,
-            amount,
-        }.invoke()?;
-
-        // Close the Escrow
+        log_compute_units!("take: close vault");
+        cpi::close_account(
+            self.accounts.token_program,
+            self.accounts.vault,
+            self.accounts.treasury.unwrap_or(self.accounts.maker),
+            vault_owner,
+            &[signer],
+        )?;
+
+        // Close the Escrow, returning its rent to the maker unless configured otherwise
         drop(data);
-        ProgramAccount::close(self.accounts.escrow, self.accounts.taker)
+        let rent_destination=match self.accounts.treasury {
+            Some(treasury) => treasury,
+            None if ESCROW_RENT_TO_TAKER => self.accounts.taker,
+            None => self.accounts.maker,
+        };
+        // The token and ATA CPIs ran since the escrow was loaded: make sure it is
+        // still the same program-owned account before handing out its rent
+        ProgramAccount::check_unchanged(self.accounts.escrow, Escrow::LEN)?;
+        ProgramAccount::close(self.accounts.escrow, rent_destination)?;
+        log_compute_units!("take: done");
+        Ok(())
+    }
+
+    /// Invokes the deposit program with the forwarded accounts and the deposit's
+    /// instruction data
+    ///
+    /// Forwarded accounts keep their writability, but only the taker keeps its
+    /// signature: the deposit needs it to move the fill out of taker_ata_a, and no
+    /// other. Any other signer of the take, such as a cosigner or the payer of a
+    /// sponsored transaction, is passed as a plain account. No program-derived
+    /// signature is passed either, so the deposit program can't reach the vault.
+    fn deposit_position(&self, deposit: PositionDeposit) -> ProgramResult {
+        let [program, accounts @ ..]=self.deposit_accounts else {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        };
+        let taker=self.accounts.taker.address();
+        let instruction_accounts: [InstructionAccount; MAX_DEPOSIT_ACCOUNTS]=core::array::from_fn(|index| match accounts.get(index) {
+            Some(account) => InstructionAccount::new(account.address(), account.is_writable(), account.address()==taker),
+            None => InstructionAccount::readonly(program.address()),
+        });
+        let account_views: [&AccountView; MAX_DEPOSIT_ACCOUNTS]=core::array::from_fn(|index| accounts.get(index).unwrap_or(program));
+
+        let instruction=InstructionView {
+            program_id: program.address(),
+            accounts: &instruction_accounts[..accounts.len()],
+            data: deposit.data,
+        };
+        cpi::invoke_deposit(&instruction, &account_views[..accounts.len()])
+    }
+
+    /// Stores the taker in the escrow's take history, creating it on the first take
+    fn record_taker(&self, take_history: &AccountView, now: i64) -> ProgramResult {
+        if take_history.is_data_empty() {
+            SignerAccount::check(self.accounts.taker).map_err(at_account(TakeAccount::Taker as usize))?;
+            let (_, bump)=TakeHistory::address(self.accounts.escrow.address());
+            let bump_binding=[bump];
+            let seeds=[
+                Seed::from(TakeHistory::SEED),
+                Seed::from(self.accounts.escrow.address().as_ref()),
+                Seed::from(&bump_binding),
+            ];
+            cpi::create_account(
+                self.accounts.taker,
+                take_history,
+                TakeHistory::LEN as u64,
+                &crate::ID,
+                &[Signer::from(&seeds)],
+            )?;
+
+            let mut data=take_history.try_borrow_mut()?;
+            let history=TakeHistory::load_mut(&mut data)?;
+            history.set_escrow(self.accounts.escrow.address().clone());
+            history.set_bump(bump_binding);
+        }
+        if !take_history.owned_by(&crate::ID) {
+            return Err(ProgramError::InvalidAccountOwner);
+        }
+
+        let mut data=take_history.try_borrow_mut()?;
+        TakeHistory::load_mut(&mut data)?.set_last_taker(self.accounts.taker.address().clone(), now);
+        Ok(())
     }
 }
 
 pub struct TakeAccounts<'a> {
+    /// Taker's wallet, paying the rent of the accounts Take creates, and then signing
     pub taker: &'a AccountView,
     pub maker: &'a AccountView,
     pub escrow: &'a AccountView,
@@ -112,23 +508,212 @@ pub struct TakeAccounts<'a> {
     pub taker_ata_a: &'a AccountView,
     pub taker_ata_b: &'a AccountView,
     pub maker_ata_b: &'a AccountView,
+    /// Only invoked to create a missing ATA. When `taker_ata_a` and `maker_ata_b`
+    /// both exist it is never used, so any read-only account may stand in for it
     pub system_program: &'a AccountView,
+    /// Token program of mint_a, and of mint_b unless `MULTI_TOKEN_PROGRAM` is set
     pub token_program: &'a AccountView,
+    pub config: &'a AccountView,
+    /// Associated token account program, creating missing ATAs
+    pub associated_token_program: &'a AccountView,
+    /// Token program of mint_b, paying the maker and holding the taker's and maker's
+    /// mint_b accounts. Passed only with `MULTI_TOKEN_PROGRAM`, `token_program` otherwise
+    pub token_program_b: &'a AccountView,
+    /// Treasury PDA receiving the vault and escrow rent, passed only with `RENT_TO_TREASURY`
+    pub treasury: Option<&'a AccountView>,
+    /// Taker's `TAKE_FEE_MINT` token account, passed only with `TAKE_FLAT_FEE`
+    pub fee_source: Option<&'a AccountView>,
+    /// Token account collecting the flat fee, passed only with `TAKE_FLAT_FEE`
+    pub fee_account: Option<&'a AccountView>,
+    /// The escrow's take history PDA, passed only with `RECORD_TAKE_HISTORY`
+    pub take_history: Option<&'a AccountView>,
+    /// Vault authority PDA owning the vault, only passed for keypair escrows
+    pub vault_authority: Option<&'a AccountView>,
+    /// Price oracle, only passed for oracle-priced escrows
+    pub oracle: Option<&'a AccountView>,
+    /// The escrow's cosigner (signer), only passed for escrows with one
+    pub cosigner: Option<&'a AccountView>,
+    /// The escrow's delegate, signing instead of the taker. The taker must have
+    /// approved it as the token delegate of taker_ata_b for at least the payment
+    pub delegate: Option<&'a AccountView>,
+    /// Instructions sysvar, passed instead of a taker signature for a take authorized
+    /// by a `TakePermit`. The taker must have approved the escrow (a keypair escrow's
+    /// vault authority) as token delegate of taker_ata_b for at least the payment
+    pub instructions_sysvar: Option<&'a AccountView>,
+    /// The taker's `PermitNonce` PDA, passed after the instructions sysvar
+    pub permit_nonce: Option<&'a AccountView>,
 }
 
 impl<'a> TryFrom<&'a [AccountView]> for TakeAccounts<'a> {
     type Error = ProgramError;
     fn try_from(accounts: &'a [AccountView]) -> Result<Self, Self::Error> {
-        let [taker, maker, escrow, mint_a, mint_b, vault, taker_ata_a, taker_ata_b, maker_ata_b, system_program, token_program] = accounts else {
-            return Err(ProgramError::NotEnoughAccountKeys);
+        // Failures name the offending account by its position, see `at_account`
+        let [taker, maker, escrow, mint_a, mint_b, vault, taker_ata_a, taker_ata_b, maker_ata_b, system_program, token_program, config, associated_token_program, rest @ ..] = accounts else {
+            return Err(missing_account(accounts.len()));
+        };
+        // No swaps while the program is paused, refunds stay available
+        ConfigAccount::check_not_paused(config).map_err(at_account(TakeAccount::Config as usize))?;
+
+        // The vault and the escrow are both closed at the end of the take, and the
+        // token accounts are each debited or credited once, so none may alias another
+        assert_distinct(&[escrow, vault, taker_ata_a, taker_ata_b, maker_ata_b])?;
+
+        // A vault closed by an earlier take or refund has no token account left to read
+        if vault.is_data_empty() {
+            return Err(EscrowError::VaultClosed.into());
+        }
+
+        ProgramAccount::check(escrow).map_err(at_account(TakeAccount::Escrow as usize))?;
+
+        // Every account Take writes to: ATA rent is paid from the taker's and maker's
+        // side, tokens move between the token accounts and the rent of the closed
+        // vault and escrow goes back out
+        for (position, account) in [
+            (TakeAccount::Taker, taker),
+            (TakeAccount::Maker, maker),
+            (TakeAccount::Escrow, escrow),
+            (TakeAccount::Vault, vault),
+            (TakeAccount::TakerAtaA, taker_ata_a),
+            (TakeAccount::TakerAtaB, taker_ata_b),
+            (TakeAccount::MakerAtaB, maker_ata_b),
+        ] {
+            WritableAccount::check(account, position as usize)?;
+        }
+
+        // Every token account and mint must belong to the token program its
+        // transfers are sent to, so a mint from another token program (e.g. mint_a
+        // on the legacy program and mint_b on Token-2022) is rejected here. With
+        // `MULTI_TOKEN_PROGRAM` mint_b's side has its own token program, passed first
+        let (token_program_b, rest) = if MULTI_TOKEN_PROGRAM {
+            let [token_program_b, rest @ ..] = rest else {
+                return Err(missing_account(TAKE_TOKEN_PROGRAM_B_INDEX));
+            };
+            TokenProgram::check(token_program_b).map_err(at_account(TAKE_TOKEN_PROGRAM_B_INDEX))?;
+            (token_program_b, rest)
+        } else {
+            (token_program, rest)
+        };
+        TokenProgram::check(token_program).map_err(at_account(TakeAccount::TokenProgram as usize))?;
+        AssociatedTokenProgram::check(associated_token_program).map_err(at_account(TakeAccount::AssociatedTokenProgram as usize))?;
+        MintInterface::check(mint_a).map_err(at_account(TakeAccount::MintA as usize))?;
+        MintInterface::check(mint_b).map_err(at_account(TakeAccount::MintB as usize))?;
+        // Ahead of the token program, so a hooked Token-2022 mint reports its hook
+        MintInterface::check_no_transfer_hook(mint_a)?;
+        MintInterface::check_no_transfer_hook(mint_b)?;
+        MintInterface::check_token_program(mint_a, token_program.address())?;
+        MintInterface::check_token_program(mint_b, token_program_b.address())?;
+
+        // Load the escrow first so the vault is validated against the stored mints,
+        // not just whatever mint accounts the caller passed in
+        let data = escrow.try_borrow()?;
+        let escrow_state = Escrow::load(&data).map_err(at_account(TakeAccount::Escrow as usize))?;
+        if escrow_state.mint_a != *mint_a.address() {
+            return Err(invalid_account(TakeAccount::MintA as usize));
+        }
+        if escrow_state.mint_b != *mint_b.address() {
+            return Err(invalid_account(TakeAccount::MintB as usize));
+        }
+
+        // With `RENT_TO_TREASURY` the treasury always comes first
+        let (treasury, rest) = if RENT_TO_TREASURY {
+            let [treasury, rest @ ..] = rest else {
+                return Err(missing_account(TAKE_TREASURY_INDEX));
+            };
+            TreasuryAccount::check(treasury).map_err(at_account(TAKE_TREASURY_INDEX))?;
+            WritableAccount::check(treasury, TAKE_TREASURY_INDEX)?;
+            (Some(treasury), rest)
+        } else {
+            (None, rest)
+        };
+
+        // With `TAKE_FLAT_FEE` the taker's fee source and the fee account come next
+        let (fee_source, fee_account, rest) = if TAKE_FLAT_FEE != 0 {
+            let [fee_source, fee_account, rest @ ..] = rest else {
+                return Err(missing_account(TAKE_FEE_INDEX + rest.len()));
+            };
+            FeeAccount::check_source(fee_source).map_err(at_account(TAKE_FEE_INDEX))?;
+            FeeAccount::check(fee_account).map_err(at_account(TAKE_FEE_INDEX + 1))?;
+            WritableAccount::check(fee_source, TAKE_FEE_INDEX)?;
+            WritableAccount::check(fee_account, TAKE_FEE_INDEX + 1)?;
+            (Some(fee_source), Some(fee_account), rest)
+        } else {
+            (None, None, rest)
+        };
+
+        // With `RECORD_TAKE_HISTORY` the escrow's take history PDA comes next
+        let (take_history, rest) = if RECORD_TAKE_HISTORY {
+            let [take_history, rest @ ..] = rest else {
+                return Err(missing_account(TAKE_HISTORY_INDEX));
+            };
+            if take_history.address() != &TakeHistory::address(escrow.address()).0 {
+                return Err(invalid_account(TAKE_HISTORY_INDEX));
+            }
+            WritableAccount::check(take_history, TAKE_HISTORY_INDEX)?;
+            (Some(take_history), rest)
+        } else {
+            (None, rest)
+        };
+
+        // A keypair escrow's vault belongs to its vault authority
+        let (vault_authority, rest) = if escrow_state.is_keypair() {
+            let [vault_authority, rest @ ..] = rest else {
+                return Err(missing_account(TAKE_VAULT_AUTHORITY_INDEX));
+            };
+            if vault_authority.address() != &Escrow::vault_authority(escrow.address()).0 {
+                return Err(invalid_account(TAKE_VAULT_AUTHORITY_INDEX));
+            }
+            (Some(vault_authority), rest)
+        } else {
+            (None, rest)
+        };
+
+        // Trailing accounts: the oracle of an oracle-priced escrow, the cosigner of a
+        // cosigned one, then either the delegate taking on behalf of the taker or the
+        // instructions sysvar of a permit followed by the taker's permit nonce
+        let (oracle, rest) = match rest.split_first() {
+            Some((oracle, rest)) if escrow_state.uses_oracle() => (Some(oracle), rest),
+            _ => (None, rest),
         };
+        let (cosigner, rest) = if escrow_state.has_cosigner() {
+            let cosigner_index = TAKE_VAULT_AUTHORITY_INDEX + escrow_state.is_keypair() as usize + oracle.is_some() as usize;
+            let [cosigner, rest @ ..] = rest else {
+                return Err(missing_account(cosigner_index));
+            };
+            // Whatever path authorizes the take, the cosigner signs it too
+            if cosigner.address() != &escrow_state.cosigner || !cosigner.is_signer() {
+                return Err(EscrowError::CosignerRequired.into());
+            }
+            (Some(cosigner), rest)
+        } else {
+            (None, rest)
+        };
+        let (delegate, instructions_sysvar, permit_nonce) = match rest {
+            [] => (None, None, None),
+            [instructions_sysvar, permit_nonce] if instructions_sysvar.address() == &INSTRUCTIONS_ID => {
+                PermitNonceAccount::check(permit_nonce, taker.address()).map_err(at_account(accounts.len() - 1))?;
+                (None, Some(instructions_sysvar), Some(permit_nonce))
+            }
+            [instructions_sysvar] if instructions_sysvar.address() == &INSTRUCTIONS_ID => return Err(missing_account(accounts.len())),
+            [delegate] => (Some(delegate), None, None),
+            _ => return Err(ProgramError::InvalidArgument),
+        };
+
+        // The taker signs, the escrow's delegate signs for them, or a permit the
+        // taker signed off-chain is checked once the amounts are known
+        match delegate {
+            Some(delegate) => {
+                if !escrow_state.has_delegate() || escrow_state.delegate != *delegate.address() {
+                    return Err(ProgramError::IncorrectAuthority);
+                }
+                SignerAccount::check(delegate).map_err(at_account(accounts.len() - 1))?;
+            }
+            None if instructions_sysvar.is_some() => {}
+            None => SignerAccount::check(taker).map_err(at_account(TakeAccount::Taker as usize))?,
+        }
+        drop(data);
 
-        SignerAccount::check(taker)?;
-        ProgramAccount::check(escrow)?;
-        MintInterface::check(mint_a)?;
-        MintInterface::check(mint_b)?;
-        AssociatedTokenAccount::check(taker_ata_b,taker,mint_b,token_program)?;
-        AssociatedTokenAccount::check(vault,escrow,mint_a,token_program)?;
+        AssociatedTokenAccount::check(taker_ata_b,taker,mint_b,token_program_b).map_err(at_account(TakeAccount::TakerAtaB as usize))?;
+        AssociatedTokenAccount::check(vault,vault_authority.unwrap_or(escrow),mint_a,token_program).map_err(at_account(TakeAccount::Vault as usize))?;
 
         Ok(Self {
             taker,
@@ -142,6 +727,834 @@ impl<'a> TryFrom<&'a [AccountView]> for TakeAccounts<'a> {
             maker_ata_b,
             system_program,
             token_program,
+            config,
+            associated_token_program,
+            token_program_b,
+            treasury,
+            fee_source,
+            fee_account,
+            take_history,
+            vault_authority,
+            oracle,
+            cosigner,
+            delegate,
+            instructions_sysvar,
+            permit_nonce,
         })
     }
 }
+
+/// Deposit of the taken mint_a into a position held by a `DEPOSIT_PROGRAMS` program
+///
+/// The fill lands in taker_ata_a as usual, then Take invokes the deposit program
+/// with the forwarded accounts and `data`. That instruction must move the whole
+/// fill out of taker_ata_a with the taker's signature, so the taker signs the take.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PositionDeposit<'a> {
+    /// Number of accounts forwarded to the deposit program, after the program itself
+    pub account_count: u8,
+    /// Instruction data of the deposit instruction, forwarded as is
+    pub data: &'a [u8],
+}
+
+/// Instruction data for the Take instruction
+///
+/// The default, sent as empty data, is a full take with no constraints.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TakeInstructionData<'a> {
+    /// Amount of mint_a to take from the vault, `None` takes everything left
+    pub fill: Option<u64>,
+    /// Smallest amount of mint_a the taker accepts to receive (0 = no minimum)
+    pub min_amount_a_out: u64,
+    /// Unix timestamp after which the taker no longer wants the take to execute (0 = no limit)
+    pub valid_until: i64,
+    /// Deposit of the fill into the taker's position, instead of leaving it in taker_ata_a
+    pub deposit: Option<PositionDeposit<'a>>,
+}
+
+impl<'a> TryFrom<&'a [u8]> for TakeInstructionData<'a> {
+    type Error = ProgramError;
+
+    /// Wire format (little endian), one of:
+    /// - empty: full take
+    /// - `[0..8]`: fill amount (u64)
+    /// - `[0..8]`: fill amount (u64, 0 = everything left), `[8..16]`: min_amount_a_out (u64)
+    /// - the same, then `[16..24]`: valid_until (i64, 0 = no limit)
+    /// - the same, then `[24]`: number of accounts forwarded to the deposit program
+    ///   (u8, at most `MAX_DEPOSIT_ACCOUNTS`), `[25..]`: the deposit's instruction data
+    fn try_from(data: &'a [u8]) -> Result<Self, Self::Error> {
+        let (data, deposit) = match data.split_at_checked(24) {
+            Some((data, [account_count, deposit_data @ ..])) => {
+                if *account_count as usize > MAX_DEPOSIT_ACCOUNTS {
+                    return Err(ProgramError::InvalidInstructionData);
+                }
+                (data, Some(PositionDeposit { account_count: *account_count, data: deposit_data }))
+            }
+            _ => (data, None),
+        };
+        let (fill, min_amount_a_out, valid_until) = match data.len() {
+            0 => return Ok(Self::default()),
+            8 => (Some(u64::from_le_bytes(data.try_into().map_err(|_| ProgramError::InvalidInstructionData)?)), 0, 0),
+            16 | 24 => {
+                let fill = u64::from_le_bytes(data[0..8].try_into().map_err(|_| ProgramError::InvalidInstructionData)?);
+                let min_amount_a_out = u64::from_le_bytes(data[8..16].try_into().map_err(|_| ProgramError::InvalidInstructionData)?);
+                let valid_until = match data.get(16..24) {
+                    Some(value) => i64::from_le_bytes(value.try_into().map_err(|_| ProgramError::InvalidInstructionData)?),
+                    None => 0,
+                };
+                ((fill != 0).then_some(fill), min_amount_a_out, valid_until)
+            }
+            _ => return Err(ProgramError::InvalidInstructionData),
+        };
+
+        Ok(Self { fill, min_amount_a_out, valid_until, deposit })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instructions::make::TRANSFER_HOOK_EXTENSION;
+    use crate::{error::readonly_account, constants::{ASSOCIATED_TOKEN_PROGRAM_ID, SYSTEM_PROGRAM_ID, TOKEN_PROGRAM_ID}, state::{PermitNonce, ReceiveAmount}, test_utils::*};
+
+    const SEED: u64 = 7;
+
+    /// A take of a plain escrow: maker 1 sells the 10 mint_a (3) in its vault for
+    /// 20 mint_b (4) to taker 2. `accounts` are in `TakeAccount` order.
+    struct Fixture {
+        accounts: Vec<TestAccount>,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            Self::with_escrow(|_| {})
+        }
+
+        /// The plain escrow, then edited by `edit`
+        fn with_escrow(edit: impl FnOnce(&mut Escrow)) -> Self {
+            let (maker, taker, mint_a, mint_b) = (address(1), address(2), address(3), address(4));
+            let (escrow, bump) = escrow_address(&maker, SEED);
+            let data = escrow_data(|state| {
+                state.set_inner(SEED, maker.clone(), mint_a.clone(), mint_b.clone(), ReceiveAmount(20), 0, 0, [bump]);
+                edit(state);
+            });
+            let mut accounts = vec![
+                TestAccount::wallet(taker.clone()).signer().writable(),
+                TestAccount::wallet(maker.clone()).writable(),
+                TestAccount::new(escrow.clone(), crate::ID, 1, &data).writable(),
+                mint(mint_a.clone()),
+                mint(mint_b.clone()),
+                vault(&escrow, &mint_a, 10),
+                token_account(address(6), &mint_a, &taker, 0),
+                token_account(address(7), &mint_b, &taker, 20),
+                token_account(address(8), &mint_b, &maker, 0),
+                TestAccount::program(SYSTEM_PROGRAM_ID),
+                TestAccount::program(TOKEN_PROGRAM_ID),
+                config(),
+                TestAccount::program(ASSOCIATED_TOKEN_PROGRAM_ID),
+            ];
+            // mint_b's token program, first of the optional accounts
+            if MULTI_TOKEN_PROGRAM {
+                accounts.push(TestAccount::program(TOKEN_PROGRAM_ID));
+            }
+            Self { accounts }
+        }
+
+        fn views(&self) -> Vec<AccountView> {
+            self.accounts.iter().map(TestAccount::view).collect()
+        }
+    }
+
+    /// Runs a Take of `fill` mint_a (all of it for `None`) over `views`
+    fn take(views: &[AccountView], fill: Option<u64>) -> ProgramResult {
+        let data = fill.map(u64::to_le_bytes).unwrap_or_default();
+        let len = if fill.is_some() { 8 } else { 0 };
+        Take::try_from((views, &data[..len]))?.process()
+    }
+
+    #[test]
+    fn parses_each_instruction_data_form() {
+        fn parse(data: &[u8]) -> Result<TakeInstructionData<'_>, ProgramError> {
+            TakeInstructionData::try_from(data)
+        }
+        let bytes = |values: &[u64]| values.iter().flat_map(|value| value.to_le_bytes()).collect::<Vec<u8>>();
+        assert_eq!(parse(&[]), Ok(TakeInstructionData::default()));
+        assert_eq!(parse(&bytes(&[4])), Ok(TakeInstructionData { fill: Some(4), ..Default::default() }));
+        // A fill of 0 is everything left once a minimum follows
+        assert_eq!(parse(&bytes(&[0, 3])), Ok(TakeInstructionData { fill: None, min_amount_a_out: 3, ..Default::default() }));
+        assert_eq!(
+            parse(&bytes(&[4, 3, 1_000])),
+            Ok(TakeInstructionData { fill: Some(4), min_amount_a_out: 3, valid_until: 1_000, deposit: None }),
+        );
+        let data = [bytes(&[4, 3, 0]), vec![2, 9, 9]].concat();
+        assert_eq!(parse(&data).unwrap().deposit, Some(PositionDeposit { account_count: 2, data: &[9, 9] }));
+
+        for len in [1, 7, 9, 15, 17, 23] {
+            assert_eq!(parse(&vec![0; len]), Err(ProgramError::InvalidInstructionData), "{len} bytes");
+        }
+        let data = [bytes(&[4, 3, 0]), vec![MAX_DEPOSIT_ACCOUNTS as u8 + 1]].concat();
+        assert_eq!(parse(&data), Err(ProgramError::InvalidInstructionData));
+    }
+
+    #[test]
+    fn enforces_min_fill_except_on_the_remainder() {
+        let fixture = Fixture::with_escrow(|escrow| {
+            escrow.set_flags(Escrow::FLAG_PARTIAL_FILL);
+            escrow.set_min_fill(4);
+        });
+        let views = fixture.views();
+        assert_eq!(take(&views, Some(3)), Err(EscrowError::FillBelowMinimum.into()));
+
+        // 7 leaves 3 in the vault, below min_fill but the remainder
+        take(&views, Some(7)).unwrap();
+        assert_eq!(balance(&views[TakeAccount::TakerAtaA as usize]), 7);
+        take(&views, Some(3)).unwrap();
+        assert_eq!(balance(&views[TakeAccount::TakerAtaA as usize]), 10);
+        assert_eq!(balance(&views[TakeAccount::MakerAtaB as usize]), 20);
+        assert!(views[TakeAccount::Escrow as usize].owned_by(&SYSTEM_PROGRAM_ID));
+    }
+
+    #[test]
+    fn dispatches_the_native_discriminator() {
+        let fixture = Fixture::new();
+        let views = fixture.views();
+        crate::process_instructions(&crate::ID, &views, &[crate::TAKE_DISCRIMINATOR]).unwrap();
+        assert_eq!(balance(&views[TakeAccount::TakerAtaA as usize]), 10);
+    }
+
+    #[cfg(feature = "anchor-compat")]
+    #[test]
+    fn dispatches_the_anchor_discriminator() {
+        let fixture = Fixture::new();
+        let views = fixture.views();
+        crate::process_instructions(&crate::ID, &views, &crate::ANCHOR_TAKE_DISCRIMINATOR).unwrap();
+        assert_eq!(balance(&views[TakeAccount::TakerAtaA as usize]), 10);
+    }
+
+    #[test]
+    fn moves_no_tokens_when_maker_ata_b_cant_be_created() {
+        let mut fixture = Fixture::new();
+        // The taker can fund the rent of taker_ata_a, but not maker_ata_b's too
+        fixture.accounts[TakeAccount::Taker as usize] = TestAccount::new(address(2), Address::default(), rent(TokenAccount::LEN), &[]).signer().writable();
+        fixture.accounts[TakeAccount::TakerAtaA as usize] = TestAccount::new(ata_address(&address(2), &address(3)), Address::default(), 0, &[]).writable();
+        fixture.accounts[TakeAccount::MakerAtaB as usize] = TestAccount::new(ata_address(&address(1), &address(4)), Address::default(), 0, &[]).writable();
+        let views = fixture.views();
+
+        // The system program's insufficient lamports, once taker_ata_a exists
+        assert_eq!(take(&views, None), Err(ProgramError::Custom(1)));
+        assert!(!views[TakeAccount::TakerAtaA as usize].is_data_empty());
+        assert_eq!(balance(&views[TakeAccount::Vault as usize]), 10);
+        assert_eq!(balance(&views[TakeAccount::TakerAtaB as usize]), 20);
+        assert!(views[TakeAccount::MakerAtaB as usize].is_data_empty());
+    }
+
+    #[test]
+    fn creates_the_missing_atas_for_their_owners() {
+        let mut fixture = Fixture::new();
+        fixture.accounts[TakeAccount::TakerAtaA as usize] = TestAccount::new(ata_address(&address(2), &address(3)), Address::default(), 0, &[]).writable();
+        fixture.accounts[TakeAccount::MakerAtaB as usize] = TestAccount::new(ata_address(&address(1), &address(4)), Address::default(), 0, &[]).writable();
+        let views = fixture.views();
+        let taker_lamports = views[TakeAccount::Taker as usize].lamports();
+
+        take(&views, None).unwrap();
+
+        let owner = |account: TakeAccount| TokenAccount::from_account_view(&views[account as usize]).unwrap().owner().clone();
+        assert_eq!(owner(TakeAccount::TakerAtaA), address(2));
+        assert_eq!(owner(TakeAccount::MakerAtaB), address(1));
+        assert_eq!(balance(&views[TakeAccount::TakerAtaA as usize]), 10);
+        assert_eq!(balance(&views[TakeAccount::MakerAtaB as usize]), 20);
+        // The taker paid the rent of both
+        assert_eq!(views[TakeAccount::Taker as usize].lamports(), taker_lamports - 2 * rent(TokenAccount::LEN));
+    }
+
+    #[test]
+    fn the_signing_taker_funds_maker_ata_b() {
+        let mut fixture = Fixture::with_escrow(|escrow| escrow.set_flags(Escrow::FLAG_PARTIAL_FILL));
+        fixture.accounts[TakeAccount::MakerAtaB as usize] = TestAccount::new(ata_address(&address(1), &address(4)), Address::default(), 0, &[]).writable();
+        let views = fixture.views();
+        let lamports = |account: TakeAccount| views[account as usize].lamports();
+        let (maker, taker) = (lamports(TakeAccount::Maker), lamports(TakeAccount::Taker));
+
+        // A partial fill, so no rent flows back to the maker
+        take(&views, Some(4)).unwrap();
+        assert_eq!(lamports(TakeAccount::Taker), taker - rent(TokenAccount::LEN));
+        assert_eq!(lamports(TakeAccount::Maker), maker);
+        assert_eq!(lamports(TakeAccount::MakerAtaB), rent(TokenAccount::LEN));
+
+        // Taking through its delegate, the taker doesn't sign and can't be charged
+        let mut fixture = Fixture::with_escrow(|escrow| escrow.set_delegate(address(12)));
+        fixture.accounts[TakeAccount::Taker as usize] = TestAccount::wallet(address(2)).writable();
+        fixture.accounts[TakeAccount::TakerAtaB as usize] = delegated(token_account(address(7), &address(4), &address(2), 20), &address(12), 20);
+        fixture.accounts[TakeAccount::MakerAtaB as usize] = TestAccount::new(ata_address(&address(1), &address(4)), Address::default(), 0, &[]).writable();
+        fixture.accounts.push(TestAccount::wallet(address(12)).signer());
+        let views = fixture.views();
+        assert_eq!(take(&views, None), Err(invalid_account(TakeAccount::Taker as usize)));
+        assert!(views[TakeAccount::MakerAtaB as usize].is_data_empty());
+        assert_eq!(views[TakeAccount::Taker as usize].lamports(), 1_000_000_000);
+    }
+
+    #[test]
+    fn pays_only_into_the_makers_own_mint_b_account() {
+        let mut fixture = Fixture::new();
+        // A taker's account passed as the maker's
+        fixture.accounts[TakeAccount::MakerAtaB as usize] = token_account(address(8), &address(4), &address(2), 0);
+        let views = fixture.views();
+        assert_eq!(take(&views, None), Err(ProgramError::InvalidAccountData));
+        assert_eq!(balance(&views[TakeAccount::Vault as usize]), 10);
+        assert_eq!(balance(&views[TakeAccount::TakerAtaB as usize]), 20);
+    }
+
+    #[test]
+    fn returns_the_escrow_and_vault_rent_to_the_maker() {
+        let fixture = Fixture::new();
+        let views = fixture.views();
+        let lamports = |account: TakeAccount| views[account as usize].lamports();
+        let (maker, taker) = (lamports(TakeAccount::Maker), lamports(TakeAccount::Taker));
+        let rent = lamports(TakeAccount::Escrow) + lamports(TakeAccount::Vault);
+
+        take(&views, None).unwrap();
+        assert_eq!(lamports(TakeAccount::Maker), maker + rent);
+        assert_eq!(lamports(TakeAccount::Taker), taker);
+        assert_eq!(lamports(TakeAccount::Escrow), 0);
+    }
+
+    #[test]
+    fn rejects_a_taker_ata_a_of_another_mint() {
+        let mut fixture = Fixture::new();
+        fixture.accounts[TakeAccount::TakerAtaA as usize] = token_account(address(6), &address(4), &address(2), 0);
+        let views = fixture.views();
+        assert_eq!(take(&views, None), Err(EscrowError::MintMismatch.into()));
+        assert_eq!(balance(&views[TakeAccount::Vault as usize]), 10);
+    }
+
+    #[test]
+    fn needs_the_system_program_only_to_create_an_ata() {
+        // Both ATAs exist: the token program stands in for the system program
+        let mut fixture = Fixture::new();
+        fixture.accounts[TakeAccount::SystemProgram as usize] = TestAccount::program(TOKEN_PROGRAM_ID);
+        let views = fixture.views();
+        take(&views, None).unwrap();
+        assert_eq!(balance(&views[TakeAccount::TakerAtaA as usize]), 10);
+
+        let mut fixture = Fixture::new();
+        fixture.accounts[TakeAccount::SystemProgram as usize] = TestAccount::program(TOKEN_PROGRAM_ID);
+        fixture.accounts[TakeAccount::MakerAtaB as usize] = TestAccount::new(ata_address(&address(1), &address(4)), Address::default(), 0, &[]).writable();
+        assert_eq!(take(&fixture.views(), None), Err(invalid_account(TakeAccount::SystemProgram as usize)));
+    }
+
+    #[test]
+    fn checks_the_maker_received_the_full_amount() {
+        assert_eq!(check_received(5, 25, 20), Ok(()));
+        // 1% withheld by a transfer fee
+        assert_eq!(check_received(5, 24, 20), Err(EscrowError::ReceivedAmountMismatch.into()));
+        assert_eq!(check_received(25, 5, 20), Err(EscrowError::ReceivedAmountMismatch.into()));
+
+        // A standard mint pays in full
+        let fixture = Fixture::new();
+        let views = fixture.views();
+        take(&views, None).unwrap();
+        assert_eq!(balance(&views[TakeAccount::MakerAtaB as usize]), 20);
+    }
+
+    #[test]
+    fn a_delegate_takes_on_behalf_of_the_taker() {
+        let mut fixture = Fixture::with_escrow(|escrow| escrow.set_delegate(address(12)));
+        // The taker doesn't sign, but approved the market maker 12 on its mint_b
+        fixture.accounts[TakeAccount::Taker as usize] = TestAccount::wallet(address(2)).writable();
+        fixture.accounts[TakeAccount::TakerAtaB as usize] = delegated(token_account(address(7), &address(4), &address(2), 20), &address(12), 20);
+        fixture.accounts.push(TestAccount::wallet(address(12)).signer());
+        let views = fixture.views();
+
+        take(&views, None).unwrap();
+        assert_eq!(balance(&views[TakeAccount::TakerAtaA as usize]), 10);
+        assert_eq!(balance(&views[TakeAccount::TakerAtaB as usize]), 0);
+        assert_eq!(balance(&views[TakeAccount::MakerAtaB as usize]), 20);
+
+        // Any other signer isn't the escrow's delegate
+        let mut fixture = Fixture::with_escrow(|escrow| escrow.set_delegate(address(12)));
+        fixture.accounts[TakeAccount::Taker as usize] = TestAccount::wallet(address(2)).writable();
+        fixture.accounts.push(TestAccount::wallet(address(13)).signer());
+        assert_eq!(take(&fixture.views(), None), Err(ProgramError::IncorrectAuthority));
+    }
+
+    #[test]
+    fn enforces_the_takers_minimum_output() {
+        let fixture = Fixture::new();
+        let views = fixture.views();
+        // Everything left, at least 11
+        let data = [0u64.to_le_bytes(), 11u64.to_le_bytes()].concat();
+        assert_eq!(Take::try_from((&views[..], &data[..])).unwrap().process(), Err(EscrowError::OutputBelowMinimum.into()));
+        assert_eq!(balance(&views[TakeAccount::Vault as usize]), 10);
+
+        let data = [0u64.to_le_bytes(), 10u64.to_le_bytes()].concat();
+        Take::try_from((&views[..], &data[..])).unwrap().process().unwrap();
+        assert_eq!(balance(&views[TakeAccount::TakerAtaA as usize]), 10);
+    }
+
+    #[test]
+    fn rejects_a_take_past_the_takers_deadline() {
+        let fixture = Fixture::new();
+        let views = fixture.views();
+        // Everything left, no minimum, valid until a second ago
+        let data = [0u64.to_le_bytes(), 0u64.to_le_bytes(), (now() - 1).to_le_bytes()].concat();
+        assert_eq!(Take::try_from((&views[..], &data[..])).unwrap().process(), Err(EscrowError::TakeDeadlinePassed.into()));
+        assert_eq!(balance(&views[TakeAccount::Vault as usize]), 10);
+        assert_eq!(balance(&views[TakeAccount::TakerAtaB as usize]), 20);
+    }
+
+    #[test]
+    fn takes_up_to_the_takers_deadline() {
+        let fixture = Fixture::new();
+        let views = fixture.views();
+        // The deadline itself still executes
+        let data = [0u64.to_le_bytes(), 0u64.to_le_bytes(), now().to_le_bytes()].concat();
+        Take::try_from((&views[..], &data[..])).unwrap().process().unwrap();
+        assert_eq!(balance(&views[TakeAccount::TakerAtaA as usize]), 10);
+        assert_eq!(balance(&views[TakeAccount::MakerAtaB as usize]), 20);
+    }
+
+    #[cfg(feature = "compute-log")]
+    #[test]
+    fn logs_compute_units_around_each_cpi() {
+        let fixture = Fixture::new();
+        take(&fixture.views(), None).unwrap();
+        assert_eq!(
+            compute_logs(),
+            [
+                "take: init taker_ata_a",
+                "take: init maker_ata_b",
+                "take: transfer mint_a",
+                "take: transfer mint_b",
+                "take: close vault",
+                "take: done",
+            ],
+        );
+    }
+
+    #[test]
+    fn rejects_an_underfunded_taker() {
+        let mut fixture = Fixture::new();
+        fixture.accounts[TakeAccount::TakerAtaB as usize] = token_account(address(7), &address(4), &address(2), 19);
+        let views = fixture.views();
+        assert_eq!(take(&views, None), Err(ProgramError::InsufficientFunds));
+        assert_eq!(balance(&views[TakeAccount::Vault as usize]), 10);
+    }
+
+    #[test]
+    fn fee_accounts_hold_the_fee_mint() {
+        let fee_account = token_account(address(12), &TAKE_FEE_MINT, &TAKE_FEE_RECIPIENT, 0);
+        assert_eq!(FeeAccount::check(&fee_account.view()), Ok(()));
+        let other_owner = token_account(address(12), &TAKE_FEE_MINT, &address(2), 0);
+        assert_eq!(FeeAccount::check(&other_owner.view()), Err(ProgramError::IllegalOwner));
+        let other_mint = token_account(address(12), &address(4), &TAKE_FEE_RECIPIENT, 0);
+        assert_eq!(FeeAccount::check(&other_mint.view()), Err(EscrowError::MintMismatch.into()));
+
+        // The taker pays from any account of the fee mint
+        assert_eq!(FeeAccount::check_source(&token_account(address(13), &TAKE_FEE_MINT, &address(2), 5).view()), Ok(()));
+        assert_eq!(FeeAccount::check_source(&token_account(address(13), &address(4), &address(2), 5).view()), Err(EscrowError::MintMismatch.into()));
+    }
+
+    #[test]
+    fn prices_an_oracle_escrow_at_the_oracle_price() {
+        // 2 mint_b per mint_a, at 1.5x
+        let mut fixture = Fixture::with_escrow(|escrow| escrow.set_oracle(address(12), 15_000));
+        fixture.accounts[TakeAccount::TakerAtaB as usize] = token_account(address(7), &address(4), &address(2), 40);
+        let price = [&2u64.to_le_bytes()[..], &[0]].concat();
+        fixture.accounts.push(TestAccount::new(address(12), address(13), 1, &price));
+        let views = fixture.views();
+
+        take(&views, None).unwrap();
+        assert_eq!(balance(&views[TakeAccount::MakerAtaB as usize]), 30);
+        assert_eq!(balance(&views[TakeAccount::TakerAtaA as usize]), 10);
+    }
+
+    #[test]
+    fn rejects_an_oracle_other_than_the_escrow_one() {
+        let mut fixture = Fixture::with_escrow(|escrow| escrow.set_oracle(address(12), 15_000));
+        let price = [&2u64.to_le_bytes()[..], &[0]].concat();
+        fixture.accounts.push(TestAccount::new(address(14), address(13), 1, &price));
+        assert_eq!(take(&fixture.views(), None), Err(ProgramError::InvalidAccountData));
+    }
+
+    #[test]
+    fn rejects_takes_while_paused() {
+        let mut fixture = Fixture::new();
+        fixture.accounts[TakeAccount::Config as usize] = initialized_config(true);
+        assert_eq!(
+            TakeAccounts::try_from(&fixture.views()[..]).err(),
+            Some(EscrowError::ProgramPaused.into()),
+        );
+
+        fixture.accounts[TakeAccount::Config as usize] = initialized_config(false);
+        assert!(TakeAccounts::try_from(&fixture.views()[..]).is_ok());
+    }
+
+    #[test]
+    fn accepts_the_escrow_mints() {
+        let fixture = Fixture::new();
+        assert!(TakeAccounts::try_from(&fixture.views()[..]).is_ok());
+    }
+
+    #[test]
+    fn rejects_a_mint_of_another_token_program() {
+        let mut fixture = Fixture::new();
+        fixture.accounts[TakeAccount::MintB as usize] = mint_2022(address(4), &[]);
+        assert_eq!(
+            TakeAccounts::try_from(&fixture.views()[..]).err(),
+            Some(ProgramError::IncorrectProgramId),
+        );
+    }
+
+    #[cfg(feature = "multi-token-program")]
+    #[test]
+    fn pays_a_token_2022_mint_b_through_its_own_program() {
+        use crate::constants::TOKEN_2022_PROGRAM_ID;
+        let mut fixture = Fixture::new();
+        let maker_ata_b = ata_address_of(&address(1), &address(4), &TOKEN_2022_PROGRAM_ID);
+        fixture.accounts[TakeAccount::MintB as usize] = mint_2022(address(4), &[]);
+        fixture.accounts[TakeAccount::TakerAtaB as usize] = token_account_2022(address(7), &address(4), &address(2), 20);
+        fixture.accounts[TakeAccount::MakerAtaB as usize] = TestAccount::new(maker_ata_b, Address::default(), 0, &[]).writable();
+        // Handed mint_a's program for mint_b, the transfers would go astray
+        assert_eq!(take(&fixture.views(), None), Err(ProgramError::IncorrectProgramId));
+
+        fixture.accounts[TAKE_TOKEN_PROGRAM_B_INDEX] = TestAccount::program(TOKEN_2022_PROGRAM_ID);
+        let views = fixture.views();
+        take(&views, None).unwrap();
+
+        // The maker's mint_b account was created under Token-2022, and paid through it
+        let maker_ata_b = &views[TakeAccount::MakerAtaB as usize];
+        assert!(maker_ata_b.owned_by(&TOKEN_2022_PROGRAM_ID));
+        assert_eq!(balance(maker_ata_b), 20);
+        assert_eq!(balance(&views[TakeAccount::TakerAtaB as usize]), 0);
+        assert_eq!(balance(&views[TakeAccount::TakerAtaA as usize]), 10);
+    }
+
+    #[test]
+    fn rejects_a_transfer_hook_mint() {
+        let mut fixture = Fixture::new();
+        fixture.accounts[TakeAccount::MintB as usize] = mint_2022(address(4), &[TRANSFER_HOOK_EXTENSION]);
+        assert_eq!(
+            TakeAccounts::try_from(&fixture.views()[..]).err(),
+            Some(EscrowError::TransferHookNotSupported.into()),
+        );
+    }
+
+    #[test]
+    fn rejects_a_mint_a_other_than_the_escrow_one() {
+        let mut fixture = Fixture::new();
+        // A valid mint, just not the one the escrow trades
+        fixture.accounts[TakeAccount::MintA as usize] = mint(address(9));
+        assert_eq!(
+            TakeAccounts::try_from(&fixture.views()[..]).err(),
+            Some(invalid_account(TakeAccount::MintA as usize)),
+        );
+    }
+
+    #[test]
+    fn reports_the_position_of_the_offending_account() {
+        let fixture = Fixture::new();
+        let views = fixture.views();
+        assert_eq!(TakeAccounts::try_from(&views[..9]).err(), Some(missing_account(9)));
+
+        let mut fixture = Fixture::new();
+        fixture.accounts[TakeAccount::Taker as usize] = TestAccount::wallet(address(2)).writable();
+        assert_eq!(TakeAccounts::try_from(&fixture.views()[..]).err(), Some(invalid_account(TakeAccount::Taker as usize)));
+
+        let mut fixture = Fixture::new();
+        fixture.accounts[TakeAccount::TokenProgram as usize] = TestAccount::program(address(9));
+        assert_eq!(TakeAccounts::try_from(&fixture.views()[..]).err(), Some(invalid_account(TakeAccount::TokenProgram as usize)));
+
+        // A token account of mint_a where the taker's mint_b account is expected
+        let mut fixture = Fixture::new();
+        fixture.accounts[TakeAccount::TakerAtaB as usize] = token_account(address(7), &address(3), &address(2), 20);
+        assert_eq!(TakeAccounts::try_from(&fixture.views()[..]).err(), Some(invalid_account(TakeAccount::TakerAtaB as usize)));
+    }
+
+    #[test]
+    fn rejects_a_closed_vault() {
+        let mut fixture = Fixture::new();
+        let (escrow, _) = escrow_address(&address(1), SEED);
+        fixture.accounts[TakeAccount::Vault as usize] = TestAccount::new(ata_address(&escrow, &address(3)), Address::default(), 0, &[]).writable();
+        assert_eq!(take(&fixture.views(), None), Err(EscrowError::VaultClosed.into()));
+    }
+
+    #[test]
+    fn takes_a_keypair_escrow_through_its_vault_authority() {
+        let mut fixture = Fixture::new();
+        let vault_authority = Escrow::vault_authority(&address(30)).0;
+        fixture.accounts[TakeAccount::Escrow as usize] = keypair_escrow(address(30), &address(1), |_| {});
+        fixture.accounts[TakeAccount::Vault as usize] = vault(&vault_authority, &address(3), 10);
+        assert_eq!(take(&fixture.views(), None), Err(missing_account(TAKE_VAULT_AUTHORITY_INDEX)));
+        fixture.accounts.push(TestAccount::wallet(address(31)));
+        assert_eq!(take(&fixture.views(), None), Err(invalid_account(TAKE_VAULT_AUTHORITY_INDEX)));
+
+        fixture.accounts[TAKE_VAULT_AUTHORITY_INDEX] = TestAccount::wallet(vault_authority);
+        let views = fixture.views();
+        take(&views, None).unwrap();
+        assert_eq!(balance(&views[TakeAccount::TakerAtaA as usize]), 10);
+        assert_eq!(balance(&views[TakeAccount::MakerAtaB as usize]), 20);
+        assert!(views[TakeAccount::Escrow as usize].owned_by(&SYSTEM_PROGRAM_ID));
+        assert!(views[TakeAccount::Vault as usize].owned_by(&SYSTEM_PROGRAM_ID));
+    }
+
+    #[test]
+    fn take_account_positions_match_the_accounts() {
+        let fixture = Fixture::new();
+        let views = fixture.views();
+        let take = TakeAccounts::try_from(&views[..]).unwrap();
+        for (position, account) in [
+            (TakeAccount::Taker, take.taker),
+            (TakeAccount::Maker, take.maker),
+            (TakeAccount::Escrow, take.escrow),
+            (TakeAccount::MintA, take.mint_a),
+            (TakeAccount::MintB, take.mint_b),
+            (TakeAccount::Vault, take.vault),
+            (TakeAccount::TakerAtaA, take.taker_ata_a),
+            (TakeAccount::TakerAtaB, take.taker_ata_b),
+            (TakeAccount::MakerAtaB, take.maker_ata_b),
+            (TakeAccount::SystemProgram, take.system_program),
+            (TakeAccount::TokenProgram, take.token_program),
+            (TakeAccount::Config, take.config),
+            (TakeAccount::AssociatedTokenProgram, take.associated_token_program),
+        ] {
+            assert_eq!(account.address(), views[position as usize].address(), "{position:?}");
+        }
+        // mint_b's token program is mint_a's, unless passed on its own
+        let token_program_b = if MULTI_TOKEN_PROGRAM { TAKE_TOKEN_PROGRAM_B_INDEX } else { TakeAccount::TokenProgram as usize };
+        assert_eq!(take.token_program_b.address(), views[token_program_b].address());
+        assert_eq!(views.len(), TAKE_TREASURY_INDEX);
+    }
+
+    #[test]
+    fn names_the_account_that_must_be_writable() {
+        for position in [
+            TakeAccount::Taker,
+            TakeAccount::Maker,
+            TakeAccount::Escrow,
+            TakeAccount::Vault,
+            TakeAccount::TakerAtaA,
+            TakeAccount::TakerAtaB,
+            TakeAccount::MakerAtaB,
+        ] {
+            let mut fixture = Fixture::new();
+            let account = fixture.accounts.remove(position as usize).readonly();
+            fixture.accounts.insert(position as usize, account);
+            assert_eq!(TakeAccounts::try_from(&fixture.views()[..]).err(), Some(readonly_account(position as usize)), "{position:?}");
+        }
+    }
+
+    #[cfg(feature = "client")]
+    #[test]
+    fn the_client_preview_matches_the_take() {
+        let fixture = Fixture::with_escrow(|escrow| escrow.set_flags(Escrow::FLAG_PARTIAL_FILL));
+        let views = fixture.views();
+        let preview = |fill| {
+            let data = views[TakeAccount::Escrow as usize].try_borrow().unwrap();
+            crate::client::preview_take(&Escrow::load_unaligned(&data).unwrap(), balance(&views[TakeAccount::Vault as usize]), fill)
+        };
+        assert_eq!(preview(Some(11)), Err(ProgramError::InvalidInstructionData));
+        assert_eq!(take(&views, Some(11)), Err(ProgramError::InvalidInstructionData));
+
+        assert_eq!(preview(Some(4)), Ok((4, 8)));
+        take(&views, Some(4)).unwrap();
+        assert_eq!((balance(&views[TakeAccount::TakerAtaA as usize]), balance(&views[TakeAccount::MakerAtaB as usize])), (4, 8));
+
+        // The rest of the vault, at the rest of the price
+        assert_eq!(preview(None), Ok((6, 12)));
+        take(&views, None).unwrap();
+        assert_eq!((balance(&views[TakeAccount::TakerAtaA as usize]), balance(&views[TakeAccount::MakerAtaB as usize])), (10, 20));
+    }
+
+    #[test]
+    fn a_cosigned_escrow_takes_only_with_its_cosigner() {
+        let mut fixture = Fixture::with_escrow(|escrow| escrow.set_cosigner(address(40)));
+        assert_eq!(take(&fixture.views(), None), Err(missing_account(TAKE_VAULT_AUTHORITY_INDEX)));
+
+        // Another signer, or the cosigner without its signature
+        fixture.accounts.push(TestAccount::wallet(address(41)).signer());
+        assert_eq!(take(&fixture.views(), None), Err(EscrowError::CosignerRequired.into()));
+        fixture.accounts[TAKE_VAULT_AUTHORITY_INDEX] = TestAccount::wallet(address(40));
+        assert_eq!(take(&fixture.views(), None), Err(EscrowError::CosignerRequired.into()));
+        assert_eq!(balance(&fixture.views()[TakeAccount::Vault as usize]), 10);
+
+        fixture.accounts[TAKE_VAULT_AUTHORITY_INDEX] = TestAccount::wallet(address(40)).signer();
+        let views = fixture.views();
+        take(&views, None).unwrap();
+        assert_eq!(balance(&views[TakeAccount::TakerAtaA as usize]), 10);
+        assert_eq!(balance(&views[TakeAccount::MakerAtaB as usize]), 20);
+    }
+
+    #[test]
+    fn records_the_last_taker_in_the_take_history() {
+        let fixture = Fixture::new();
+        let views = fixture.views();
+        let (escrow, _) = escrow_address(&address(1), SEED);
+        let history = TestAccount::new(TakeHistory::address(&escrow).0, Address::default(), 0, &[]).writable();
+        let history = history.view();
+        let take = Take::try_from((&views[..], &[][..])).unwrap();
+
+        // Created on the first take, at the taker's expense
+        take.record_taker(&history, 1_000).unwrap();
+        assert!(history.owned_by(&crate::ID));
+        assert_eq!(views[TakeAccount::Taker as usize].lamports(), 1_000_000_000 - rent(TakeHistory::LEN));
+        take.record_taker(&history, 2_000).unwrap();
+
+        let data = history.try_borrow().unwrap();
+        let history = TakeHistory::load(&data).unwrap();
+        assert_eq!((history.escrow.clone(), history.last_taker.clone(), history.taken_at), (escrow.clone(), address(2), 2_000));
+        assert_eq!(history.bump, [TakeHistory::address(&escrow).1]);
+    }
+
+    #[test]
+    fn sequential_takers_pay_exactly_the_receive_amount() {
+        let mut fixture = Fixture::with_escrow(|escrow| escrow.set_flags(Escrow::FLAG_PARTIAL_FILL));
+        take(&fixture.views(), Some(3)).unwrap();
+
+        // Another taker fills the rest, priced against what is left
+        let taker = address(50);
+        fixture.accounts[TakeAccount::Taker as usize] = TestAccount::wallet(taker.clone()).signer().writable();
+        fixture.accounts[TakeAccount::TakerAtaA as usize] = token_account(address(51), &address(3), &taker, 0);
+        fixture.accounts[TakeAccount::TakerAtaB as usize] = token_account(address(52), &address(4), &taker, 20);
+        let views = fixture.views();
+        take(&views, None).unwrap();
+
+        assert_eq!(balance(&views[TakeAccount::TakerAtaA as usize]), 7);
+        assert_eq!(balance(&views[TakeAccount::TakerAtaB as usize]), 20 - 14);
+        assert_eq!(balance(&views[TakeAccount::MakerAtaB as usize]), 20);
+    }
+
+    #[test]
+    fn rejects_a_partial_fill_leaving_the_rest_unpriced() {
+        // 9 of 10 would round up to the whole receive of 2
+        let fixture = Fixture::with_escrow(|escrow| {
+            escrow.set_flags(Escrow::FLAG_PARTIAL_FILL);
+            escrow.set_receive(ReceiveAmount(2));
+        });
+        let views = fixture.views();
+        assert_eq!(take(&views, Some(9)), Err(EscrowError::FillBelowMinimum.into()));
+        take(&views, Some(5)).unwrap();
+        assert_eq!(balance(&views[TakeAccount::MakerAtaB as usize]), 1);
+    }
+
+    #[test]
+    fn rejects_a_vault_aliasing_the_escrow() {
+        let mut fixture = Fixture::new();
+        // The escrow passed again where the vault is expected
+        let (escrow, _) = escrow_address(&address(1), SEED);
+        let data = escrow_data(|state| state.set_maker(address(1)));
+        fixture.accounts[TakeAccount::Vault as usize] = TestAccount::new(escrow, crate::ID, 1, &data).writable();
+        assert_eq!(TakeAccounts::try_from(&fixture.views()[..]).err(), Some(ProgramError::InvalidArgument));
+    }
+
+    #[test]
+    fn rejects_a_token_account_aliasing_the_vault() {
+        let mut fixture = Fixture::new();
+        let vault = fixture.views()[TakeAccount::Vault as usize].address().clone();
+        fixture.accounts[TakeAccount::TakerAtaA as usize] = token_account(vault, &address(3), &address(2), 0);
+        assert_eq!(TakeAccounts::try_from(&fixture.views()[..]).err(), Some(ProgramError::InvalidArgument));
+    }
+
+    #[test]
+    fn a_permit_take_passes_the_takers_permit_nonce() {
+        let mut fixture = Fixture::new();
+        // Relayed: the taker doesn't sign
+        fixture.accounts[TakeAccount::Taker as usize] = TestAccount::wallet(address(2)).writable();
+        fixture.accounts.push(TestAccount::new(INSTRUCTIONS_ID, Address::default(), 1, &[]));
+        assert_eq!(
+            TakeAccounts::try_from(&fixture.views()[..]).err(),
+            Some(missing_account(fixture.accounts.len())),
+        );
+
+        let (permit_nonce, _) = PermitNonce::address(&address(2));
+        fixture.accounts.push(TestAccount::new(permit_nonce.clone(), crate::ID, 1, &[0; PermitNonce::LEN]).writable());
+        let views = fixture.views();
+        let accounts = TakeAccounts::try_from(&views[..]).unwrap();
+        assert_eq!(accounts.permit_nonce.map(AccountView::address), Some(&permit_nonce));
+    }
+
+    #[test]
+    fn splits_off_the_position_deposit_accounts() {
+        // A mock allowlist of the deposit program 9
+        let programs = [address(9)];
+        let mut fixture = Fixture::new();
+        fixture.accounts.extend([
+            TestAccount::program(address(9)),
+            TestAccount::wallet(address(10)),
+            TestAccount::wallet(address(11)),
+        ]);
+        let views = fixture.views();
+        let deposit = PositionDeposit { account_count: 2, data: &[] };
+
+        let (accounts, deposit_accounts) = split_deposit_accounts(&views, Some(deposit), &programs).unwrap();
+        assert_eq!(accounts.len(), TAKE_TREASURY_INDEX);
+        assert!(TakeAccounts::try_from(accounts).is_ok());
+        let deposit_accounts: Vec<Address> = deposit_accounts.iter().map(|account| account.address().clone()).collect();
+        assert_eq!(deposit_accounts, [address(9), address(10), address(11)]);
+
+        // Without a deposit every account is a Take account
+        assert_eq!(split_deposit_accounts(&views, None, &programs).map(|(accounts, _)| accounts.len()), Ok(views.len()));
+    }
+
+    #[test]
+    fn rejects_a_deposit_program_not_allowed() {
+        let mut fixture = Fixture::new();
+        fixture.accounts.push(TestAccount::program(address(9)));
+        let views = fixture.views();
+        let deposit = PositionDeposit { account_count: 0, data: &[] };
+        assert_eq!(split_deposit_accounts(&views, Some(deposit), &[address(10)]).err(), Some(ProgramError::IncorrectProgramId));
+        // Nor a deposit with more accounts than were passed
+        let deposit = PositionDeposit { account_count: u8::MAX, data: &[] };
+        assert_eq!(split_deposit_accounts(&views, Some(deposit), &[address(9)]).err(), Some(missing_account(views.len())));
+    }
+
+    /// Runs a full take over `views`, depositing the fill of 10 through the mock
+    /// deposit program 9 (see `simulate_deposit`) with the last `account_count` views
+    fn take_into_position(views: &[AccountView], account_count: u8) -> ProgramResult {
+        let amount = 10u64.to_le_bytes();
+        let instruction_data = TakeInstructionData { deposit: Some(PositionDeposit { account_count, data: &amount }), ..Default::default() };
+        let (accounts, deposit_accounts) = split_deposit_accounts(views, instruction_data.deposit, &[address(9)])?;
+        Take { accounts: TakeAccounts::try_from(accounts)?, instruction_data, deposit_accounts }.process()
+    }
+
+    #[test]
+    fn deposits_the_fill_with_only_the_takers_signature() {
+        // The cosigner signs the take too, and is forwarded to the deposit program
+        let mut fixture = Fixture::with_escrow(|escrow| escrow.set_cosigner(address(40)));
+        fixture.accounts.push(TestAccount::wallet(address(40)).signer());
+        let (program, position) = (TestAccount::program(address(9)), token_account(address(20), &address(3), &address(21), 0));
+        let mut views = fixture.views();
+        views.extend([
+            program.view(),
+            fixture.accounts[TakeAccount::TakerAtaA as usize].view(),
+            position.view(),
+            fixture.accounts[TakeAccount::Taker as usize].view(),
+            fixture.accounts[TAKE_VAULT_AUTHORITY_INDEX].view(),
+        ]);
+
+        take_into_position(&views, 4).unwrap();
+        assert_eq!(balance(&position.view()), 10);
+        assert_eq!(balance(&views[TakeAccount::TakerAtaA as usize]), 0);
+        assert_eq!(deposit_signers(), [address(2)]);
+    }
+
+    #[test]
+    fn deposits_only_for_a_signing_taker() {
+        // Taking through its delegate, the taker has no signature to lend the deposit
+        let mut fixture = Fixture::with_escrow(|escrow| escrow.set_delegate(address(12)));
+        fixture.accounts[TakeAccount::Taker as usize] = TestAccount::wallet(address(2)).writable();
+        fixture.accounts[TakeAccount::TakerAtaB as usize] = delegated(token_account(address(7), &address(4), &address(2), 20), &address(12), 20);
+        fixture.accounts.push(TestAccount::wallet(address(12)).signer());
+        let (program, position) = (TestAccount::program(address(9)), token_account(address(20), &address(3), &address(21), 0));
+        let mut views = fixture.views();
+        views.extend([
+            program.view(),
+            fixture.accounts[TakeAccount::TakerAtaA as usize].view(),
+            position.view(),
+            fixture.accounts[TakeAccount::Taker as usize].view(),
+        ]);
+
+        assert_eq!(take_into_position(&views, 3), Err(invalid_account(TakeAccount::Taker as usize)));
+        assert_eq!(balance(&views[TakeAccount::Vault as usize]), 10);
+        assert_eq!(balance(&position.view()), 0);
+    }
+}
@@ -0,0 +1,75 @@
+use pinocchio::{AccountView, ProgramResult, error::ProgramError};
+use super::make::ProgramAccount;
+use crate::state::Escrow;
+use crate::cpi;
+
+/// Checks the escrow's vault and writes its balance to return data, without a swap.
+///
+/// Lets integrators confirm on-chain that the vault backing an escrow is the
+/// canonical one and see how much of mint_a it still holds. Nothing is modified.
+///
+/// Return data: the vault balance of mint_a (u64, little endian).
+///
+/// # Accounts
+/// - `escrow`: Escrow account to verify
+/// - `vault`: The escrow's vault
+pub struct VerifyVault<'a> {
+    pub escrow: &'a AccountView,
+    pub vault: &'a AccountView,
+}
+
+impl<'a> TryFrom<&'a [AccountView]> for VerifyVault<'a> {
+    type Error = ProgramError;
+    fn try_from(accounts: &'a [AccountView]) -> Result<Self, Self::Error> {
+        let [escrow, vault] = accounts else {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        };
+
+        ProgramAccount::check(escrow)?;
+
+        Ok(Self { escrow, vault })
+    }
+}
+
+impl<'a> VerifyVault<'a> {
+    pub const DISCRIMINATOR: &'a u8 = &crate::constants::VERIFY_VAULT_DISCRIMINATOR;
+
+    /// 1. vault is the escrow's associated token account of mint_a
+    /// 2. vault holds mint_a and is owned by the escrow
+    /// 3. vault balance -> return data
+    pub fn process(&self) -> ProgramResult {
+        let data = self.escrow.try_borrow()?;
+        let escrow = Escrow::load(&data)?;
+
+        // Validates the vault is the canonical one before reading it
+        let amount = escrow.vault_amount(self.escrow, self.vault)?;
+
+        cpi::set_return_data(&amount.to_le_bytes());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{address, escrow, return_data, token_account, vault};
+
+    #[test]
+    fn reports_the_vault_balance() {
+        let escrow = escrow(&address(1), 7, |_| {});
+        let escrow_address = escrow.view().address().clone();
+        let vault = vault(&escrow_address, &address(3), 42);
+        let accounts = [escrow.view(), vault.view()];
+        VerifyVault::try_from(&accounts[..]).unwrap().process().unwrap();
+        assert_eq!(return_data(), 42u64.to_le_bytes());
+    }
+
+    #[test]
+    fn rejects_a_vault_other_than_the_canonical_one() {
+        let escrow = escrow(&address(1), 7, |_| {});
+        let escrow_address = escrow.view().address().clone();
+        let vault = token_account(address(5), &address(3), &escrow_address, 42);
+        let accounts = [escrow.view(), vault.view()];
+        assert_eq!(VerifyVault::try_from(&accounts[..]).unwrap().process(), Err(ProgramError::InvalidSeeds));
+    }
+}
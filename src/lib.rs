@@ -1,9 +1,33 @@
 use pinocchio::{AccountView,error::ProgramError,ProgramResult,entrypoint,Address};
 //use solana_address::declare_id;
 entrypoint!(process_instructions);
-mod state;
+
+/// Logs `$label` and the remaining compute units, only with the `compute-log` feature
+/// on-chain. Marks the CPIs of `Make` and `Take` for profiling on devnet. Host tests
+/// record the labels instead, see `test_utils::compute_logs`.
+macro_rules! log_compute_units {
+    ($label:literal) => {
+        #[cfg(all(feature = "compute-log", target_os = "solana"))]
+        unsafe {
+            pinocchio::syscalls::sol_log_($label.as_ptr(), $label.len() as u64);
+            pinocchio::syscalls::sol_log_compute_units_();
+        }
+        #[cfg(all(feature = "compute-log", test))]
+        crate::test_utils::simulate_log_compute_units($label);
+    };
+}
+
+pub mod state;
+pub mod error;
+pub mod constants;
+pub use constants::*;
 mod instructions;
 pub use instructions::*;
+mod cpi;
+#[cfg(feature = "client")]
+pub mod client;
+#[cfg(test)]
+mod test_utils;
 
 // declare_id!("22222222222222222222222222222222222222222222");
 pub const ID: Address = Address::new_from_array([
@@ -12,14 +36,163 @@ pub const ID: Address = Address::new_from_array([
     0x19, 0x92, 0xba, 0xe8, 0xaf, 0xd1, 0xcd, 0x07,
     0x8e, 0xf8, 0xaf, 0x70, 0x47, 0xdc, 0x11, 0xf7,
 ]);
+
+/// Deploy admin allowed to create the program config and pause the program.
+/// Replace with the operator's key before deploying.
+pub const ADMIN: Address = Address::new_from_array([
+    0x5c, 0x0b, 0x2e, 0x91, 0x7a, 0x44, 0xd3, 0x18,
+    0xe6, 0x23, 0x9f, 0x50, 0x0c, 0x7b, 0xa8, 0x36,
+    0x41, 0xbd, 0x6e, 0x02, 0x95, 0x1f, 0xc7, 0x8a,
+    0x3d, 0x64, 0xf0, 0x29, 0xb3, 0x57, 0x0e, 0xca,
+]);
+
+/// Current Unix timestamp from the clock sysvar
+#[cfg(not(test))]
+pub(crate) fn unix_timestamp() -> Result<i64, ProgramError> {
+    use pinocchio::sysvars::{clock::Clock, Sysvar};
+    Ok(Clock::get()?.unix_timestamp)
+}
+
+/// Host tests have no clock sysvar: they set the time with `test_utils::set_now`
+#[cfg(test)]
+pub(crate) fn unix_timestamp() -> Result<i64, ProgramError> {
+    Ok(test_utils::now())
+}
+
+// Every instruction must fit the dispatcher's limit, in its longest encoding
+const _: () = assert!(MAX_INSTRUCTION_DATA_LEN >= 8 + MAKE_DATA_MAX_LEN);
+const _: () = assert!(MAX_MAKE_MANY_GROUPS * MAKE_DATA_LEN < MAX_INSTRUCTION_DATA_LEN);
+
 pub fn process_instructions(
     _program_id: &Address,
     accounts: &[AccountView],
     instruction_data: &[u8]
 ) -> ProgramResult{
-    match instruction_data.split_first() {
-        Some((Make::DISCRIMINATOR,data)) => make::Make::try_from((accounts,data))?.process(),
-        Some((Take::DISCRIMINATOR,_)) => take::Take::try_from(accounts)?.process(),
+    if instruction_data.len() > MAX_INSTRUCTION_DATA_LEN {
+        return Err(error::EscrowError::InstructionDataTooLarge.into());
+    }
+
+    // Anchor clients prefix the data with an 8-byte discriminator. Anything that
+    // doesn't match one falls through to the native 1-byte discriminators.
+    #[cfg(feature = "anchor-compat")]
+    match instruction_data.split_first_chunk::<8>() {
+        Some((&ANCHOR_MAKE_DISCRIMINATOR,data)) => return make::Make::try_from((accounts,data))?.process(),
+        Some((&ANCHOR_TAKE_DISCRIMINATOR,data)) => return take::Take::try_from((accounts,data))?.process(),
+        _ => {}
+    }
+
+    let Some((&discriminator,data)) = instruction_data.split_first() else {
+        return Err(ProgramError::InvalidInstructionData);
+    };
+
+    // Route on (version, instruction id), see `constants::INSTRUCTION_VERSION_SHIFT`
+    let (version,id) = split_discriminator(discriminator);
+    match (version,&id) {
+        (0,Make::DISCRIMINATOR) => make::Make::try_from((accounts,data))?.process(),
+        (0,Take::DISCRIMINATOR) => take::Take::try_from((accounts,data))?.process(),
+        (0,Refund::DISCRIMINATOR) => refund::Refund::try_from(accounts)?.process(),
+        (0,ExtendDeadline::DISCRIMINATOR) => extend_deadline::ExtendDeadline::try_from((accounts,data))?.process(),
+        (0,RefundExpired::DISCRIMINATOR) => refund_expired::RefundExpired::try_from(accounts)?.process(),
+        (0,SetPaused::DISCRIMINATOR) => set_paused::SetPaused::try_from((accounts,data))?.process(),
+        (0,GetEscrow::DISCRIMINATOR) => get_escrow::GetEscrow::try_from(accounts)?.process(),
+        (0,Match::DISCRIMINATOR) => match_escrows::Match::try_from(accounts)?.process(),
+        (0,Propose::DISCRIMINATOR) => propose::Propose::try_from((accounts,data))?.process(),
+        (0,Accept::DISCRIMINATOR) => accept::Accept::try_from(accounts)?.process(),
+        (0,SweepTreasury::DISCRIMINATOR) => sweep_treasury::SweepTreasury::try_from(accounts)?.process(),
+        (0,SetMintB::DISCRIMINATOR) => set_mint_b::SetMintB::try_from(accounts)?.process(),
+        (1,SetMintB::DISCRIMINATOR) => set_mint_b::SetMintB::try_from((accounts,data))?.process(),
+        (0,VerifyVault::DISCRIMINATOR) => verify_vault::VerifyVault::try_from(accounts)?.process(),
+        (0,Crank::DISCRIMINATOR) => crank::Crank::try_from(accounts)?.process(),
+        (0,Rebalance::DISCRIMINATOR) => rebalance::Rebalance::try_from((accounts,data))?.process(),
+        (0,MakeMany::DISCRIMINATOR) => make_many::MakeMany::try_from((accounts,data))?.process(),
+        (0,Deposit::DISCRIMINATOR) => deposit::Deposit::try_from((accounts,data))?.process(),
+        (0,InitConfig::DISCRIMINATOR) => init_config::InitConfig::try_from(accounts)?.process(),
+        (0,InitPermitNonce::DISCRIMINATOR) => init_permit_nonce::InitPermitNonce::try_from(accounts)?.process(),
+        (0,CancelProposal::DISCRIMINATOR) => cancel_proposal::CancelProposal::try_from(accounts)?.process(),
+        _ => Err(ProgramError::InvalidInstructionData),
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{state::{Escrow, ReceiveAmount}, test_utils::*};
+
+    /// Dispatches `instruction_data` for maker 1's escrow of mint 3 for 20 of
+    /// mint 4, along with a new mint_b 5, returning the stored mint_b and receive
+    fn set_mint_b(instruction_data: &[u8]) -> Result<(Address, ReceiveAmount), ProgramError> {
+        let maker = address(1);
+        let (escrow, bump) = escrow_address(&maker, 7);
+        let data = escrow_data(|state| state.set_inner(7, maker.clone(), address(3), address(4), ReceiveAmount(20), 0, 0, [bump]));
+        let (maker, escrow, mint_b) = (TestAccount::wallet(maker).signer(), TestAccount::new(escrow, ID, 1, &data).writable(), mint(address(5)));
+        let accounts = [maker.view(), escrow.view(), mint_b.view()];
+
+        process_instructions(&ID, &accounts, instruction_data)?;
+        let data = accounts[1].try_borrow()?;
+        let escrow = Escrow::load(&data)?;
+        Ok((escrow.mint_b.clone(), escrow.receive))
+    }
+
+    #[test]
+    fn set_mint_b_v0_keeps_receive() {
+        assert_eq!(set_mint_b(&[SET_MINT_B_DISCRIMINATOR]), Ok((address(5), ReceiveAmount(20))));
+    }
+
+    #[test]
+    fn set_mint_b_v1_replaces_receive() {
+        let data = [&[SET_MINT_B_V1_DISCRIMINATOR][..], &30u64.to_le_bytes()].concat();
+        assert_eq!(set_mint_b(&data), Ok((address(5), ReceiveAmount(30))));
+        // The new receive is required
+        assert_eq!(set_mint_b(&[SET_MINT_B_V1_DISCRIMINATOR]), Err(ProgramError::InvalidInstructionData));
+    }
+
+    #[test]
+    fn discriminators_match_the_instruction_ids() {
+        use constants::Instruction;
+        let discriminators = [
+            (Instruction::Make, *Make::DISCRIMINATOR, MAKE_DISCRIMINATOR),
+            (Instruction::Take, *Take::DISCRIMINATOR, TAKE_DISCRIMINATOR),
+            (Instruction::Refund, *Refund::DISCRIMINATOR, REFUND_DISCRIMINATOR),
+            (Instruction::ExtendDeadline, *ExtendDeadline::DISCRIMINATOR, EXTEND_DEADLINE_DISCRIMINATOR),
+            (Instruction::RefundExpired, *RefundExpired::DISCRIMINATOR, REFUND_EXPIRED_DISCRIMINATOR),
+            (Instruction::SetPaused, *SetPaused::DISCRIMINATOR, SET_PAUSED_DISCRIMINATOR),
+            (Instruction::GetEscrow, *GetEscrow::DISCRIMINATOR, GET_ESCROW_DISCRIMINATOR),
+            (Instruction::Match, *Match::DISCRIMINATOR, MATCH_DISCRIMINATOR),
+            (Instruction::Propose, *Propose::DISCRIMINATOR, PROPOSE_DISCRIMINATOR),
+            (Instruction::Accept, *Accept::DISCRIMINATOR, ACCEPT_DISCRIMINATOR),
+            (Instruction::SweepTreasury, *SweepTreasury::DISCRIMINATOR, SWEEP_TREASURY_DISCRIMINATOR),
+            (Instruction::SetMintB, *SetMintB::DISCRIMINATOR, SET_MINT_B_DISCRIMINATOR),
+            (Instruction::VerifyVault, *VerifyVault::DISCRIMINATOR, VERIFY_VAULT_DISCRIMINATOR),
+            (Instruction::Crank, *Crank::DISCRIMINATOR, CRANK_DISCRIMINATOR),
+            (Instruction::Rebalance, *Rebalance::DISCRIMINATOR, REBALANCE_DISCRIMINATOR),
+            (Instruction::MakeMany, *MakeMany::DISCRIMINATOR, MAKE_MANY_DISCRIMINATOR),
+            (Instruction::Deposit, *Deposit::DISCRIMINATOR, DEPOSIT_DISCRIMINATOR),
+            (Instruction::InitConfig, *InitConfig::DISCRIMINATOR, INIT_CONFIG_DISCRIMINATOR),
+            (Instruction::InitPermitNonce, *InitPermitNonce::DISCRIMINATOR, INIT_PERMIT_NONCE_DISCRIMINATOR),
+            (Instruction::CancelProposal, *CancelProposal::DISCRIMINATOR, CANCEL_PROPOSAL_DISCRIMINATOR),
+        ];
+        for (id, (instruction, discriminator, constant)) in discriminators.into_iter().enumerate() {
+            // Ids run in order, each within the id bits of a discriminator byte
+            assert_eq!(instruction as usize, id);
+            assert_eq!(instruction as u8, discriminator);
+            assert_eq!(discriminator, constant);
+            assert_eq!(split_discriminator(constant), (0, constant));
+        }
+    }
+
+    #[test]
+    fn rejects_instruction_data_over_the_limit() {
+        let data = [&[GET_ESCROW_DISCRIMINATOR][..], &[0; MAX_INSTRUCTION_DATA_LEN]].concat();
+        assert_eq!(process_instructions(&ID, &[], &data), Err(error::EscrowError::InstructionDataTooLarge.into()));
+        // At the limit it reaches the instruction, which has no accounts to read
+        assert_eq!(process_instructions(&ID, &[], &data[..MAX_INSTRUCTION_DATA_LEN]), Err(ProgramError::NotEnoughAccountKeys));
+    }
+
+    #[test]
+    fn rejects_unknown_versions() {
+        let data = [&[versioned_discriminator(SET_MINT_B_DISCRIMINATOR, 2)][..], &30u64.to_le_bytes()].concat();
+        assert_eq!(set_mint_b(&data), Err(ProgramError::InvalidInstructionData));
+        assert_eq!(set_mint_b(&[versioned_discriminator(MAKE_DISCRIMINATOR, 1)]), Err(ProgramError::InvalidInstructionData));
+    }
+}
@@ -1,19 +1,115 @@
-use pinocchio::{error::ProgramError,Address};
+use pinocchio::{error::ProgramError,AccountView,Address};
+use crate::{constants::{ASSOCIATED_TOKEN_PROGRAM_ID, TOKEN_2022_PROGRAM_ID, TOKEN_PROGRAM_ID}, error::EscrowError, instructions::TokenAccountInterface};
 use core::mem::size_of;
-#[repr(C)]
 
+/// Amount of mint_a the maker deposits into the vault
+///
+/// A distinct type from `ReceiveAmount` so the two sides of the swap can't be
+/// swapped by accident. Transparent: stored and sent exactly like a `u64`.
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DepositAmount(pub u64);
+
+/// Amount of mint_b the maker asks for in return, see `DepositAmount`
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ReceiveAmount(pub u64);
+
+impl DepositAmount {
+    #[inline(always)]
+    pub const fn get(self) -> u64 {
+        self.0
+    }
+}
+
+impl ReceiveAmount {
+    #[inline(always)]
+    pub const fn get(self) -> u64 {
+        self.0
+    }
+}
+
+#[repr(C)]
+#[derive(Clone)]
 pub struct Escrow {
     pub seed: u64,
     pub maker: Address,
     pub mint_a: Address,
     pub mint_b: Address,
-    pub receive: u64,
+    pub receive: ReceiveAmount,
+    pub deadline: i64,
+    pub min_fill: u64,
+    pub oracle: Address,
+    pub multiplier: u64,
+    pub delegate: Address,
+    /// Second signer every take needs, e.g. a compliance key (default address = none)
+    pub cosigner: Address,
+    /// mint_a taken by partial fills so far
+    pub filled: u64,
+    /// Unix timestamp of the Make that created the escrow (0 = unknown)
+    pub created_at: i64,
+    /// Mode bits, see `Escrow::FLAG_PARTIAL_FILL`
+    pub flags: u8,
     pub bump: [u8;1],
 }
 
+// Adding or removing an `Escrow` field must update `Escrow::LEN` and this check together,
+// and changes the account size: existing escrows need a migration.
+// seed 8 + maker 32 + mint_a 32 + mint_b 32 + receive 8 + deadline 8 + min_fill 8
+// + oracle 32 + multiplier 8 + delegate 32 + cosigner 32 + filled 8 + created_at 8 + flags 1 + bump 1 = 250
+const _: () = assert!(Escrow::LEN == 250);
+// Fields are laid out back to back: the only padding is after `bump`, rounding the
+// struct up to its 8-byte alignment. Interior padding would shift fields off `LEN`.
+const _: () = assert!(size_of::<Escrow>() == Escrow::LEN.next_multiple_of(core::mem::align_of::<Escrow>()));
+// The filter offsets follow the field sizes listed above
+const _: () = assert!(Escrow::MAKER_OFFSET == 8 && Escrow::MINT_A_OFFSET == 40 && Escrow::MINT_B_OFFSET == 72);
+
 impl Escrow {
-    pub const LEN: usize=size_of::<u64>()+size_of::<Address>()*3+size_of::<u64>()+size_of::<[u8;1]>();
+    /// Seed prefix used to derive escrow PDAs: `[SEED, maker, seed.to_le_bytes()]`
+    ///
+    /// The address only depends on the maker and seed, so once an escrow is closed
+    /// the same pair derives the same address again. Clients caching escrow
+    /// addresses should fold a nonce into the seed (see `client::nonced_seed`).
+    pub const SEED: &'static [u8] = crate::constants::ESCROW_SEED;
+
+    pub const LEN: usize=size_of::<u64>()+size_of::<Address>()*3+size_of::<u64>()+size_of::<i64>()+size_of::<u64>()+size_of::<Address>()+size_of::<u64>()+size_of::<Address>()*2+size_of::<u64>()+size_of::<i64>()+size_of::<u8>()+size_of::<[u8;1]>();
+
+    /// Byte offset of `maker` in the account data, for `getProgramAccounts` `memcmp`
+    /// filters: `{ memcmp: { offset: MAKER_OFFSET, bytes: <maker base58> } }`, together
+    /// with `client::ESCROW_DATA_SIZE`, selects every escrow of that maker.
+    pub const MAKER_OFFSET: usize = core::mem::offset_of!(Escrow, maker);
+    /// Byte offset of `mint_a` in the account data, see `MAKER_OFFSET`
+    pub const MINT_A_OFFSET: usize = core::mem::offset_of!(Escrow, mint_a);
+    /// Byte offset of `mint_b` in the account data, see `MAKER_OFFSET`
+    pub const MINT_B_OFFSET: usize = core::mem::offset_of!(Escrow, mint_b);
+
+    /// Takers may fill the escrow in several partial takes. Without it a take
+    /// must drain the whole vault.
+    pub const FLAG_PARTIAL_FILL: u8 = 1 << 0;
+
+    /// Set by the first partial fill. A sealed escrow's terms can't be modified
+    /// anymore, so every fill of the sequence trades against the same terms.
+    pub const FLAG_SEALED: u8 = 1 << 1;
+
+    /// The vault holds a single NFT: `mint_a` must have 0 decimals and a supply of 1,
+    /// checked by `Make` and again by `Take`. Excludes `FLAG_PARTIAL_FILL`.
+    pub const FLAG_NFT: u8 = 1 << 2;
+
+    /// Set by `Make` when the escrow is a keypair account signed at creation
+    /// instead of a PDA. A keypair can't sign for the program later, so the vault
+    /// belongs to the vault authority PDA (see `Escrow::vault_authority`), whose
+    /// bump is stored in `bump`. Keypair escrows support Take and Refund only.
+    pub const FLAG_KEYPAIR: u8 = 1 << 3;
+
+    /// The escrow is a standing offer: the take draining the vault leaves the escrow
+    /// and its vault open instead of closing them, for the maker to refill with
+    /// `Deposit`. Only the maker's Refund closes it. Not available to keypair escrows.
+    pub const FLAG_PERSISTENT: u8 = 1 << 4;
+
+    /// Every flag `Make` accepts, the program sets the others itself
+    pub const FLAGS_ALL: u8 = Self::FLAG_PARTIAL_FILL | Self::FLAG_NFT | Self::FLAG_PERSISTENT;
 
+    #[cfg(not(feature = "safe-state"))]
     #[inline(always)]
     pub fn load_mut(bytes: &mut [u8]) -> Result<&mut Self,ProgramError> {
         if bytes.len() != Self::LEN {
@@ -22,12 +118,176 @@ impl Escrow {
         Ok( unsafe{&mut *core::mem::transmute::<*mut u8, *mut Self>(bytes.as_mut_ptr())})
     }
 
+    /// Loads an initialized escrow
+    ///
+    /// Every escrow is written with its maker, so a zero maker means the account
+    /// has the right length but was never initialized.
+    #[cfg(not(feature = "safe-state"))]
     #[inline(always)]
     pub fn load(bytes: &[u8]) -> Result<&Self,ProgramError> {
         if bytes.len() != Self::LEN {
             return Err(ProgramError::InvalidInstructionData);
         }
-        Ok( unsafe{& *core::mem::transmute::<*const u8, *const Self>(bytes.as_ptr())})
+        let escrow = unsafe{& *core::mem::transmute::<*const u8, *const Self>(bytes.as_ptr())};
+        if escrow.maker == Address::default() {
+            return Err(EscrowError::UninitializedEscrow.into());
+        }
+        Ok(escrow)
+    }
+
+    /// `safe-state` counterpart of `load_mut`: edits a copy of the escrow, written
+    /// back to `bytes` when the returned guard is dropped
+    #[cfg(feature = "safe-state")]
+    pub fn load_mut(bytes: &mut [u8]) -> Result<EscrowMut<'_>,ProgramError> {
+        if bytes.len() != Self::LEN {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        let escrow = Self::read(bytes);
+        Ok(EscrowMut { bytes, escrow })
+    }
+
+    /// `safe-state` counterpart of `load`: returns a copy read field by field
+    #[cfg(feature = "safe-state")]
+    pub fn load(bytes: &[u8]) -> Result<Self,ProgramError> {
+        Self::load_unaligned(bytes)
+    }
+
+    /// Copies an initialized escrow out of `bytes` field by field, so unlike the
+    /// default `load` it takes buffers at any alignment, e.g. data fetched over RPC
+    #[cfg(any(feature = "safe-state", feature = "client"))]
+    pub fn load_unaligned(bytes: &[u8]) -> Result<Self,ProgramError> {
+        if bytes.len() != Self::LEN {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        let escrow = Self::read(bytes);
+        if escrow.maker == Address::default() {
+            return Err(EscrowError::UninitializedEscrow.into());
+        }
+        Ok(escrow)
+    }
+
+    /// Reads the fields in declaration order at their `repr(C)` offsets, which have
+    /// no padding up to `LEN`. `bytes` must be `LEN` long.
+    #[cfg(any(feature = "safe-state", feature = "client", test))]
+    fn read(bytes: &[u8]) -> Self {
+        let mut reader = ByteReader(bytes);
+        Self {
+            seed: u64::from_le_bytes(reader.take()),
+            maker: Address::new_from_array(reader.take()),
+            mint_a: Address::new_from_array(reader.take()),
+            mint_b: Address::new_from_array(reader.take()),
+            receive: ReceiveAmount(u64::from_le_bytes(reader.take())),
+            deadline: i64::from_le_bytes(reader.take()),
+            min_fill: u64::from_le_bytes(reader.take()),
+            oracle: Address::new_from_array(reader.take()),
+            multiplier: u64::from_le_bytes(reader.take()),
+            delegate: Address::new_from_array(reader.take()),
+            cosigner: Address::new_from_array(reader.take()),
+            filled: u64::from_le_bytes(reader.take()),
+            created_at: i64::from_le_bytes(reader.take()),
+            flags: u8::from_le_bytes(reader.take()),
+            bump: reader.take(),
+        }
+    }
+
+    /// Writes the fields back at the offsets `read` takes them from
+    #[cfg(any(feature = "safe-state", feature = "client", test))]
+    fn write(&self, bytes: &mut [u8]) {
+        let mut writer = ByteWriter(bytes);
+        writer.put(&self.seed.to_le_bytes());
+        writer.put(self.maker.as_ref());
+        writer.put(self.mint_a.as_ref());
+        writer.put(self.mint_b.as_ref());
+        writer.put(&self.receive.get().to_le_bytes());
+        writer.put(&self.deadline.to_le_bytes());
+        writer.put(&self.min_fill.to_le_bytes());
+        writer.put(self.oracle.as_ref());
+        writer.put(&self.multiplier.to_le_bytes());
+        writer.put(self.delegate.as_ref());
+        writer.put(self.cosigner.as_ref());
+        writer.put(&self.filled.to_le_bytes());
+        writer.put(&self.created_at.to_le_bytes());
+        writer.put(&[self.flags]);
+        writer.put(&self.bump);
+    }
+
+    /// Validates that `account` is the escrow of `maker` created with `seed`
+    ///
+    /// Checks ownership, re-derives the PDA and compares the stored maker and seed,
+    /// so composing programs can verify an escrow in a single call.
+    pub fn verify_pda(account: &AccountView, maker: &Address, seed: u64) -> Result<(), ProgramError> {
+        if !account.owned_by(&crate::ID) {
+            return Err(ProgramError::InvalidAccountOwner);
+        }
+
+        let (escrow_address, _) = Address::find_program_address(
+            &[Self::SEED, maker.as_ref(), &seed.to_le_bytes()],
+            &crate::ID,
+        );
+        if account.address() != &escrow_address {
+            return Err(ProgramError::InvalidSeeds);
+        }
+
+        let data = account.try_borrow()?;
+        let escrow = Self::load(&data)?;
+        if escrow.maker != *maker || escrow.seed != seed {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(())
+    }
+
+    /// Re-derives this escrow's PDA from its stored seed and bump, for `maker`
+    ///
+    /// Cheaper than `find_program_address`'s bump search. `create_program_address`
+    /// fails for seeds landing on the curve, which no program can sign for, so a
+    /// forged bump can only produce an error here, never an address to trust.
+    pub fn address_with_bump(&self, maker: &Address) -> Result<Address, ProgramError> {
+        Address::create_program_address(
+            &[Self::SEED, maker.as_ref(), &self.seed.to_le_bytes(), &self.bump],
+            &crate::ID,
+        )
+        .map_err(|_| ProgramError::InvalidSeeds)
+    }
+
+    /// Derives the PDA owning the vault of the keypair escrow at `escrow`, and its bump
+    pub fn vault_authority(escrow: &Address) -> (Address, u8) {
+        Address::find_program_address(&[crate::constants::VAULT_AUTHORITY_SEED, escrow.as_ref()], &crate::ID)
+    }
+
+    /// Reads the balance of the escrow's vault
+    ///
+    /// `authority` owns the vault: the account this state was loaded from, or its
+    /// vault authority for a keypair escrow. Validates that the vault is the
+    /// canonical vault, the associated token account of `authority` for `mint_a`
+    /// under the token program owning the vault, and still a token account of
+    /// `mint_a` owned by `authority`.
+    pub fn vault_amount(&self, authority: &AccountView, vault: &AccountView) -> Result<u64, ProgramError> {
+        let token_program = if vault.owned_by(&TOKEN_2022_PROGRAM_ID) { &TOKEN_2022_PROGRAM_ID } else { &TOKEN_PROGRAM_ID };
+        let (vault_address, _) = Address::find_program_address(
+            &[authority.address().as_ref(), token_program.as_ref(), self.mint_a.as_ref()],
+            &ASSOCIATED_TOKEN_PROGRAM_ID,
+        );
+        if vault.address() != &vault_address {
+            return Err(ProgramError::InvalidSeeds);
+        }
+        let token_account = TokenAccountInterface::from_account_view(vault)?;
+        if token_account.mint() != &self.mint_a || token_account.owner() != authority.address() {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(token_account.amount())
+    }
+
+    /// Copies the escrow out of account data, so callers don't keep a borrow on the account
+    #[cfg(not(feature = "safe-state"))]
+    #[inline(always)]
+    pub fn load_owned(bytes: &[u8]) -> Result<Self,ProgramError> {
+        Self::load(bytes).cloned()
+    }
+
+    /// Copies the escrow out of account data, so callers don't keep a borrow on the account
+    #[cfg(feature = "safe-state")]
+    pub fn load_owned(bytes: &[u8]) -> Result<Self,ProgramError> {
+        Self::load(bytes)
     }
 
     #[inline(always)]
@@ -47,21 +307,829 @@ impl Escrow {
         self.mint_b = mint_b;
     }
     #[inline(always)]
-    pub fn set_receive(&mut self, receive: u64) {
+    pub fn set_receive(&mut self, receive: ReceiveAmount) {
         self.receive = receive;
     }
     #[inline(always)]
+    pub fn set_deadline(&mut self, deadline: i64) {
+        self.deadline = deadline;
+    }
+    #[inline(always)]
+    pub fn set_min_fill(&mut self, min_fill: u64) {
+        self.min_fill = min_fill;
+    }
+    /// Prices the escrow from `oracle` at take time, scaled by `multiplier` (basis points)
+    #[inline(always)]
+    pub fn set_oracle(&mut self, oracle: Address, multiplier: u64) {
+        self.oracle = oracle;
+        self.multiplier = multiplier;
+    }
+    /// Lets `delegate` sign Take on behalf of takers (default address = no delegate)
+    #[inline(always)]
+    pub fn set_delegate(&mut self, delegate: Address) {
+        self.delegate = delegate;
+    }
+    /// Requires `cosigner` to sign every Take as well (default address = no cosigner)
+    #[inline(always)]
+    pub fn set_cosigner(&mut self, cosigner: Address) {
+        self.cosigner = cosigner;
+    }
+    #[inline(always)]
+    pub fn set_filled(&mut self, filled: u64) {
+        self.filled = filled;
+    }
+    #[inline(always)]
+    pub fn set_created_at(&mut self, created_at: i64) {
+        self.created_at = created_at;
+    }
+    #[inline(always)]
+    pub fn set_flags(&mut self, flags: u8) {
+        self.flags = flags;
+    }
+    #[inline(always)]
     pub fn set_bump(&mut self, bump: [u8;1]) {
         self.bump = bump;
     }
 
     #[inline(always)]
-    pub fn set_inner(&mut self, seed: u64, maker: Address, mint_a: Address, mint_b: Address, receive: u64, bump: [u8;1]) {
+    #[allow(clippy::too_many_arguments)]
+    pub fn set_inner(&mut self, seed: u64, maker: Address, mint_a: Address, mint_b: Address, receive: ReceiveAmount, deadline: i64, min_fill: u64, bump: [u8;1]) {
         self.set_seeds(seed);
         self.set_maker(maker);
         self.set_mint_a(mint_a);
         self.set_mint_b(mint_b);
         self.set_receive(receive);
+        self.set_deadline(deadline);
+        self.set_min_fill(min_fill);
         self.set_bump(bump);
     }
-}
\ No newline at end of file
+
+    /// Serializes the escrow into the exact account data `Make` would leave, `LEN`
+    /// bytes that `load` reads back, for fixtures injected into a test validator
+    #[cfg(feature = "client")]
+    pub fn to_account_data(&self) -> Vec<u8> {
+        let mut data = vec![0; Self::LEN];
+        self.write(&mut data);
+        data
+    }
+
+    /// Price implied by the escrow: mint_b asked per unit of mint_a deposited.
+    /// `deposited_amount` is the vault's current balance; returns `None` if it's 0.
+    #[cfg(feature = "client")]
+    pub fn implied_rate(&self, deposited_amount: u64) -> Option<f64> {
+        if deposited_amount == 0 {
+            return None;
+        }
+        Some(self.receive.get() as f64 / deposited_amount as f64)
+    }
+
+    /// Returned by `age` for an escrow whose creation time isn't recorded
+    pub const AGE_UNKNOWN: i64 = -1;
+
+    /// Seconds since the escrow was created, at `current_ts`
+    ///
+    /// Returns `AGE_UNKNOWN` when `created_at` is 0. A clock behind `created_at`
+    /// counts as age 0.
+    #[inline(always)]
+    pub fn age(&self, current_ts: i64) -> i64 {
+        if self.created_at == 0 {
+            return Self::AGE_UNKNOWN;
+        }
+        current_ts.saturating_sub(self.created_at).max(0)
+    }
+
+    /// Returns true if the price is read from an oracle instead of the fixed `receive`
+    #[inline(always)]
+    pub fn uses_oracle(&self) -> bool {
+        self.oracle != Address::default()
+    }
+
+    /// Returns true if takers may fill the escrow in several partial takes
+    #[inline(always)]
+    pub fn is_partial_fillable(&self) -> bool {
+        self.flags & Self::FLAG_PARTIAL_FILL != 0
+    }
+
+    /// Returns true if the escrow trades a single NFT of `mint_a`
+    #[inline(always)]
+    pub fn is_nft(&self) -> bool {
+        self.flags & Self::FLAG_NFT != 0
+    }
+
+    /// Returns true if a draining take leaves the escrow open, see `Escrow::FLAG_PERSISTENT`
+    #[inline(always)]
+    pub fn is_persistent(&self) -> bool {
+        self.flags & Self::FLAG_PERSISTENT != 0
+    }
+
+    /// Returns true if the escrow is a keypair account, see `Escrow::FLAG_KEYPAIR`
+    #[inline(always)]
+    pub fn is_keypair(&self) -> bool {
+        self.flags & Self::FLAG_KEYPAIR != 0
+    }
+
+    /// Returns true if a partial fill has been taken and the terms are frozen
+    #[inline(always)]
+    pub fn is_sealed(&self) -> bool {
+        self.flags & Self::FLAG_SEALED != 0
+    }
+
+    /// Returns true if a delegate may take on behalf of takers
+    #[inline(always)]
+    pub fn has_delegate(&self) -> bool {
+        self.delegate != Address::default()
+    }
+
+    /// Returns true if every take must also be signed by `cosigner`
+    #[inline(always)]
+    pub fn has_cosigner(&self) -> bool {
+        self.cosigner != Address::default()
+    }
+
+    /// Returns true if the escrow has a deadline and it is before `now`.
+    /// A deadline of `0` means the escrow never expires.
+    #[inline(always)]
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.deadline != 0 && now > self.deadline
+    }
+
+    /// Fails with `RefundGracePeriod` while the escrow is younger than `grace_period`
+    /// seconds at `now`, see `refund::REFUND_GRACE_PERIOD`
+    pub fn check_grace_period(&self, now: i64, grace_period: i64) -> Result<(), ProgramError> {
+        if grace_period != 0 && !self.is_expired(now) && self.created_at != 0 && now < self.created_at.saturating_add(grace_period) {
+            return Err(EscrowError::RefundGracePeriod.into());
+        }
+        Ok(())
+    }
+
+    /// Validates a take of `fill` mint_a out of a vault holding `vault_amount`,
+    /// returning true if it is the final fill draining the vault
+    pub fn check_fill(&self, fill: u64, vault_amount: u64) -> Result<bool, ProgramError> {
+        if fill == 0 || fill > vault_amount {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        let is_final_fill = fill == vault_amount;
+
+        // All-or-nothing escrows only accept a take of the whole vault
+        if !is_final_fill && !self.is_partial_fillable() {
+            return Err(EscrowError::PartialFillNotAllowed.into());
+        }
+
+        // Tiny fills would let a taker drain the escrow in rent-churning increments
+        if !is_final_fill && fill < self.min_fill {
+            return Err(EscrowError::FillBelowMinimum.into());
+        }
+        Ok(is_final_fill)
+    }
+
+    /// mint_b a fixed-price take of `fill` out of a vault holding `vault_amount` pays
+    ///
+    /// The share of `receive` matching the share of the vault, rounded up in the
+    /// maker's favor, and whatever is left on the final fill. Each fill is priced
+    /// against what is left, so sequential fills by different takers add up to
+    /// exactly `receive` for the whole vault.
+    ///
+    /// A partial fill can't pay all of the remaining `receive`: the rest of the
+    /// vault would go to the next taker for free. Rounding makes that happen when
+    /// `receive` is small next to the vault, which rejects the fill as too small.
+    ///
+    /// Token amounts are `u64`, so a product of two of them is only exact in `u128`:
+    /// every such computation widens first and narrows back with a checked
+    /// conversion, never an `as` cast (see also `OraclePrice::quote`).
+    pub fn fixed_price_quote(&self, fill: u64, vault_amount: u64) -> Result<u64, ProgramError> {
+        if fill == vault_amount {
+            return Ok(self.receive.get());
+        }
+        let quote = (self.receive.get() as u128 * fill as u128).div_ceil(vault_amount as u128);
+        let quote = u64::try_from(quote).map_err(|_| ProgramError::ArithmeticOverflow)?;
+        if quote >= self.receive.get() {
+            return Err(EscrowError::FillBelowMinimum.into());
+        }
+        Ok(quote)
+    }
+
+    /// Records a take of `fill` mint_a paid with `paid` mint_b on an escrow that stays
+    /// open: a partial fill, or the final fill of a persistent escrow
+    ///
+    /// What is left to receive shrinks by the payment (an oracle quotes each fill
+    /// instead), the fill counts towards `filled` and the terms are sealed.
+    pub fn apply_fill(&mut self, fill: u64, paid: u64) -> Result<(), ProgramError> {
+        let remaining_receive = if self.uses_oracle() {
+            self.receive
+        } else {
+            ReceiveAmount(self.receive.get().checked_sub(paid).ok_or(ProgramError::ArithmeticOverflow)?)
+        };
+        let filled = self.filled.checked_add(fill).ok_or(ProgramError::ArithmeticOverflow)?;
+        self.set_receive(remaining_receive);
+        self.set_filled(filled);
+        self.set_flags(self.flags | Self::FLAG_SEALED);
+        Ok(())
+    }
+}
+/// Escrow loaded by `Escrow::load_mut` under `safe-state`, stored back on drop
+#[cfg(feature = "safe-state")]
+pub struct EscrowMut<'a> {
+    bytes: &'a mut [u8],
+    escrow: Escrow,
+}
+
+#[cfg(feature = "safe-state")]
+impl core::ops::Deref for EscrowMut<'_> {
+    type Target = Escrow;
+    fn deref(&self) -> &Escrow {
+        &self.escrow
+    }
+}
+
+#[cfg(feature = "safe-state")]
+impl core::ops::DerefMut for EscrowMut<'_> {
+    fn deref_mut(&mut self) -> &mut Escrow {
+        &mut self.escrow
+    }
+}
+
+#[cfg(feature = "safe-state")]
+impl Drop for EscrowMut<'_> {
+    fn drop(&mut self) {
+        self.escrow.write(self.bytes);
+    }
+}
+
+/// Consumes fixed-size chunks from the front of a length-checked buffer
+#[cfg(any(feature = "safe-state", feature = "client", test))]
+struct ByteReader<'a>(&'a [u8]);
+
+#[cfg(any(feature = "safe-state", feature = "client", test))]
+impl ByteReader<'_> {
+    fn take<const N: usize>(&mut self) -> [u8; N] {
+        let (chunk, rest) = self.0.split_first_chunk::<N>().expect("length checked by the caller");
+        self.0 = rest;
+        *chunk
+    }
+}
+
+/// Fills a length-checked buffer front to back
+#[cfg(any(feature = "safe-state", feature = "client", test))]
+struct ByteWriter<'a>(&'a mut [u8]);
+
+#[cfg(any(feature = "safe-state", feature = "client", test))]
+impl ByteWriter<'_> {
+    fn put(&mut self, value: &[u8]) {
+        let (chunk, rest) = core::mem::take(&mut self.0).split_at_mut(value.len());
+        chunk.copy_from_slice(value);
+        self.0 = rest;
+    }
+}
+
+/// Price account read by `Take` for oracle-priced escrows.
+///
+/// Layout (little endian):
+/// - `[0..8]`: price of one mint_a base unit in mint_b base units, scaled by `10^exponent`
+/// - `[8]`: exponent, at most `OraclePrice::MAX_EXPONENT`
+pub struct OraclePrice {
+    pub price: u64,
+    pub exponent: u8,
+}
+
+impl OraclePrice {
+    pub const LEN: usize=size_of::<u64>()+size_of::<u8>();
+    pub const MAX_EXPONENT: u8 = 18;
+
+    /// Basis points of a `multiplier` equal to 1x
+    pub const MULTIPLIER_ONE: u64 = 10_000;
+
+    #[inline(always)]
+    pub fn load(bytes: &[u8]) -> Result<Self,ProgramError> {
+        let (Some(price), Some(&exponent)) = (bytes.get(0..8), bytes.get(8)) else {
+            return Err(ProgramError::InvalidAccountData);
+        };
+        if exponent > Self::MAX_EXPONENT {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let price = u64::from_le_bytes(price.try_into().map_err(|_| ProgramError::InvalidAccountData)?);
+        Ok(Self { price, exponent })
+    }
+
+    /// mint_b owed for `amount` of mint_a, scaled by `multiplier` basis points and
+    /// rounded up in the maker's favor
+    pub fn quote(&self, amount: u64, multiplier: u64) -> Result<u64,ProgramError> {
+        let numerator = (amount as u128)
+            .checked_mul(self.price as u128)
+            .and_then(|value| value.checked_mul(multiplier as u128))
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        let denominator = 10u128.pow(self.exponent as u32) * Self::MULTIPLIER_ONE as u128;
+        numerator.div_ceil(denominator).try_into().map_err(|_| ProgramError::ArithmeticOverflow)
+    }
+}
+
+/// Program-wide settings, in the config PDA created by `InitConfig` (or the first `SetPaused`)
+#[repr(C)]
+#[derive(Clone)]
+pub struct Config {
+    pub admin: Address,
+    pub paused: u8,
+    pub bump: [u8;1],
+}
+
+// admin 32 + paused 1 + bump 1 = 34, byte aligned so without padding
+const _: () = assert!(Config::LEN == 34);
+const _: () = assert!(size_of::<Config>() == Config::LEN);
+
+impl Config {
+    /// Seed of the program config PDA: `[SEED]`
+    pub const SEED: &'static [u8] = crate::constants::CONFIG_SEED;
+
+    pub const LEN: usize=size_of::<Address>()+size_of::<u8>()+size_of::<[u8;1]>();
+
+    /// Settings in effect while the config PDA doesn't exist: the deploy admin,
+    /// not paused. Fees are compile-time (see `TAKE_FLAT_FEE`), so none apply here.
+    pub const DEFAULT: Self = Self {
+        admin: crate::ADMIN,
+        paused: 0,
+        bump: [0],
+    };
+
+    #[inline(always)]
+    pub fn load_mut(bytes: &mut [u8]) -> Result<&mut Self,ProgramError> {
+        if bytes.len() != Self::LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok( unsafe{&mut *core::mem::transmute::<*mut u8, *mut Self>(bytes.as_mut_ptr())})
+    }
+
+    #[inline(always)]
+    pub fn load(bytes: &[u8]) -> Result<&Self,ProgramError> {
+        if bytes.len() != Self::LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok( unsafe{& *core::mem::transmute::<*const u8, *const Self>(bytes.as_ptr())})
+    }
+
+    #[inline(always)]
+    pub fn set_admin(&mut self, admin: Address) {
+        self.admin = admin;
+    }
+    #[inline(always)]
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused as u8;
+    }
+    #[inline(always)]
+    pub fn set_bump(&mut self, bump: [u8;1]) {
+        self.bump = bump;
+    }
+
+    #[inline(always)]
+    pub fn is_paused(&self) -> bool {
+        self.paused != 0
+    }
+}
+
+/// Last take of an escrow, kept with `RECORD_TAKE_HISTORY` in a PDA that outlives it
+///
+/// `Take` creates it on the escrow's first take and overwrites it on every later one.
+#[repr(C)]
+pub struct TakeHistory {
+    pub escrow: Address,
+    pub last_taker: Address,
+    /// Unix timestamp of the last take
+    pub taken_at: i64,
+    pub bump: [u8;1],
+}
+
+// escrow 32 + last_taker 32 + taken_at 8 + bump 1 = 73
+const _: () = assert!(TakeHistory::LEN == 73);
+const _: () = assert!(size_of::<TakeHistory>() == TakeHistory::LEN.next_multiple_of(core::mem::align_of::<TakeHistory>()));
+
+impl TakeHistory {
+    /// Seed prefix of take history PDAs: `[SEED, escrow]`
+    pub const SEED: &'static [u8] = crate::constants::TAKE_HISTORY_SEED;
+
+    pub const LEN: usize=size_of::<Address>()*2+size_of::<i64>()+size_of::<[u8;1]>();
+
+    #[inline(always)]
+    pub fn load_mut(bytes: &mut [u8]) -> Result<&mut Self,ProgramError> {
+        if bytes.len() != Self::LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok( unsafe{&mut *core::mem::transmute::<*mut u8, *mut Self>(bytes.as_mut_ptr())})
+    }
+
+    #[inline(always)]
+    pub fn load(bytes: &[u8]) -> Result<&Self,ProgramError> {
+        if bytes.len() != Self::LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok( unsafe{& *core::mem::transmute::<*const u8, *const Self>(bytes.as_ptr())})
+    }
+
+    /// Derives the take history PDA of the escrow at `escrow`, and its bump
+    pub fn address(escrow: &Address) -> (Address, u8) {
+        Address::find_program_address(&[Self::SEED, escrow.as_ref()], &crate::ID)
+    }
+
+    #[inline(always)]
+    pub fn set_escrow(&mut self, escrow: Address) {
+        self.escrow = escrow;
+    }
+    #[inline(always)]
+    pub fn set_last_taker(&mut self, last_taker: Address, taken_at: i64) {
+        self.last_taker = last_taker;
+        self.taken_at = taken_at;
+    }
+    #[inline(always)]
+    pub fn set_bump(&mut self, bump: [u8;1]) {
+        self.bump = bump;
+    }
+}
+
+/// A taker's next permit nonce, in the PDA created by `InitPermitNonce`.
+///
+/// A take under a `TakePermit` must carry the current nonce and advances it, so
+/// each signed permit authorizes a single take.
+#[repr(C)]
+pub struct PermitNonce {
+    pub taker: Address,
+    pub nonce: u64,
+    pub bump: [u8;1],
+}
+
+// taker 32 + nonce 8 + bump 1 = 41
+const _: () = assert!(PermitNonce::LEN == 41);
+const _: () = assert!(size_of::<PermitNonce>() == PermitNonce::LEN.next_multiple_of(core::mem::align_of::<PermitNonce>()));
+
+impl PermitNonce {
+    /// Seed prefix of permit nonce PDAs: `[SEED, taker]`
+    pub const SEED: &'static [u8] = crate::constants::PERMIT_NONCE_SEED;
+
+    pub const LEN: usize=size_of::<Address>()+size_of::<u64>()+size_of::<[u8;1]>();
+
+    #[inline(always)]
+    pub fn load_mut(bytes: &mut [u8]) -> Result<&mut Self,ProgramError> {
+        if bytes.len() != Self::LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok( unsafe{&mut *core::mem::transmute::<*mut u8, *mut Self>(bytes.as_mut_ptr())})
+    }
+
+    #[inline(always)]
+    pub fn load(bytes: &[u8]) -> Result<&Self,ProgramError> {
+        if bytes.len() != Self::LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok( unsafe{& *core::mem::transmute::<*const u8, *const Self>(bytes.as_ptr())})
+    }
+
+    /// Derives the permit nonce PDA of `taker`, and its bump
+    pub fn address(taker: &Address) -> (Address, u8) {
+        Address::find_program_address(&[Self::SEED, taker.as_ref()], &crate::ID)
+    }
+
+    #[inline(always)]
+    pub fn set_taker(&mut self, taker: Address) {
+        self.taker = taker;
+    }
+    #[inline(always)]
+    pub fn set_nonce(&mut self, nonce: u64) {
+        self.nonce = nonce;
+    }
+    #[inline(always)]
+    pub fn set_bump(&mut self, bump: [u8;1]) {
+        self.bump = bump;
+    }
+}
+
+/// Negotiated terms recorded by `Propose`, settled by the designated taker with `Accept`.
+///
+/// No tokens are held: the proposal PDA is approved as delegate of the maker's
+/// mint_a account for `amount`, and moves it only when the taker accepts.
+#[repr(C)]
+pub struct Proposal {
+    pub seed: u64,
+    pub maker: Address,
+    pub taker: Address,
+    pub mint_a: Address,
+    pub mint_b: Address,
+    pub amount: u64,
+    pub receive: u64,
+    pub bump: [u8;1],
+}
+
+// seed 8 + maker 32 + taker 32 + mint_a 32 + mint_b 32 + amount 8 + receive 8 + bump 1 = 153
+const _: () = assert!(Proposal::LEN == 153);
+const _: () = assert!(size_of::<Proposal>() == Proposal::LEN.next_multiple_of(core::mem::align_of::<Proposal>()));
+
+impl Proposal {
+    /// Seed prefix of proposal PDAs: `[SEED, maker, seed.to_le_bytes()]`
+    pub const SEED: &'static [u8] = crate::constants::PROPOSAL_SEED;
+
+    pub const LEN: usize=size_of::<u64>()+size_of::<Address>()*4+size_of::<u64>()*2+size_of::<[u8;1]>();
+
+    #[inline(always)]
+    pub fn load_mut(bytes: &mut [u8]) -> Result<&mut Self,ProgramError> {
+        if bytes.len() != Self::LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok( unsafe{&mut *core::mem::transmute::<*mut u8, *mut Self>(bytes.as_mut_ptr())})
+    }
+
+    #[inline(always)]
+    pub fn load(bytes: &[u8]) -> Result<&Self,ProgramError> {
+        if bytes.len() != Self::LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok( unsafe{& *core::mem::transmute::<*const u8, *const Self>(bytes.as_ptr())})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{address, escrow, escrow_address, escrow_data, token_account, vault, TestAccount};
+
+    #[test]
+    fn escrow_round_trips_edge_values() {
+        let data = escrow_data(|escrow| {
+            escrow.set_inner(u64::MAX, address(1), address(2), address(3), ReceiveAmount(u64::MAX), i64::MIN, u64::MAX, [u8::MAX]);
+            escrow.set_oracle(address(4), u64::MAX);
+            escrow.set_delegate(address(5));
+            escrow.set_cosigner(address(6));
+            escrow.set_filled(u64::MAX);
+            escrow.set_created_at(i64::MAX);
+            escrow.set_flags(u8::MAX);
+        });
+        // Loaded from account data, aligned like the runtime's
+        let account = TestAccount::new(address(9), crate::ID, 1, &data);
+        let view = account.view();
+        let data = view.try_borrow().unwrap();
+
+        let escrow = Escrow::load(&data).unwrap();
+        assert_eq!(escrow.seed, u64::MAX);
+        assert_eq!(escrow.maker, address(1));
+        assert_eq!(escrow.mint_a, address(2));
+        assert_eq!(escrow.mint_b, address(3));
+        assert_eq!(escrow.receive, ReceiveAmount(u64::MAX));
+        assert_eq!(escrow.deadline, i64::MIN);
+        assert_eq!(escrow.min_fill, u64::MAX);
+        assert_eq!((escrow.oracle.clone(), escrow.multiplier), (address(4), u64::MAX));
+        assert_eq!(escrow.delegate, address(5));
+        assert_eq!(escrow.cosigner, address(6));
+        assert_eq!(escrow.filled, u64::MAX);
+        assert_eq!(escrow.created_at, i64::MAX);
+        assert_eq!(escrow.flags, u8::MAX);
+        assert_eq!(escrow.bump, [u8::MAX]);
+
+        // The filter offsets point at the stored fields
+        assert_eq!(&data[Escrow::MAKER_OFFSET..][..32], address(1).as_ref());
+        assert_eq!(&data[Escrow::MINT_A_OFFSET..][..32], address(2).as_ref());
+        assert_eq!(&data[Escrow::MINT_B_OFFSET..][..32], address(3).as_ref());
+    }
+
+    #[test]
+    fn apply_fill_seals_and_tracks_what_is_left() {
+        let data = escrow_data(|escrow| {
+            escrow.set_inner(7, address(1), address(2), address(3), ReceiveAmount(20), 0, 0, [0]);
+            escrow.apply_fill(4, 8).unwrap();
+        });
+        let account = TestAccount::new(address(9), crate::ID, 1, &data);
+        let view = account.view();
+        let data = view.try_borrow().unwrap();
+        let escrow = Escrow::load(&data).unwrap();
+        assert_eq!((escrow.receive, escrow.filled, escrow.is_sealed()), (ReceiveAmount(12), 4, true));
+
+        // An oracle prices each fill, so there is no fixed price left to track
+        let data = escrow_data(|escrow| {
+            escrow.set_inner(7, address(1), address(2), address(3), ReceiveAmount(20), 0, 0, [0]);
+            escrow.set_oracle(address(4), 1);
+            escrow.apply_fill(4, 30).unwrap();
+        });
+        let account = TestAccount::new(address(9), crate::ID, 1, &data);
+        let view = account.view();
+        let data = view.try_borrow().unwrap();
+        assert_eq!(Escrow::load(&data).unwrap().receive, ReceiveAmount(20));
+    }
+
+    #[test]
+    fn load_owned_matches_load_and_releases_the_borrow() {
+        let data = escrow_data(|escrow| {
+            escrow.set_inner(7, address(1), address(2), address(3), ReceiveAmount(20), 100, 5, [254]);
+            escrow.set_filled(3);
+            escrow.set_flags(Escrow::FLAG_PARTIAL_FILL);
+        });
+        let account = TestAccount::new(address(9), crate::ID, 1, &data);
+        let view = account.view();
+
+        let owned = Escrow::load_owned(&view.try_borrow().unwrap()).unwrap();
+        // The copy holds no borrow: the account can be written while it's alive
+        view.try_borrow_mut().unwrap()[Escrow::MAKER_OFFSET..][..32].copy_from_slice(address(8).as_ref());
+
+        let data = view.try_borrow().unwrap();
+        let loaded = Escrow::load(&data).unwrap();
+        assert_eq!(owned.maker, address(1));
+        assert_eq!(loaded.maker, address(8));
+        assert_eq!((owned.seed, owned.mint_a.clone(), owned.mint_b.clone()), (loaded.seed, loaded.mint_a.clone(), loaded.mint_b.clone()));
+        assert_eq!((owned.receive, owned.deadline, owned.min_fill), (loaded.receive, loaded.deadline, loaded.min_fill));
+        assert_eq!((owned.filled, owned.flags, owned.bump), (loaded.filled, loaded.flags, loaded.bump));
+    }
+
+    #[cfg(feature = "client")]
+    #[test]
+    fn implied_rate_divides_receive_by_the_deposit() {
+        let data = escrow_data(|escrow| escrow.set_inner(7, address(1), address(2), address(3), ReceiveAmount(30), 0, 0, [254]));
+        let account = TestAccount::new(address(9), crate::ID, 1, &data);
+        let escrow = Escrow::load_owned(&account.view().try_borrow().unwrap()).unwrap();
+        assert_eq!(escrow.implied_rate(12), Some(2.5));
+        assert_eq!(escrow.implied_rate(0), None);
+    }
+
+    #[cfg(feature = "client")]
+    #[test]
+    fn to_account_data_round_trips_through_load() {
+        let data = escrow_data(|escrow| {
+            escrow.set_inner(7, address(1), address(2), address(3), ReceiveAmount(30), 1_800_000_000, 2, [254]);
+            escrow.set_oracle(address(9), 3);
+            escrow.set_created_at(1_700_000_000);
+            escrow.set_filled(4);
+            escrow.set_flags(Escrow::FLAG_PARTIAL_FILL);
+        });
+        let escrow = Escrow::load_unaligned(&data).unwrap();
+
+        // The very bytes Make leaves on chain
+        let fixture = escrow.to_account_data();
+        assert_eq!(fixture, data);
+        let account = TestAccount::new(address(8), crate::ID, 1, &fixture);
+        let loaded = Escrow::load_owned(&account.view().try_borrow().unwrap()).unwrap();
+        assert_eq!(loaded.to_account_data(), fixture);
+        assert_eq!((loaded.oracle, loaded.multiplier, loaded.created_at), (address(9), 3, 1_700_000_000));
+    }
+
+    #[test]
+    fn verify_pda_matches_only_the_makers_seed() {
+        let account = escrow(&address(1), 7, |_| {});
+        assert_eq!(Escrow::verify_pda(&account.view(), &address(1), 7), Ok(()));
+        assert_eq!(Escrow::verify_pda(&account.view(), &address(1), 8), Err(ProgramError::InvalidSeeds));
+        assert_eq!(Escrow::verify_pda(&account.view(), &address(2), 7), Err(ProgramError::InvalidSeeds));
+
+        let data = escrow_data(|state| state.set_maker(address(1)));
+        let foreign = TestAccount::new(account.view().address().clone(), address(9), 1, &data);
+        assert_eq!(Escrow::verify_pda(&foreign.view(), &address(1), 7), Err(ProgramError::InvalidAccountOwner));
+    }
+
+    #[test]
+    fn vault_amount_reads_only_the_canonical_vault() {
+        let account = escrow(&address(1), 7, |_| {});
+        let state = Escrow::load_owned(&account.view().try_borrow().unwrap()).unwrap();
+        let escrow_address = account.view().address().clone();
+
+        let canonical = vault(&escrow_address, &address(3), 10);
+        assert_eq!(state.vault_amount(&account.view(), &canonical.view()), Ok(10));
+
+        // Right mint and owner, but not the escrow's associated token account
+        let elsewhere = token_account(address(5), &address(3), &escrow_address, 10);
+        assert_eq!(state.vault_amount(&account.view(), &elsewhere.view()), Err(ProgramError::InvalidSeeds));
+    }
+
+    #[test]
+    fn checks_the_grace_period_from_created_at() {
+        escrow_data(|escrow| {
+            escrow.set_created_at(1_000);
+            assert_eq!(escrow.check_grace_period(1_059, 60), Err(EscrowError::RefundGracePeriod.into()));
+            assert_eq!(escrow.check_grace_period(1_060, 60), Ok(()));
+            // Disabled
+            assert_eq!(escrow.check_grace_period(1_000, 0), Ok(()));
+
+            // An expired escrow is always refundable
+            escrow.set_deadline(1_010);
+            assert_eq!(escrow.check_grace_period(1_011, 60), Ok(()));
+
+            // Nor has one without a recorded creation time a grace period
+            escrow.set_deadline(0);
+            escrow.set_created_at(0);
+            assert_eq!(escrow.check_grace_period(1_000, 60), Ok(()));
+        });
+    }
+
+    #[test]
+    fn fixed_price_quotes_widen_and_narrow_checked() {
+        escrow_data(|escrow| {
+            escrow.set_receive(ReceiveAmount(20));
+            // Rounded up in the maker's favor, the final fill paying the rest
+            assert_eq!(escrow.fixed_price_quote(1, 3), Ok(7));
+            assert_eq!(escrow.fixed_price_quote(3, 3), Ok(20));
+
+            // Exact where a u64 product would wrap
+            escrow.set_receive(ReceiveAmount(u64::MAX));
+            assert_eq!(escrow.fixed_price_quote(u64::MAX - 1, u64::MAX), Ok(u64::MAX - 1));
+            // A quote beyond u64 is an error, not truncated
+            assert_eq!(escrow.fixed_price_quote(3, 2), Err(ProgramError::ArithmeticOverflow));
+        });
+    }
+
+    #[test]
+    fn only_off_curve_bumps_derive_an_address() {
+        let (canonical, bump) = escrow_address(&address(1), 7);
+        for candidate in 0..=u8::MAX {
+            escrow_data(|escrow| {
+                escrow.set_inner(7, address(1), address(3), address(4), ReceiveAmount(20), 0, 0, [candidate]);
+                let derived = escrow.address_with_bump(&address(1));
+                match candidate.cmp(&bump) {
+                    core::cmp::Ordering::Equal => assert_eq!(derived, Ok(canonical.clone())),
+                    // The bump search skipped these because they land on the curve
+                    core::cmp::Ordering::Greater => assert_eq!(derived, Err(ProgramError::InvalidSeeds), "bump {candidate}"),
+                    core::cmp::Ordering::Less => assert_ne!(derived, Ok(canonical.clone()), "bump {candidate}"),
+                }
+            });
+        }
+    }
+
+    #[test]
+    fn age_counts_from_created_at() {
+        escrow_data(|escrow| {
+            assert_eq!(escrow.age(1_000), Escrow::AGE_UNKNOWN);
+            escrow.set_created_at(900);
+            assert_eq!(escrow.age(1_000), 100);
+            assert_eq!(escrow.age(900), 0);
+            // A clock behind created_at
+            assert_eq!(escrow.age(800), 0);
+        });
+    }
+
+    #[test]
+    fn amounts_are_laid_out_as_u64() {
+        assert_eq!((size_of::<DepositAmount>(), size_of::<ReceiveAmount>()), (size_of::<u64>(), size_of::<u64>()));
+        assert_eq!((align_of::<DepositAmount>(), align_of::<ReceiveAmount>()), (align_of::<u64>(), align_of::<u64>()));
+
+        // receive is stored exactly as the u64 it wraps
+        let data = escrow_data(|escrow| escrow.set_inner(7, address(1), address(2), address(3), ReceiveAmount(0x0102_0304_0506_0708), 0, 0, [0]));
+        let offset = core::mem::offset_of!(Escrow, receive);
+        assert_eq!(data[offset..offset + 8], 0x0102_0304_0506_0708u64.to_le_bytes());
+    }
+
+    #[test]
+    fn escrow_layout_matches_the_golden_values() {
+        use core::mem::offset_of;
+        // A change here changes the account layout: existing escrows need a migration
+        assert_eq!(offset_of!(Escrow, seed), 0); // u64
+        assert_eq!(offset_of!(Escrow, maker), 8); // + 8
+        assert_eq!(offset_of!(Escrow, mint_a), 40); // + 32
+        assert_eq!(offset_of!(Escrow, mint_b), 72); // + 32
+        assert_eq!(offset_of!(Escrow, receive), 104); // + 32
+        assert_eq!(offset_of!(Escrow, deadline), 112); // + 8
+        assert_eq!(offset_of!(Escrow, min_fill), 120); // + 8
+        assert_eq!(offset_of!(Escrow, oracle), 128); // + 8
+        assert_eq!(offset_of!(Escrow, multiplier), 160); // + 32
+        assert_eq!(offset_of!(Escrow, delegate), 168); // + 8
+        assert_eq!(offset_of!(Escrow, cosigner), 200); // + 32
+        assert_eq!(offset_of!(Escrow, filled), 232); // + 32
+        assert_eq!(offset_of!(Escrow, created_at), 240); // + 8
+        assert_eq!(offset_of!(Escrow, flags), 248); // + 8
+        assert_eq!(offset_of!(Escrow, bump), 249); // + 1
+        assert_eq!(Escrow::LEN, 250); // + 1
+        // Only padded past the last field, up to the 8-byte alignment
+        assert_eq!(size_of::<Escrow>(), 256);
+    }
+
+    #[test]
+    fn lengths_match_the_runtime_sizes() {
+        // Each LEN ends at the last field, its struct only padded past it
+        assert_eq!(core::mem::offset_of!(Escrow, bump) + 1, Escrow::LEN);
+        assert_eq!(core::mem::offset_of!(Config, bump) + 1, Config::LEN);
+        assert_eq!(core::mem::offset_of!(TakeHistory, bump) + 1, TakeHistory::LEN);
+        assert_eq!(core::mem::offset_of!(PermitNonce, bump) + 1, PermitNonce::LEN);
+        assert_eq!(core::mem::offset_of!(Proposal, bump) + 1, Proposal::LEN);
+        assert_eq!(escrow_data(|escrow| escrow.set_maker(address(1))).len(), Escrow::LEN);
+    }
+
+    #[test]
+    fn byte_reader_matches_the_struct_layout() {
+        let data = escrow_data(|escrow| {
+            escrow.set_inner(1, address(1), address(2), address(3), ReceiveAmount(4), -5, 6, [7]);
+            escrow.set_oracle(address(4), 8);
+            escrow.set_delegate(address(5));
+            escrow.set_cosigner(address(6));
+            escrow.set_filled(9);
+            escrow.set_created_at(10);
+            escrow.set_flags(11);
+        });
+        // The default build's view of the data, without the safe-state reader
+        let transmuted = unsafe { core::ptr::read_unaligned(data.as_ptr() as *const Escrow) };
+        let read = Escrow::read(&data);
+
+        let fields = |escrow: &Escrow| (
+            (escrow.seed, escrow.maker.clone(), escrow.mint_a.clone(), escrow.mint_b.clone(), escrow.receive, escrow.deadline, escrow.min_fill),
+            (escrow.oracle.clone(), escrow.multiplier, escrow.delegate.clone(), escrow.cosigner.clone(), escrow.filled, escrow.created_at, escrow.flags, escrow.bump),
+        );
+        assert_eq!(fields(&read), fields(&transmuted));
+
+        // And the writer puts every field back where the struct has it
+        let mut written = [0; Escrow::LEN];
+        transmuted.write(&mut written);
+        assert_eq!(&written[..], &data[..]);
+    }
+
+    #[test]
+    fn escrow_load_rejects_uninitialized_and_short_data() {
+        let account = TestAccount::new(address(9), crate::ID, 1, &[0; Escrow::LEN]);
+        assert_eq!(Escrow::load(&account.view().try_borrow().unwrap()).err(), Some(EscrowError::UninitializedEscrow.into()));
+        assert_eq!(Escrow::load(&[1; Escrow::LEN - 1]).err(), Some(ProgramError::InvalidInstructionData));
+    }
+}
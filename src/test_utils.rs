@@ -0,0 +1,432 @@
+//! Host-side fixtures for the unit tests: accounts laid out like the runtime's
+//! input buffer, token and escrow data, and a settable clock.
+//!
+//! The `simulate_*` functions stand in for the CPIs of `crate::cpi` on the
+//! host. Each applies the effect of the invoked program to the accounts, with
+//! the checks a test can trip (balances, token account owners), but trusts the
+//! signatures: a PDA signing with the wrong seeds isn't caught here.
+
+use core::cell::{Cell, RefCell};
+use pinocchio::{account::{RuntimeAccount, NOT_BORROWED}, error::ProgramError, sysvars::rent::{ACCOUNT_STORAGE_OVERHEAD, DEFAULT_LAMPORTS_PER_BYTE}, instruction::InstructionView, AccountView, Address, ProgramResult};
+use pinocchio_token::state::{Mint, TokenAccount};
+use crate::{constants::{ASSOCIATED_TOKEN_PROGRAM_ID, SYSTEM_PROGRAM_ID, TOKEN_2022_PROGRAM_ID, TOKEN_PROGRAM_ID}, instructions::{CreateAssociatedTokenAccount, TokenAccountInterface}, state::{Config, Escrow, ReceiveAmount}};
+
+/// Room the runtime leaves after account data for reallocs
+const REALLOC_PADDING: usize = 10 * 1024;
+
+/// An account in its runtime layout: the `RuntimeAccount` header, then its data
+pub struct TestAccount {
+    raw: *mut u64,
+    words: usize,
+}
+
+impl TestAccount {
+    pub fn new(address: Address, owner: Address, lamports: u64, data: &[u8]) -> Self {
+        let bytes = size_of::<RuntimeAccount>() + data.len() + REALLOC_PADDING;
+        let words = bytes.div_ceil(size_of::<u64>());
+        let raw = Box::into_raw(vec![0u64; words].into_boxed_slice()) as *mut u64;
+        unsafe {
+            let header = raw as *mut RuntimeAccount;
+            (*header).borrow_state = NOT_BORROWED;
+            (*header).address = address;
+            (*header).owner = owner;
+            (*header).lamports = lamports;
+            (*header).data_len = data.len() as u64;
+            let data_ptr = (raw as *mut u8).add(size_of::<RuntimeAccount>());
+            core::ptr::copy_nonoverlapping(data.as_ptr(), data_ptr, data.len());
+        }
+        Self { raw, words }
+    }
+
+    /// A system-owned wallet with some lamports and no data
+    pub fn wallet(address: Address) -> Self {
+        Self::new(address, Address::default(), 1_000_000_000, &[])
+    }
+
+    /// An executable program account at `address`
+    pub fn program(address: Address) -> Self {
+        Self::new(address, Address::default(), 1, &[]).executable()
+    }
+
+    fn header(&mut self) -> &mut RuntimeAccount {
+        unsafe { &mut *(self.raw as *mut RuntimeAccount) }
+    }
+
+    pub fn signer(mut self) -> Self {
+        self.header().is_signer = 1;
+        self
+    }
+
+    pub fn writable(mut self) -> Self {
+        self.header().is_writable = 1;
+        self
+    }
+
+    pub fn readonly(mut self) -> Self {
+        self.header().is_writable = 0;
+        self
+    }
+
+    pub fn executable(mut self) -> Self {
+        self.header().executable = 1;
+        self
+    }
+
+    /// The account as the program sees it. The view points into this account,
+    /// which must outlive it
+    pub fn view(&self) -> AccountView {
+        unsafe { AccountView::new_unchecked(self.raw as *mut RuntimeAccount) }
+    }
+}
+
+impl Drop for TestAccount {
+    fn drop(&mut self) {
+        unsafe {
+            drop(Box::from_raw(core::ptr::slice_from_raw_parts_mut(self.raw, self.words)));
+        }
+    }
+}
+
+/// A distinct, recognizable address per `n`
+pub fn address(n: u8) -> Address {
+    Address::new_from_array([n; 32])
+}
+
+/// Data of an initialized legacy mint
+pub fn mint_data(decimals: u8, supply: u64) -> Vec<u8> {
+    let mut data = vec![0; Mint::LEN];
+    data[36..44].copy_from_slice(&supply.to_le_bytes());
+    data[44] = decimals;
+    data[45] = 1;
+    data
+}
+
+/// Data of an initialized Token-2022 mint carrying `extensions`, each as an
+/// empty TLV entry
+pub fn mint_2022_data(extensions: &[u16]) -> Vec<u8> {
+    let mut data = mint_data(6, 1_000_000);
+    data.resize(TokenAccount::LEN, 0);
+    // Account type: mint
+    data.push(1);
+    for extension in extensions {
+        data.extend_from_slice(&extension.to_le_bytes());
+        data.extend_from_slice(&0u16.to_le_bytes());
+    }
+    data
+}
+
+/// Data of an initialized token account
+pub fn token_account_data(mint: &Address, owner: &Address, amount: u64) -> Vec<u8> {
+    let mut data = vec![0; TokenAccount::LEN];
+    data[0..32].copy_from_slice(mint.as_ref());
+    data[32..64].copy_from_slice(owner.as_ref());
+    data[64..72].copy_from_slice(&amount.to_le_bytes());
+    // Initialized
+    data[108] = 1;
+    data
+}
+
+/// A legacy mint account at `address`
+pub fn mint(address: Address) -> TestAccount {
+    TestAccount::new(address, TOKEN_PROGRAM_ID, 1, &mint_data(6, 1_000_000))
+}
+
+/// A Token-2022 mint account at `address`, carrying `extensions`
+pub fn mint_2022(address: Address, extensions: &[u16]) -> TestAccount {
+    TestAccount::new(address, TOKEN_2022_PROGRAM_ID, 1, &mint_2022_data(extensions))
+}
+
+/// A writable legacy token account at `address`
+pub fn token_account(address: Address, mint: &Address, owner: &Address, amount: u64) -> TestAccount {
+    TestAccount::new(address, TOKEN_PROGRAM_ID, 1, &token_account_data(mint, owner, amount)).writable()
+}
+
+/// A writable Token-2022 token account at `address`
+pub fn token_account_2022(address: Address, mint: &Address, owner: &Address, amount: u64) -> TestAccount {
+    TestAccount::new(address, TOKEN_2022_PROGRAM_ID, 1, &token_account_data(mint, owner, amount)).writable()
+}
+
+/// `account`, a token account, with `delegate` approved for `amount`
+pub fn delegated(account: TestAccount, delegate: &Address, amount: u64) -> TestAccount {
+    {
+        let view = account.view();
+        write_delegate(&mut view.try_borrow_mut().unwrap(), delegate, amount);
+    }
+    account
+}
+
+/// Sets the delegate of the token account `data`
+fn write_delegate(data: &mut [u8], delegate: &Address, amount: u64) {
+    data[72] = 1;
+    data[76..108].copy_from_slice(delegate.as_ref());
+    data[121..129].copy_from_slice(&amount.to_le_bytes());
+}
+
+/// Token balance of the token account `account`, of either token program
+pub fn balance(account: &AccountView) -> u64 {
+    TokenAccountInterface::from_account_view(account).unwrap().amount()
+}
+
+/// Data of an escrow written by `init`
+pub fn escrow_data(init: impl FnOnce(&mut Escrow)) -> Vec<u8> {
+    // 8-byte aligned, as account data is
+    let mut buffer = [0u64; Escrow::LEN.div_ceil(8)];
+    let bytes = unsafe { core::slice::from_raw_parts_mut(buffer.as_mut_ptr() as *mut u8, Escrow::LEN) };
+    // The explicit deref goes through `safe-state`'s guard, which writes back on drop
+    #[allow(clippy::explicit_auto_deref)]
+    init(&mut *Escrow::load_mut(bytes).unwrap());
+    bytes.to_vec()
+}
+
+/// Escrow PDA of `maker` and `seed`, and its bump
+pub fn escrow_address(maker: &Address, seed: u64) -> (Address, u8) {
+    Address::find_program_address(&[Escrow::SEED, maker.as_ref(), &seed.to_le_bytes()], &crate::ID)
+}
+
+/// A writable escrow PDA of `maker` and `seed` offering mint 3 for 20 of mint
+/// 4, then edited by `edit`
+pub fn escrow(maker: &Address, seed: u64, edit: impl FnOnce(&mut Escrow)) -> TestAccount {
+    let (address, bump) = escrow_address(maker, seed);
+    let data = escrow_data(|state| {
+        state.set_inner(seed, maker.clone(), self::address(3), self::address(4), ReceiveAmount(20), 0, 0, [bump]);
+        edit(state);
+    });
+    TestAccount::new(address, crate::ID, rent(Escrow::LEN), &data).writable()
+}
+
+/// The vault of the escrow at `escrow`: its associated token account for `mint`
+pub fn vault(escrow: &Address, mint: &Address, amount: u64) -> TestAccount {
+    token_account(ata_address(escrow, mint), mint, escrow, amount)
+}
+
+/// A writable keypair escrow at `address` of `maker` offering mint 3 for 20 of
+/// mint 4, then edited by `edit`. Its bump is the vault authority's, as Make stores it.
+pub fn keypair_escrow(address: Address, maker: &Address, edit: impl FnOnce(&mut Escrow)) -> TestAccount {
+    let (_, bump) = Escrow::vault_authority(&address);
+    let data = escrow_data(|state| {
+        state.set_inner(0, maker.clone(), self::address(3), self::address(4), ReceiveAmount(20), 0, 0, [bump]);
+        state.set_flags(Escrow::FLAG_KEYPAIR);
+        edit(state);
+    });
+    TestAccount::new(address, crate::ID, rent(Escrow::LEN), &data).writable()
+}
+
+/// The config PDA, never initialized: the program runs on `Config::DEFAULT`
+pub fn config() -> TestAccount {
+    TestAccount::wallet(Address::find_program_address(&[Config::SEED], &crate::ID).0)
+}
+
+/// The config PDA, initialized with the deploy admin and `paused`
+pub fn initialized_config(paused: bool) -> TestAccount {
+    let (address, bump) = Address::find_program_address(&[Config::SEED], &crate::ID);
+    let mut data = [0; Config::LEN];
+    let config = Config::load_mut(&mut data).unwrap();
+    *config = Config::DEFAULT;
+    config.set_paused(paused);
+    config.set_bump([bump]);
+    TestAccount::new(address, crate::ID, rent(Config::LEN), &data).writable()
+}
+
+/// Associated token account of `wallet` for the legacy `mint`
+pub fn ata_address(wallet: &Address, mint: &Address) -> Address {
+    ata_address_of(wallet, mint, &TOKEN_PROGRAM_ID)
+}
+
+/// Associated token account of `wallet` for `mint` of `token_program`
+pub fn ata_address_of(wallet: &Address, mint: &Address, token_program: &Address) -> Address {
+    Address::find_program_address(&[wallet.as_ref(), token_program.as_ref(), mint.as_ref()], &ASSOCIATED_TOKEN_PROGRAM_ID).0
+}
+
+thread_local! {
+    static NOW: Cell<i64> = const { Cell::new(1_700_000_000) };
+}
+
+/// Sets the Unix timestamp `crate::unix_timestamp` returns on this thread
+pub fn set_now(now: i64) {
+    NOW.with(|cell| cell.set(now));
+}
+
+/// Current Unix timestamp of this thread's test clock
+pub fn now() -> i64 {
+    NOW.with(Cell::get)
+}
+
+thread_local! {
+    static RETURN_DATA: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Return data last set on this thread
+pub fn return_data() -> Vec<u8> {
+    RETURN_DATA.with(|cell| cell.borrow().clone())
+}
+
+pub fn simulate_set_return_data(data: &[u8]) {
+    RETURN_DATA.with(|cell| *cell.borrow_mut() = data.to_vec());
+}
+
+#[cfg(feature = "compute-log")]
+thread_local! {
+    static COMPUTE_LOGS: RefCell<Vec<&'static str>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Labels `log_compute_units!` logged on this thread, oldest first
+#[cfg(feature = "compute-log")]
+pub fn compute_logs() -> Vec<&'static str> {
+    COMPUTE_LOGS.with(|cell| cell.borrow().clone())
+}
+
+#[cfg(feature = "compute-log")]
+pub fn simulate_log_compute_units(label: &'static str) {
+    COMPUTE_LOGS.with(|cell| cell.borrow_mut().push(label));
+}
+
+/// Rent-exempt minimum of an account with `space` bytes of data
+pub fn rent(space: usize) -> u64 {
+    (ACCOUNT_STORAGE_OVERHEAD + space as u64) * DEFAULT_LAMPORTS_PER_BYTE
+}
+
+// Error codes of the simulated programs
+const SYSTEM_ACCOUNT_ALREADY_IN_USE: u32 = 0;
+const SYSTEM_INSUFFICIENT_LAMPORTS: u32 = 1;
+const TOKEN_INSUFFICIENT_FUNDS: u32 = 1;
+const TOKEN_MINT_MISMATCH: u32 = 3;
+const TOKEN_OWNER_MISMATCH: u32 = 4;
+const TOKEN_NON_NATIVE_HAS_BALANCE: u32 = 11;
+
+/// Moves `lamports` from `from` to `to`
+fn pay(from: &AccountView, to: &AccountView, lamports: u64) -> ProgramResult {
+    let left = from.lamports().checked_sub(lamports).ok_or(ProgramError::Custom(SYSTEM_INSUFFICIENT_LAMPORTS))?;
+    from.set_lamports(left);
+    to.set_lamports(to.lamports() + lamports);
+    Ok(())
+}
+
+pub fn simulate_transfer_lamports(from: &AccountView, to: &AccountView, lamports: u64) -> ProgramResult {
+    pay(from, to, lamports)
+}
+
+/// Allocates `space` bytes to the unused `account` and assigns it to `owner`
+fn allocate(payer: &AccountView, account: &AccountView, space: usize, owner: &Address) -> ProgramResult {
+    if account.lamports() > 0 || !account.owned_by(&SYSTEM_PROGRAM_ID) {
+        return Err(ProgramError::Custom(SYSTEM_ACCOUNT_ALREADY_IN_USE));
+    }
+    pay(payer, account, rent(space))?;
+    account.resize(space)?;
+    unsafe { account.assign(owner) };
+    Ok(())
+}
+
+pub fn simulate_create_account(payer: &AccountView, account: &AccountView, space: u64, owner: &Address) -> ProgramResult {
+    allocate(payer, account, space as usize, owner)
+}
+
+pub fn simulate_create_associated_token_account(create: &CreateAssociatedTokenAccount) -> ProgramResult {
+    if !create.account.is_data_empty() {
+        // CreateIdempotent leaves an existing account to the caller's checks
+        return if create.idempotent { Ok(()) } else { Err(ProgramError::Custom(SYSTEM_ACCOUNT_ALREADY_IN_USE)) };
+    }
+    allocate(create.funding_account, create.account, TokenAccount::LEN, create.token_program.address())?;
+    create.account.try_borrow_mut()?.copy_from_slice(&token_account_data(create.mint.address(), create.wallet.address(), 0));
+    Ok(())
+}
+
+/// Whether `authority` may move the tokens of the token account `data`: its
+/// owner, or its delegate
+fn is_token_authority(data: &[u8], authority: &AccountView) -> bool {
+    &data[32..64] == authority.address().as_ref()
+        || (data[72] == 1 && &data[76..108] == authority.address().as_ref())
+}
+
+fn token_amount(data: &[u8]) -> u64 {
+    u64::from_le_bytes(data[64..72].try_into().unwrap())
+}
+
+/// Fails as the token program does when handed an account of another program
+fn check_token_program(token_program: &AccountView, accounts: &[&AccountView]) -> ProgramResult {
+    if accounts.iter().any(|account| !account.owned_by(token_program.address())) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    Ok(())
+}
+
+pub fn simulate_transfer(token_program: &AccountView, from: &AccountView, to: &AccountView, authority: &AccountView, amount: u64) -> ProgramResult {
+    check_token_program(token_program, &[from, to])?;
+    move_tokens(from, to, authority, amount)
+}
+
+/// Moves `amount` tokens between the token accounts `from` and `to`, as a transfer
+/// signed by `authority`
+fn move_tokens(from: &AccountView, to: &AccountView, authority: &AccountView, amount: u64) -> ProgramResult {
+    let mut from_data = from.try_borrow_mut()?;
+    if !is_token_authority(&from_data, authority) {
+        return Err(ProgramError::Custom(TOKEN_OWNER_MISMATCH));
+    }
+    let left = token_amount(&from_data).checked_sub(amount).ok_or(ProgramError::Custom(TOKEN_INSUFFICIENT_FUNDS))?;
+    if from.address() == to.address() {
+        return Ok(());
+    }
+    let mut to_data = to.try_borrow_mut()?;
+    if from_data[0..32] != to_data[0..32] {
+        return Err(ProgramError::Custom(TOKEN_MINT_MISMATCH));
+    }
+    from_data[64..72].copy_from_slice(&left.to_le_bytes());
+    let received = token_amount(&to_data) + amount;
+    to_data[64..72].copy_from_slice(&received.to_le_bytes());
+    Ok(())
+}
+
+pub fn simulate_close_account(token_program: &AccountView, account: &AccountView, destination: &AccountView, authority: &AccountView) -> ProgramResult {
+    check_token_program(token_program, &[account])?;
+    {
+        let data = account.try_borrow()?;
+        if &data[32..64] != authority.address().as_ref() {
+            return Err(ProgramError::Custom(TOKEN_OWNER_MISMATCH));
+        }
+        if token_amount(&data) != 0 {
+            return Err(ProgramError::Custom(TOKEN_NON_NATIVE_HAS_BALANCE));
+        }
+    }
+    pay(account, destination, account.lamports())?;
+    account.resize(0)?;
+    unsafe { account.assign(&SYSTEM_PROGRAM_ID) };
+    Ok(())
+}
+
+pub fn simulate_approve(token_program: &AccountView, source: &AccountView, delegate: &AccountView, authority: &AccountView, amount: u64) -> ProgramResult {
+    check_token_program(token_program, &[source])?;
+    let mut data = source.try_borrow_mut()?;
+    if &data[32..64] != authority.address().as_ref() {
+        return Err(ProgramError::Custom(TOKEN_OWNER_MISMATCH));
+    }
+    write_delegate(&mut data, delegate.address(), amount);
+    Ok(())
+}
+
+thread_local! {
+    static DEPOSIT_SIGNERS: RefCell<Vec<Address>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Accounts the last simulated deposit was invoked with as signers, on this thread
+pub fn deposit_signers() -> Vec<Address> {
+    DEPOSIT_SIGNERS.with(|cell| cell.borrow().clone())
+}
+
+/// A mock `DEPOSIT_PROGRAMS` program: moves the u64 amount of its instruction data
+/// from the first forwarded token account into the second, on the authority of the
+/// third, which must be passed as a signer
+pub fn simulate_deposit(instruction: &InstructionView, accounts: &[&AccountView]) -> ProgramResult {
+    // The runtime refuses a signature the caller doesn't hold
+    if instruction.accounts.iter().zip(accounts).any(|(meta, account)| meta.is_signer && !account.is_signer()) {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    DEPOSIT_SIGNERS.with(|cell| *cell.borrow_mut() = instruction.accounts.iter().filter(|meta| meta.is_signer).map(|meta| meta.address.clone()).collect());
+
+    let ([from, to, authority, ..], [_, _, authority_meta, ..]) = (accounts, instruction.accounts) else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+    if !authority_meta.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    let amount = u64::from_le_bytes(instruction.data.try_into().map_err(|_| ProgramError::InvalidInstructionData)?);
+    move_tokens(from, to, authority, amount)
+}